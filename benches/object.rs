@@ -0,0 +1,75 @@
+#![feature(test)]
+
+extern crate seal_lang;
+extern crate test;
+
+use seal_lang::vm::value::{Symbol, Value};
+use seal_lang::vm::value::object::ObjectFields;
+use seal_lang::vm::value::shape::InlineCache;
+use seal_lang::vm::value::shared::Shared;
+use std::rc::Rc;
+use test::{black_box, Bencher};
+
+fn mixed(count: usize) -> Vec<Value> {
+    let array = Value::Array(Shared::new(vec![]));
+    (0..count)
+        .map(|i| match i % 5 {
+            0 => Value::Integer(i as i64),
+            1 => Value::Float(i as f64),
+            2 => Value::String(Rc::new(i.to_string())),
+            3 => Value::Symbol(Symbol::intern("symbol")),
+            _ => array.clone(),
+        })
+        .collect()
+}
+
+fn names() -> Vec<Symbol> {
+    (0..16).map(|i| Symbol::intern(&format!("field{}", i))).collect()
+}
+
+/// 100 objects with the same 16 fields.
+fn objects() -> Vec<ObjectFields> {
+    let names = names();
+    let values = mixed(16);
+    (0..100)
+        .map(|_| {
+            let mut fields = ObjectFields::new();
+            for (i, value) in values.iter().enumerate() {
+                fields.set(names[i], value.clone());
+            }
+            fields
+        })
+        .collect()
+}
+
+#[bench]
+fn make_objects(b: &mut Bencher) {
+    b.iter(|| black_box(objects()));
+}
+
+#[bench]
+fn read_fields(b: &mut Bencher) {
+    let names = names();
+    let objects = objects();
+    b.iter(|| {
+        for fields in &objects {
+            for i in 0..16 {
+                black_box(fields.get(names[i]));
+            }
+        }
+    });
+}
+
+#[bench]
+fn read_fields_cached(b: &mut Bencher) {
+    let names = names();
+    let objects = objects();
+    let mut caches = vec![InlineCache::new(); 16];
+    b.iter(|| {
+        for fields in &objects {
+            for (i, cache) in caches.iter_mut().enumerate() {
+                black_box(cache.get(fields, names[i]));
+            }
+        }
+    });
+}
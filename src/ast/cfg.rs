@@ -0,0 +1,625 @@
+//! Control-flow graphs of function bodies.
+//!
+//! `Cfg::build` lowers a function body into basic blocks. Block 0 is the
+//! entry and block 1 is a single exit that every `return`, `throw`, error
+//! from `?` and fall off the end of the body leads to. Statements following
+//! a jump land in a block with no predecessors, so unreachable code is easy
+//! to find. Lambdas are values rather than control flow here; build a graph
+//! of a lambda's body separately with `Cfg::from_block`.
+
+use ast::*;
+use ast::printer::{self, Config};
+use ast::visit::{self, Visitor};
+use std::fmt::Write;
+
+pub type BlockId = usize;
+
+pub const ENTRY: BlockId = 0;
+pub const EXIT: BlockId = 1;
+
+#[derive(Debug)]
+pub struct Cfg<'a> {
+    pub blocks: Vec<BasicBlock<'a>>,
+}
+
+#[derive(Debug)]
+pub struct BasicBlock<'a> {
+    pub nodes: Vec<Node<'a>>,
+    pub terminator: Terminator<'a>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Node<'a> {
+    /// A `use`, declaration, assignment or call, or the values of the
+    /// `return` or `throw` that ends the block
+    Statement(&'a Statement),
+    /// The condition of an `if` or `while`, tested by the block's `Branch`
+    Condition(&'a Expression),
+    /// The iterator of a `for` loop, evaluated once before the first pass
+    Iterator(&'a Expression),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Terminator<'a> {
+    Goto(BlockId),
+    /// Tests the condition; the first block is taken when it's true
+    Branch(&'a Expression, BlockId, BlockId),
+    /// Binds the next item of a `for` loop and runs the body, or leaves the
+    /// loop once the iterator is exhausted
+    Iterate(&'a ForLoop, BlockId, BlockId),
+    /// The last node contains a `?`, which either continues to the block or
+    /// returns the error
+    Try(BlockId),
+    Return(Location),
+    Throw(Location),
+    /// Only the exit block ends here
+    Exit,
+}
+
+impl<'a> Terminator<'a> {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match *self {
+            Terminator::Goto(target) => vec![target],
+            Terminator::Branch(_, t, f) => vec![t, f],
+            Terminator::Iterate(_, body, done) => vec![body, done],
+            Terminator::Try(ok) => vec![ok, EXIT],
+            Terminator::Return(_) | Terminator::Throw(_) => vec![EXIT],
+            Terminator::Exit => vec![],
+        }
+    }
+}
+
+impl<'a> Cfg<'a> {
+    pub fn build(func: &'a Function) -> Cfg<'a> {
+        Cfg::from_block(&func.body)
+    }
+
+    pub fn from_block(body: &'a Block) -> Cfg<'a> {
+        let mut builder = Builder {
+            blocks: vec![],
+            current: ENTRY,
+            loops: vec![],
+        };
+        builder.new_block();
+        builder.new_block();
+        builder.blocks[EXIT].terminator = Terminator::Exit;
+
+        builder.block(body);
+        builder.goto(EXIT);
+
+        Cfg { blocks: builder.blocks }
+    }
+
+    pub fn successors(&self, block: BlockId) -> Vec<BlockId> {
+        self.blocks[block].terminator.successors()
+    }
+
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut preds = vec![vec![]; self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for succ in block.terminator.successors() {
+                preds[succ].push(id);
+            }
+        }
+        preds
+    }
+
+    /// The blocks reachable from the entry, each before all of its
+    /// successors except along back edges.
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = vec![];
+        // Each entry is a block and how many of its successors are done
+        let mut stack = vec![(ENTRY, 0)];
+        visited[ENTRY] = true;
+
+        while let Some((block, next)) = stack.pop() {
+            let succs = self.successors(block);
+            if next < succs.len() {
+                stack.push((block, next + 1));
+                let succ = succs[next];
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                order.push(block);
+            }
+        }
+
+        order.reverse();
+        order
+    }
+
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        for block in self.reverse_postorder() {
+            reachable[block] = true;
+        }
+        reachable
+    }
+
+    /// Computes dominators with the iterative algorithm from Cooper, Harvey
+    /// and Kennedy's "A Simple, Fast Dominance Algorithm".
+    pub fn dominators(&self) -> Dominators {
+        let order = self.reverse_postorder();
+        let preds = self.predecessors();
+
+        let mut rpo_index = vec![usize::max_value(); self.blocks.len()];
+        for (i, &block) in order.iter().enumerate() {
+            rpo_index[block] = i;
+        }
+
+        let mut idom = vec![None; self.blocks.len()];
+        idom[ENTRY] = Some(ENTRY);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in &preds[block] {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idom, &rpo_index, pred, other),
+                    });
+                }
+
+                if new_idom != idom[block] {
+                    idom[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        Dominators { idom: idom }
+    }
+
+    /// Renders the graph in Graphviz's dot language.
+    pub fn to_dot(&self, name: &str) -> String {
+        let config = Config::default();
+        let reachable = self.reachable();
+        let mut out = String::new();
+
+        writeln!(out, "digraph \"{}\" {{", escape(name)).unwrap();
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut label = match id {
+                ENTRY => "entry".to_string(),
+                EXIT => "exit".to_string(),
+                _ => format!("bb{}", id),
+            };
+            label.push_str("\\l");
+
+            for node in &block.nodes {
+                let text = match *node {
+                    Node::Statement(stmnt) => printer::print_statement(stmnt, &config),
+                    Node::Condition(expr) => {
+                        format!("cond {}", printer::print_expression(expr, &config))
+                    }
+                    Node::Iterator(expr) => {
+                        format!("iter {}", printer::print_expression(expr, &config))
+                    }
+                };
+                label.push_str(&escape(&text));
+                label.push_str("\\l");
+            }
+
+            let style = if reachable[id] { "" } else { ", style=dashed" };
+            writeln!(out, "    bb{} [label=\"{}\"{}];", id, label, style).unwrap();
+        }
+
+        for (id, block) in self.blocks.iter().enumerate() {
+            let edges: Vec<(BlockId, &str)> = match block.terminator {
+                Terminator::Goto(target) => vec![(target, "")],
+                Terminator::Branch(_, t, f) => vec![(t, "true"), (f, "false")],
+                Terminator::Iterate(_, body, done) => vec![(body, "next"), (done, "done")],
+                Terminator::Try(ok) => vec![(ok, "ok"), (EXIT, "error")],
+                Terminator::Return(_) => vec![(EXIT, "return")],
+                Terminator::Throw(_) => vec![(EXIT, "throw")],
+                Terminator::Exit => vec![],
+            };
+
+            for (target, label) in edges {
+                if label.is_empty() {
+                    writeln!(out, "    bb{} -> bb{};", id, target).unwrap();
+                } else {
+                    writeln!(out, "    bb{} -> bb{} [label=\"{}\"];", id, target, label).unwrap();
+                }
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+#[derive(Debug)]
+pub struct Dominators {
+    idom: Vec<Option<BlockId>>,
+}
+
+impl Dominators {
+    /// The closest block that dominates `block`. The entry is its own
+    /// immediate dominator, and unreachable blocks have none.
+    pub fn immediate(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block]
+    }
+
+    /// Whether every path from the entry to `b` goes through `a`.
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        let mut block = b;
+        loop {
+            if block == a {
+                return true;
+            }
+            match self.idom[block] {
+                Some(parent) if parent != block => block = parent,
+                _ => return false,
+            }
+        }
+    }
+}
+
+fn intersect(idom: &[Option<BlockId>], rpo_index: &[usize], a: BlockId, b: BlockId) -> BlockId {
+    let (mut a, mut b) = (a, b);
+    while a != b {
+        while rpo_index[a] > rpo_index[b] {
+            a = idom[a].unwrap();
+        }
+        while rpo_index[b] > rpo_index[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\l")
+}
+
+struct LoopTargets<'a> {
+    label: Option<&'a str>,
+    head: BlockId,
+    after: BlockId,
+}
+
+struct Builder<'a> {
+    blocks: Vec<BasicBlock<'a>>,
+    current: BlockId,
+    loops: Vec<LoopTargets<'a>>,
+}
+
+impl<'a> Builder<'a> {
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(BasicBlock {
+            nodes: vec![],
+            terminator: Terminator::Goto(EXIT),
+        });
+        self.blocks.len() - 1
+    }
+
+    fn terminate(&mut self, terminator: Terminator<'a>) {
+        self.blocks[self.current].terminator = terminator;
+    }
+
+    fn goto(&mut self, target: BlockId) {
+        self.terminate(Terminator::Goto(target));
+    }
+
+    /// Ends the current block with a jump. Anything after it goes into a
+    /// new block that nothing leads to.
+    fn jump(&mut self, terminator: Terminator<'a>) {
+        self.terminate(terminator);
+        self.current = self.new_block();
+    }
+
+    fn node(&mut self, node: Node<'a>) {
+        let fallible = match node {
+            Node::Statement(stmnt) => {
+                let mut finder = FindTry(false);
+                finder.visit_statement(stmnt);
+                finder.0
+            }
+            Node::Condition(expr) | Node::Iterator(expr) => {
+                let mut finder = FindTry(false);
+                finder.visit_expression(expr);
+                finder.0
+            }
+        };
+
+        self.blocks[self.current].nodes.push(node);
+
+        if fallible {
+            let next = self.new_block();
+            self.terminate(Terminator::Try(next));
+            self.current = next;
+        }
+    }
+
+    fn find_loop(&self, label: &Option<Label>) -> Option<&LoopTargets<'a>> {
+        match *label {
+            Some(ref label) => self.loops.iter().rev().find(|l| l.label == Some(&label[..])),
+            None => self.loops.last(),
+        }
+    }
+
+    fn enter_loop(&mut self, label: &'a Option<Label>, head: BlockId, after: BlockId) {
+        self.loops.push(LoopTargets {
+            label: label.as_ref().map(|l| &l[..]),
+            head: head,
+            after: after,
+        });
+    }
+
+    fn block(&mut self, block: &'a Block) {
+        for stmnt in &block.statements {
+            self.statement(stmnt);
+        }
+    }
+
+    fn statement(&mut self, stmnt: &'a Statement) {
+        match *stmnt {
+            Statement::Use(_) |
+            Statement::Expression(_) |
+            Statement::Declaration(..) |
+            Statement::Assignment(..) => self.node(Node::Statement(stmnt)),
+
+            Statement::IfElse(ref if_else) => self.if_else(if_else),
+
+            Statement::Loop(ref l) => {
+                let head = self.new_block();
+                let after = self.new_block();
+                self.goto(head);
+                self.current = head;
+
+                self.enter_loop(&l.label, head, after);
+                self.block(&l.block);
+                self.goto(head);
+                self.loops.pop();
+
+                self.current = after;
+            }
+
+            Statement::WhileLoop(ref l) => {
+                let head = self.new_block();
+                let body = self.new_block();
+                let after = self.new_block();
+                self.goto(head);
+                self.current = head;
+                self.node(Node::Condition(&l.condition));
+                self.terminate(Terminator::Branch(&l.condition, body, after));
+
+                self.current = body;
+                self.enter_loop(&l.label, head, after);
+                self.block(&l.block);
+                self.goto(head);
+                self.loops.pop();
+
+                self.current = after;
+            }
+
+            Statement::ForLoop(ref l) => {
+                self.node(Node::Iterator(&l.iterator));
+                let head = self.new_block();
+                let body = self.new_block();
+                let after = self.new_block();
+                self.goto(head);
+                self.current = head;
+                self.terminate(Terminator::Iterate(l, body, after));
+
+                self.current = body;
+                self.enter_loop(&l.label, head, after);
+                self.block(&l.block);
+                self.goto(head);
+                self.loops.pop();
+
+                self.current = after;
+            }
+
+            Statement::Return(_, loc) => {
+                self.node(Node::Statement(stmnt));
+                self.jump(Terminator::Return(loc));
+            }
+            Statement::Throw(_, loc) => {
+                self.node(Node::Statement(stmnt));
+                self.jump(Terminator::Throw(loc));
+            }
+
+            // A jump with no loop to go to is reported by the
+            // `break_outside_loop` and `unknown_label` lints, and just leaves
+            // the function here
+            Statement::Break(ref label, _) => {
+                let target = self.find_loop(label).map(|l| l.after).unwrap_or(EXIT);
+                self.jump(Terminator::Goto(target));
+            }
+            Statement::Continue(ref label, _) => {
+                let target = self.find_loop(label).map(|l| l.head).unwrap_or(EXIT);
+                self.jump(Terminator::Goto(target));
+            }
+        }
+    }
+
+    fn if_else(&mut self, if_else: &'a IfElse) {
+        self.node(Node::Condition(&if_else.condition));
+
+        let then = self.new_block();
+        let after = self.new_block();
+        let otherwise = match if_else.else_block {
+            Some(_) => self.new_block(),
+            None => after,
+        };
+        self.terminate(Terminator::Branch(&if_else.condition, then, otherwise));
+
+        self.current = then;
+        self.block(&if_else.if_block);
+        self.goto(after);
+
+        if let Some(ref else_block) = if_else.else_block {
+            self.current = otherwise;
+            self.block(else_block);
+            self.goto(after);
+        }
+
+        self.current = after;
+    }
+}
+
+/// Looks for a `?` outside of any lambda.
+struct FindTry(bool);
+
+impl Visitor for FindTry {
+    fn visit_expression(&mut self, expr: &Expression) {
+        if let Expression::Try(..) = *expr {
+            self.0 = true;
+        }
+        visit::walk_expression(self, expr);
+    }
+
+    fn visit_lambda(&mut self, _lambda: &Lambda) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::parse_Module;
+
+    fn function(source: &str) -> Function {
+        match parse_Module(Lexer::new(source)).unwrap() {
+            Module::Root { mut items } => {
+                match items.remove(0) {
+                    Item::Function(_, func) => func,
+                    _ => panic!("expected a function"),
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// The block with a statement or condition printed as `text`.
+    fn find(cfg: &Cfg, text: &str) -> BlockId {
+        let config = Config::default();
+        cfg.blocks
+            .iter()
+            .position(|block| {
+                block.nodes.iter().any(|node| {
+                    let printed = match *node {
+                        Node::Statement(stmnt) => printer::print_statement(stmnt, &config),
+                        Node::Condition(expr) | Node::Iterator(expr) => {
+                            printer::print_expression(expr, &config)
+                        }
+                    };
+                    printed.trim() == text
+                })
+            })
+            .unwrap_or_else(|| panic!("no block has `{}`", text))
+    }
+
+    fn goto_target(cfg: &Cfg, block: BlockId) -> BlockId {
+        match cfg.blocks[block].terminator {
+            Terminator::Goto(target) => target,
+            ref other => panic!("expected a goto, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn labelled_jumps() {
+        let func = function("fn f() {
+            'outer: loop {
+                loop {
+                    a();
+                    break 'outer;
+                }
+            }
+            b();
+            'rows: while x {
+                for y in z {
+                    c();
+                    continue 'rows;
+                }
+            }
+        }");
+        let cfg = Cfg::build(&func);
+
+        assert_eq!(goto_target(&cfg, find(&cfg, "a();")), find(&cfg, "b();"));
+        assert_eq!(goto_target(&cfg, find(&cfg, "c();")), find(&cfg, "x"));
+    }
+
+    #[test]
+    fn try_in_conditions_splits_the_block() {
+        let func = function("fn f()? {
+            while g()? {
+                a();
+            }
+            if h()? {
+                b();
+            }
+        }");
+        let cfg = Cfg::build(&func);
+
+        for &(cond, body) in &[("g()?", "a();"), ("h()?", "b();")] {
+            let next = match cfg.blocks[find(&cfg, cond)].terminator {
+                Terminator::Try(next) => next,
+                ref other => panic!("expected a try after `{}`, found {:?}", cond, other),
+            };
+            match cfg.blocks[next].terminator {
+                Terminator::Branch(_, then, _) => assert_eq!(then, find(&cfg, body)),
+                ref other => panic!("expected a branch on `{}`, found {:?}", cond, other),
+            }
+        }
+    }
+
+    #[test]
+    fn code_after_a_jump_is_unreachable() {
+        let func = function("fn f() {
+            loop {
+                break;
+                a();
+            }
+            return 1;
+            b();
+        }");
+        let cfg = Cfg::build(&func);
+        let reachable = cfg.reachable();
+
+        assert!(!reachable[find(&cfg, "a();")]);
+        assert!(!reachable[find(&cfg, "b();")]);
+        assert!(reachable[find(&cfg, "return 1;")]);
+        match cfg.blocks[find(&cfg, "return 1;")].terminator {
+            Terminator::Return(_) => (),
+            ref other => panic!("expected a return, found {:?}", other),
+        }
+        assert_eq!(cfg.dominators().immediate(find(&cfg, "b();")), None);
+    }
+
+    #[test]
+    fn loop_head_dominators() {
+        let func = function("fn f() {
+            a();
+            while x {
+                if y {
+                    b();
+                } else {
+                    c();
+                }
+            }
+            d();
+        }");
+        let cfg = Cfg::build(&func);
+        let doms = cfg.dominators();
+        let head = find(&cfg, "x");
+
+        // The back edges from the loop body don't change the head's dominator
+        assert_eq!(doms.immediate(head), Some(find(&cfg, "a();")));
+        assert_eq!(doms.immediate(find(&cfg, "d();")), Some(head));
+        assert!(doms.dominates(head, find(&cfg, "b();")));
+        assert!(doms.dominates(head, find(&cfg, "c();")));
+        assert!(!doms.dominates(find(&cfg, "b();"), head));
+        assert_eq!(doms.immediate(ENTRY), Some(ENTRY));
+    }
+}
@@ -1,182 +1,98 @@
+//! Constant folding.
+//!
+//! Numbers are computed with the same kernels as the runtime, in `vm::ops`,
+//! so folding never changes what an expression evaluates to. Integers that
+//! overflow an `i64` become `Literal::BigInt`, and fold back to
+//! `Literal::Integer` when a result fits again. Dividing by zero and
+//! shifting by a negative amount or by 64 or more are errors, and folding
+//! never evaluates such an expression.
+//! It is left in the tree so the error is raised when the code runs, and a
+//! `Diagnostic` pointing at the operator is returned so it can be reported at
+//! compile time too.
+//!
+//! A number computed from a suffixed operand keeps the suffix, since it may
+//! pick the lane type of a SIMD vector. Math with an `f32` operand is done
+//! in `f32`. A result that doesn't fit its suffix's type, or one of two
+//! operands with different suffixes, isn't folded.
+
 use ast;
 use ast::ArrayLiteral;
 use ast::BinOp;
 use ast::Expression;
 use ast::Literal;
-use num::Integer;
+use ast::NumSuffix;
+use ast::visit::{self, VisitorMut};
+use vm::ops::{self, Number};
 
-pub fn fold_module(root: &mut ast::Module) {
-    match *root {
-        ast::Module::Root { ref mut items } => {
-            for item in items.iter_mut() {
-                fold_item(item);
-            }
-        }
-        ast::Module::Inline { ref mut items, .. } => {
-            for item in items.iter_mut() {
-                fold_item(item);
-            }
-        }
-        _ => (),
-    }
-}
+pub use vm::ops::ArithError as FoldError;
 
-fn fold_item(item: &mut ast::Item) {
-    match *item {
-        ast::Item::Module(ref mut module) => fold_module(module),
-        ast::Item::TypeImpl(_, ref mut imp) => fold_impl(imp),
-        ast::Item::Function(_, ref mut func) => fold_func(func),
-        _ => (),
-    }
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub location: ast::Location,
+    pub error: FoldError,
 }
 
-fn fold_impl(imp: &mut ast::TypeImpl) {
-    for func in imp.methods.iter_mut() {
-        fold_func(func);
-    }
+pub fn fold_module(root: &mut ast::Module) -> Vec<Diagnostic> {
+    let mut folder = ConstantFolder { diagnostics: vec![] };
+    folder.visit_module(root);
+    folder.diagnostics
 }
 
-fn fold_func(func: &mut ast::Function) {
-    fold_block(&mut func.body);
+pub fn fold(expr: &mut Expression) -> Vec<Diagnostic> {
+    let mut folder = ConstantFolder { diagnostics: vec![] };
+    folder.visit_expression(expr);
+    folder.diagnostics
 }
 
-fn fold_block(block: &mut ast::Block) {
-    for stmnt in block.statements.iter_mut() {
-        fold_statement(stmnt);
-    }
+struct ConstantFolder {
+    diagnostics: Vec<Diagnostic>,
 }
 
-fn fold_statement(stmnt: &mut ast::Statement) {
-    use ast::Statement::*;
-    match *stmnt {
-        Expression(ref mut expr) => fold(expr),
-        Declaration(_, _, Some(ref mut expr)) => fold(expr),
-        Assignment(ref mut lhs, ref mut extra, _, ref mut rhs) => {
-            fold(lhs);
-            fold_all(extra);
-            fold(rhs);
-        }
-        IfElse(ast::IfElse { ref mut if_block, ref mut else_block, .. }) => {
-            fold_block(if_block);
-            if let Some(ref mut else_block) = *else_block {
-                fold_block(else_block);
-            }
-        }
-        Loop(ast::Loop { ref mut block, .. }) => fold_block(block),
-        ForLoop(ast::ForLoop { ref mut iterator, ref mut block, .. }) => {
-            fold(iterator);
-            fold_block(block);
-        }
-        WhileLoop(ast::WhileLoop { ref mut condition, ref mut block, .. }) => {
-            fold(condition);
-            fold_block(block);
-        }
-        Return(ref mut exprs) => fold_all(exprs),
-        Throw(ref mut expr) => fold(expr),
-
-        _ => (),
-    }
-}
+impl VisitorMut for ConstantFolder {
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        visit::walk_expression_mut(self, expr);
 
-fn fold_all(exprs: &mut [Expression]) {
-    for expr in exprs.iter_mut() {
-        fold(expr);
+        if let Some(diag) = simplify(expr) {
+            self.diagnostics.push(diag);
+        }
     }
 }
 
-pub fn fold(expr: &mut Expression) {
+/// Folds `expr` itself, assuming its operands have already been folded.
+pub fn simplify(expr: &mut Expression) -> Option<Diagnostic> {
     let new_value = match *expr {
-        Expression::Literal(ref mut lit) => {
-            fold_literal(lit);
-            None
-        }
-        Expression::MemberAccess(ref mut lhs, _) => {
-            fold(lhs);
-            None
-        }
-        Expression::IndexAccess(ref mut lhs, ref mut exprs) => {
-            fold(lhs);
-            fold_all(exprs);
-            None
-        }
-        Expression::FunctionCall(ref mut lhs, ref mut exprs) => {
-            fold(lhs);
-            fold_all(exprs);
-            None
-        }
-        Expression::ObjectConstructor(_, ref mut lit) => {
-            fold_obj_literal(lit);
-            None
-        }
-        Expression::BinaryOp(ref mut lhs, op, ref mut rhs) => {
-            fold(lhs);
-            fold(rhs);
-            simplify_binary(lhs, op, rhs)
-        }
-        Expression::Negate(ref mut rhs) => {
-            fold(rhs);
-            apply_negate(rhs)
-        }
-        Expression::Not(ref mut rhs) => {
-            fold(rhs);
-            apply_not(rhs)
-        }
-        Expression::Try(ref mut lhs) => {
-            fold(lhs);
-            None
-        }
-        Expression::Lambda(ref mut lambda) => {
-            fold_block(&mut lambda.body);
-            None
+        Expression::BinaryOp(ref lhs, op, ref rhs, loc) => {
+            match simplify_binary(lhs, op, rhs) {
+                Ok(val) => val,
+                Err(err) => {
+                    return Some(Diagnostic {
+                        location: loc,
+                        error: err,
+                    })
+                }
+            }
         }
+        Expression::Negate(ref rhs) => apply_negate(rhs),
+        Expression::Not(ref rhs) => apply_not(rhs),
+        Expression::Literal(Literal::SimdSplat(ref value, ref ty)) => expand_simd_splat(value, ty),
         _ => None,
     };
 
     if let Some(val) = new_value {
         *expr = val;
     }
-}
-
-fn fold_literal(lit: &mut Literal) {
-    match *lit {
-        Literal::Array(ref mut arr) => fold_arr_literal(arr),
-        Literal::Object(ref mut obj) => fold_obj_literal(obj),
-        Literal::Simd(ref mut arr, _) => fold_all(arr),
-        Literal::SimdSplat(ref mut expr, _) => fold(&mut **expr),
-        _ => (),
-    }
-}
-
-fn fold_arr_literal(arr: &mut ArrayLiteral) {
-    match *arr {
-        ArrayLiteral::List(ref mut exprs) => fold_all(exprs),
-        ArrayLiteral::Splat(ref mut val, ref mut count) => {
-            fold(val);
-            fold(count);
-        }
-    }
-}
-
-fn fold_obj_literal(obj: &mut ast::ObjectLiteral) {
-    for (_, expr) in obj.iter_mut() {
-        fold(expr);
-    }
+    None
 }
 
 fn apply_negate(rhs: &Expression) -> Option<Expression> {
-    use ast::Literal::*;
-
     let lit = match *rhs {
         Expression::Literal(ref lit) => lit,
         _ => return None,
     };
-
-    Some(Expression::Literal(match *lit {
-        Integer(i) => Integer(-i),
-        Float(f) => Float(-f),
-
-        _ => return None,
-    }))
+    match number(unsuffixed(lit)) {
+        Some(n) => with_suffix(number_literal(n.negate()), suffix(lit)).map(Expression::Literal),
+        None => None,
+    }
 }
 
 fn apply_not(rhs: &Expression) -> Option<Expression> {
@@ -187,34 +103,51 @@ fn apply_not(rhs: &Expression) -> Option<Expression> {
         _ => return None,
     };
 
-    Some(Expression::Literal(match *lit {
+    let not = match *unsuffixed(lit) {
         Bool(b) => Bool(!b),
         Integer(i) => Integer(!i),
 
         _ => return None,
-    }))
+    };
+    with_suffix(not, suffix(lit)).map(Expression::Literal)
 }
 
-fn simplify_binary(lhs: &Expression, op: BinOp, rhs: &Expression) -> Option<Expression> {
+fn simplify_binary(lhs: &Expression,
+                   op: BinOp,
+                   rhs: &Expression)
+                   -> Result<Option<Expression>, FoldError> {
     use ast::BinOp::*;
 
     let (lhs, rhs) = match (lhs, rhs) {
         (&Expression::Literal(ref lhs), &Expression::Literal(ref rhs)) => (lhs, rhs),
-        _ => return None,
+        _ => return Ok(None),
     };
+    let suffix = match (suffix(lhs), suffix(rhs)) {
+        (Some(l), Some(r)) if l != r => return Ok(None),
+        (l, r) => l.or(r),
+    };
+    let (lhs, rhs) = (unsuffixed(lhs), unsuffixed(rhs));
+
+    match (lhs, rhs) {
+        (&Literal::String(_), &Literal::String(_)) => return Ok(simplify_string(lhs, op, rhs)),
+        (&Literal::Array(_), &Literal::Array(_)) => return Ok(simplify_array(lhs, op, rhs)),
+        (&Literal::Simd(..), &Literal::Simd(..)) => return Ok(simplify_simd(lhs, op, rhs)),
+        _ => (),
+    }
 
     match op {
-        LogicalOr | LogicalAnd => simplify_logical(lhs, op, rhs),
+        LogicalOr | LogicalAnd => Ok(simplify_logical(lhs, op, rhs)),
         Equal | NotEqual | LessThan | LessOrEqual | GreaterThan | GreaterOrEqual => {
-            simplify_equality(lhs, op, rhs)
+            Ok(simplify_equality(lhs, op, rhs))
         }
-        BitOr | BitAnd | BitXor => simplify_bitwise(lhs, op, rhs),
-        LShiftLeft | LShiftRight | AShiftRight => simplify_shift(lhs, op, rhs),
+        BitOr | BitAnd | BitXor => Ok(simplify_bitwise(lhs, op, rhs, suffix)),
+        LShiftLeft | LShiftRight | AShiftRight => simplify_shift(lhs, op, rhs, suffix),
 
-        Add | Sub | Mul | Div | Rem | Mod => simplify_arithmetic(lhs, op, rhs),
+        Add | Sub | Mul | Div | Rem | Mod => simplify_arithmetic(lhs, op, rhs, suffix),
+        DivRem => simplify_divrem(lhs, rhs, suffix),
 
         // I can't constant-fold these
-        Implements | RangeExclusive | RangeInclusive | DivRem => None,
+        Implements | RangeExclusive | RangeInclusive => Ok(None),
     }
 }
 
@@ -230,126 +163,509 @@ fn simplify_logical(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expressio
     }))
 }
 
-fn simplify_equality(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
-    use ast::BinOp::*;
-    use ast::Literal::*;
+/// A suffixed number's value.
+fn unsuffixed(lit: &Literal) -> &Literal {
+    match *lit {
+        Literal::Suffixed(ref lit, _) => lit,
+        ref lit => lit,
+    }
+}
 
-    Some(Expression::Literal(match (lhs, op, rhs) {
-        (&Bool(l), Equal, &Bool(r)) => Bool(l == r),
-        (&Integer(l), Equal, &Integer(r)) => Bool(l == r),
-        (&Integer(l), Equal, &Float(r)) => Bool((l as f64) == r),
-        (&Float(l), Equal, &Integer(r)) => Bool(l == (r as f64)),
-        (&Float(l), Equal, &Float(r)) => Bool(l == r),
-
-        (&Bool(l), NotEqual, &Bool(r)) => Bool(l != r),
-        (&Integer(l), NotEqual, &Integer(r)) => Bool(l != r),
-        (&Integer(l), NotEqual, &Float(r)) => Bool((l as f64) != r),
-        (&Float(l), NotEqual, &Integer(r)) => Bool(l != (r as f64)),
-        (&Float(l), NotEqual, &Float(r)) => Bool(l != r),
-
-        (&Integer(l), LessThan, &Integer(r)) => Bool(l < r),
-        (&Integer(l), LessThan, &Float(r)) => Bool((l as f64) < r),
-        (&Float(l), LessThan, &Integer(r)) => Bool(l < (r as f64)),
-        (&Float(l), LessThan, &Float(r)) => Bool(l < r),
-
-        (&Integer(l), LessOrEqual, &Integer(r)) => Bool(l <= r),
-        (&Integer(l), LessOrEqual, &Float(r)) => Bool((l as f64) <= r),
-        (&Float(l), LessOrEqual, &Integer(r)) => Bool(l <= (r as f64)),
-        (&Float(l), LessOrEqual, &Float(r)) => Bool(l <= r),
-
-        (&Integer(l), GreaterThan, &Integer(r)) => Bool(l > r),
-        (&Integer(l), GreaterThan, &Float(r)) => Bool((l as f64) > r),
-        (&Float(l), GreaterThan, &Integer(r)) => Bool(l > (r as f64)),
-        (&Float(l), GreaterThan, &Float(r)) => Bool(l > r),
-
-        (&Integer(l), GreaterOrEqual, &Integer(r)) => Bool(l >= r),
-        (&Integer(l), GreaterOrEqual, &Float(r)) => Bool((l as f64) >= r),
-        (&Float(l), GreaterOrEqual, &Integer(r)) => Bool(l >= (r as f64)),
-        (&Float(l), GreaterOrEqual, &Float(r)) => Bool(l >= r),
+fn suffix(lit: &Literal) -> Option<NumSuffix> {
+    match *lit {
+        Literal::Suffixed(_, suffix) => Some(suffix),
+        _ => None,
+    }
+}
 
+/// `lit` with `suffix`, if its value has that type. An `f32` is rounded to
+/// one, and mustn't overflow it.
+fn with_suffix(lit: Literal, suffix: Option<NumSuffix>) -> Option<Literal> {
+    let suffix = match suffix {
+        Some(suffix) => suffix,
+        None => return Some(lit),
+    };
+    let lit = match (lit, suffix) {
+        (Literal::Integer(i), NumSuffix::I32) if fits_i32(i) => Literal::Integer(i),
+        (Literal::Integer(i), NumSuffix::U32) if fits_u32(i) => Literal::Integer(i),
+        (Literal::Integer(i), NumSuffix::I64) => Literal::Integer(i),
+        (Literal::Float(f), NumSuffix::F32) if (f as f32).is_finite() || !f.is_finite() => {
+            Literal::Float(f as f32 as f64)
+        }
+        (Literal::Float(f), NumSuffix::F64) => Literal::Float(f),
         _ => return None,
-    }))
+    };
+    Some(Literal::Suffixed(Box::new(lit), suffix))
+}
+
+/// The operands of an arithmetic operator, which are `f32`s when the result
+/// is.
+fn operands(lhs: &Literal,
+            rhs: &Literal,
+            suffix: Option<NumSuffix>)
+            -> Option<(Number, Number)> {
+    let (l, r) = match (number(lhs), number(rhs)) {
+        (Some(l), Some(r)) => (l, r),
+        _ => return None,
+    };
+    if suffix != Some(NumSuffix::F32) {
+        return Some((l, r));
+    }
+    let round = |num: Number| match number_literal(num) {
+        Literal::Integer(i) => Number::Float(i as f32 as f64),
+        Literal::BigInt(s) => Number::Float(s.parse::<f32>().unwrap() as f64),
+        Literal::Float(f) => Number::Float(f as f32 as f64),
+        _ => unreachable!(),
+    };
+    Some((round(l), round(r)))
 }
 
-fn simplify_bitwise(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
+fn number(lit: &Literal) -> Option<Number> {
+    match *lit {
+        Literal::Integer(i) => Some(Number::Int(i)),
+        Literal::BigInt(ref s) => s.parse().ok().map(Number::Big),
+        Literal::Float(f) => Some(Number::Float(f)),
+        _ => None,
+    }
+}
+
+fn number_literal(num: Number) -> Literal {
+    match num {
+        Number::Int(i) => Literal::Integer(i),
+        Number::Big(b) => Literal::BigInt(b.to_string()),
+        Number::Float(f) => Literal::Float(f),
+    }
+}
+
+fn simplify_equality(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
     use ast::BinOp::*;
+
+    let result = match (lhs, op, rhs) {
+        (&Literal::Bool(l), Equal, &Literal::Bool(r)) => Some(l == r),
+        (&Literal::Bool(l), NotEqual, &Literal::Bool(r)) => Some(l != r),
+        (&Literal::Symbol(ref l), Equal, &Literal::Symbol(ref r)) => Some(l == r),
+        (&Literal::Symbol(ref l), NotEqual, &Literal::Symbol(ref r)) => Some(l != r),
+        _ => {
+            match (number(lhs), number(rhs)) {
+                (Some(l), Some(r)) => l.compare(op, &r),
+                _ => None,
+            }
+        }
+    };
+    result.map(|b| Expression::Literal(Literal::Bool(b)))
+}
+
+fn simplify_bitwise(lhs: &Literal,
+                    op: BinOp,
+                    rhs: &Literal,
+                    suffix: Option<NumSuffix>)
+                    -> Option<Expression> {
     use ast::Literal::*;
 
-    Some(Expression::Literal(match (lhs, op, rhs) {
-        (&Bool(l), BitOr, &Bool(r)) => Bool(l | r),
-        (&Bool(l), BitAnd, &Bool(r)) => Bool(l & r),
-        (&Bool(l), BitXor, &Bool(r)) => Bool(l ^ r),
+    match (lhs, rhs) {
+        (&Bool(l), &Bool(r)) => ops::bool_bitwise(l, op, r).map(|b| Expression::Literal(Bool(b))),
+        (&Integer(l), &Integer(r)) => {
+            ops::int_bitwise(l, op, r)
+                .and_then(|i| with_suffix(Integer(i), suffix))
+                .map(Expression::Literal)
+        }
+        _ => None,
+    }
+}
 
-        (&Integer(l), BitOr, &Integer(r)) => Integer(l | r),
-        (&Integer(l), BitAnd, &Integer(r)) => Integer(l & r),
-        (&Integer(l), BitXor, &Integer(r)) => Integer(l ^ r),
+fn simplify_shift(lhs: &Literal,
+                  op: BinOp,
+                  rhs: &Literal,
+                  suffix: Option<NumSuffix>)
+                  -> Result<Option<Expression>, FoldError> {
+    match (lhs, rhs) {
+        (&Literal::Integer(l), &Literal::Integer(r)) => {
+            Ok(ops::shift(l, op, r)?
+                .and_then(|i| with_suffix(Literal::Integer(i), suffix))
+                .map(Expression::Literal))
+        }
+        _ => Ok(None),
+    }
+}
 
-        _ => return None,
-    }))
+fn simplify_arithmetic(lhs: &Literal,
+                       op: BinOp,
+                       rhs: &Literal,
+                       suffix: Option<NumSuffix>)
+                       -> Result<Option<Expression>, FoldError> {
+    match operands(lhs, rhs, suffix) {
+        Some((l, r)) => {
+            Ok(l.arith(op, &r)?
+                .and_then(|n| with_suffix(number_literal(n), suffix))
+                .map(Expression::Literal))
+        }
+        None => Ok(None),
+    }
+}
+
+/// `a /% b` is the pair `[a / b, a % b]`.
+fn simplify_divrem(lhs: &Literal,
+                   rhs: &Literal,
+                   suffix: Option<NumSuffix>)
+                   -> Result<Option<Expression>, FoldError> {
+    let (div, rem) = match operands(lhs, rhs, suffix) {
+        Some((l, r)) => l.divrem(&r)?,
+        None => return Ok(None),
+    };
+    let (div, rem) = match (with_suffix(number_literal(div), suffix),
+                            with_suffix(number_literal(rem), suffix)) {
+        (Some(div), Some(rem)) => (div, rem),
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Expression::Literal(Literal::Array(ArrayLiteral::List(vec![
+        Expression::Literal(div),
+        Expression::Literal(rem),
+    ])))))
 }
 
-fn simplify_shift(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
+/// The text between the quotes of a string literal, if it has no escapes.
+/// Literals are kept as written, so escaped strings can't be compared yet.
+fn plain_string(lit: &str) -> Option<&str> {
+    let body = &lit[1..lit.len() - 1];
+    if body.contains('\\') { None } else { Some(body) }
+}
+
+fn simplify_string(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
     use ast::BinOp::*;
     use ast::Literal::*;
 
-    Some(Expression::Literal(match (lhs, op, rhs) {
-        (&Integer(l), LShiftLeft, &Integer(r)) => Integer(((l as u64) << r) as i64),
-        (&Integer(l), AShiftRight, &Integer(r)) => Integer(l >> r),
-        (&Integer(l), LShiftRight, &Integer(r)) => Integer(((l as u64) >> r) as i64),
+    let (l, r) = match (lhs, rhs) {
+        (&String(ref l), &String(ref r)) => (l, r),
+        _ => return None,
+    };
+
+    if let Add = op {
+        // Escapes stay valid when only the inner quotes are removed
+        return Some(Expression::Literal(String(format!("{}{}", &l[..l.len() - 1], &r[1..]))));
+    }
 
+    let (l, r) = match (plain_string(l), plain_string(r)) {
+        (Some(l), Some(r)) => (l, r),
         _ => return None,
-    }))
+    };
+
+    Some(Expression::Literal(Bool(match op {
+        Equal => l == r,
+        NotEqual => l != r,
+        LessThan => l < r,
+        LessOrEqual => l <= r,
+        GreaterThan => l > r,
+        GreaterOrEqual => l >= r,
+
+        _ => return None,
+    })))
 }
 
-fn simplify_arithmetic(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
-    use ast::BinOp::*;
+fn simplify_array(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
+    let equal = match op {
+        BinOp::Equal => true,
+        BinOp::NotEqual => false,
+        _ => return None,
+    };
+
+    literal_eq(lhs, rhs).map(|eq| Expression::Literal(Literal::Bool(eq == equal)))
+}
+
+/// Structural equality of two constants, or `None` if either isn't one.
+fn expr_eq(lhs: &Expression, rhs: &Expression) -> Option<bool> {
+    match (lhs, rhs) {
+        (&Expression::Nil, &Expression::Nil) => Some(true),
+        (&Expression::Nil, &Expression::Literal(_)) |
+        (&Expression::Literal(_), &Expression::Nil) => Some(false),
+        (&Expression::Literal(ref l), &Expression::Literal(ref r)) => literal_eq(l, r),
+        _ => None,
+    }
+}
+
+fn literal_eq(lhs: &Literal, rhs: &Literal) -> Option<bool> {
     use ast::Literal::*;
 
-    Some(Expression::Literal(match (lhs, op, rhs) {
-        // Add
-        (&Integer(l), Add, &Integer(r)) => Integer(l + r),
-        (&Integer(l), Add, &Float(r)) => Float((l as f64) + r),
-        (&Float(l), Add, &Integer(r)) => Float(l + (r as f64)),
-        (&Float(l), Add, &Float(r)) => Float(l + r),
-
-        // Sub
-        (&Integer(l), Sub, &Integer(r)) => Integer(l - r),
-        (&Integer(l), Sub, &Float(r)) => Float((l as f64) - r),
-        (&Float(l), Sub, &Integer(r)) => Float(l - (r as f64)),
-        (&Float(l), Sub, &Float(r)) => Float(l - r),
-
-        // Mul
-        (&Integer(l), Mul, &Integer(r)) => Integer(l * r),
-        (&Integer(l), Mul, &Float(r)) => Float((l as f64) * r),
-        (&Float(l), Mul, &Integer(r)) => Float(l * (r as f64)),
-        (&Float(l), Mul, &Float(r)) => Float(l * r),
-
-        // Div
-        (&Integer(l), Div, &Integer(r)) => Integer(l / r),
-        (&Integer(l), Div, &Float(r)) => Float((l as f64) / r),
-        (&Float(l), Div, &Integer(r)) => Float(l / (r as f64)),
-        (&Float(l), Div, &Float(r)) => Float(l / r),
-
-        // Rem
-        (&Integer(l), Rem, &Integer(r)) => Integer(l % r),
-        (&Integer(l), Rem, &Float(r)) => Float(frem((l as f64), r)),
-        (&Float(l), Rem, &Integer(r)) => Float(frem(l, (r as f64))),
-        (&Float(l), Rem, &Float(r)) => Float(frem(l, r)),
-
-        // Mod
-        (&Integer(l), Mod, &Integer(r)) => Integer(l.mod_floor(&r)),
-        (&Integer(l), Mod, &Float(r)) => Float(fmod((l as f64), r)),
-        (&Float(l), Mod, &Integer(r)) => Float(fmod(l, (r as f64))),
-        (&Float(l), Mod, &Float(r)) => Float(fmod(l, r)),
+    if let (Some(l), Some(r)) = (number(lhs), number(rhs)) {
+        return l.compare(BinOp::Equal, &r);
+    }
+
+    match (lhs, rhs) {
+        (&Bool(l), &Bool(r)) => Some(l == r),
+        (&Symbol(ref l), &Symbol(ref r)) => Some(l == r),
+        (&String(ref l), &String(ref r)) => {
+            match (plain_string(l), plain_string(r)) {
+                (Some(l), Some(r)) => Some(l == r),
+                _ => None,
+            }
+        }
+        (&Array(ref l), &Array(ref r)) => array_eq(l, r),
+
+        (&Object(_), _) | (_, &Object(_)) |
+        (&Simd(..), _) | (_, &Simd(..)) |
+        (&SimdSplat(..), _) | (_, &SimdSplat(..)) => None,
+
+        // Different kinds of scalars and arrays are never equal
+        _ => Some(false),
+    }
+}
+
+fn array_eq(lhs: &ArrayLiteral, rhs: &ArrayLiteral) -> Option<bool> {
+    match (lhs, rhs) {
+        (&ArrayLiteral::List(ref l), &ArrayLiteral::List(ref r)) => {
+            if l.len() != r.len() {
+                return Some(false);
+            }
+            all_eq(l.iter().zip(r.iter()))
+        }
+        (&ArrayLiteral::List(ref list), &ArrayLiteral::Splat(ref value, ref count)) |
+        (&ArrayLiteral::Splat(ref value, ref count), &ArrayLiteral::List(ref list)) => {
+            match **count {
+                Expression::Literal(Literal::Integer(n)) if n as u64 != list.len() as u64 => {
+                    return Some(false)
+                }
+                Expression::Literal(Literal::Integer(_)) => (),
+                _ => return None,
+            }
+            all_eq(list.iter().map(|e| (e, &**value)))
+        }
+        (&ArrayLiteral::Splat(ref lv, ref lc), &ArrayLiteral::Splat(ref rv, ref rc)) => {
+            match (&**lc, &**rc) {
+                (&Expression::Literal(Literal::Integer(l)),
+                 &Expression::Literal(Literal::Integer(r))) => {
+                    if l != r {
+                        Some(false)
+                    } else if l <= 0 {
+                        Some(true)
+                    } else {
+                        expr_eq(lv, rv)
+                    }
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+fn all_eq<'a, I>(pairs: I) -> Option<bool>
+    where I: Iterator<Item = (&'a Expression, &'a Expression)>
+{
+    for (l, r) in pairs {
+        match expr_eq(l, r) {
+            Some(true) => (),
+            other => return other,
+        }
+    }
+    Some(true)
+}
+
+// Every SIMD type in `vm::value::simd` is four lanes wide
+const SIMD_LANES: usize = 4;
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum LaneType {
+    F32,
+    I32,
+    U32,
+}
+
+fn lane_type(lanes: &[Expression], ty: &Option<ast::Identifier>) -> Option<LaneType> {
+    match ty.as_ref().map(|s| &s[..]) {
+        Some("f32") => return Some(LaneType::F32),
+        Some("i32") => return Some(LaneType::I32),
+        Some("u32") => return Some(LaneType::U32),
+        Some(_) => return None,
+        None => (),
+    }
+
+    // Otherwise lanes with a suffix decide it, and without one any float
+    // lane makes it an f32 vector
+    let mut suffixed = None;
+    let mut ty = LaneType::I32;
+    for lane in lanes {
+        match *lane {
+            Expression::Literal(Literal::Integer(_)) => (),
+            Expression::Literal(Literal::Float(_)) => ty = LaneType::F32,
+            Expression::Literal(Literal::Suffixed(_, suffix)) => {
+                let lane_ty = match suffix {
+                    NumSuffix::F32 => LaneType::F32,
+                    NumSuffix::I32 => LaneType::I32,
+                    NumSuffix::U32 => LaneType::U32,
+                    NumSuffix::I64 | NumSuffix::F64 => return None,
+                };
+                if suffixed.is_some() && suffixed != Some(lane_ty) {
+                    return None;
+                }
+                suffixed = Some(lane_ty);
+            }
+            _ => return None,
+        }
+    }
+    Some(suffixed.unwrap_or(ty))
+}
+
+impl LaneType {
+    fn name(self) -> &'static str {
+        match self {
+            LaneType::F32 => "f32",
+            LaneType::I32 => "i32",
+            LaneType::U32 => "u32",
+        }
+    }
+}
+
+fn has_suffix(lanes: &[Expression]) -> bool {
+    lanes.iter().any(|lane| match *lane {
+        Expression::Literal(Literal::Suffixed(..)) => true,
+        _ => false,
+    })
+}
+
+fn expand_simd_splat(value: &Expression, ty: &Option<ast::Identifier>) -> Option<Expression> {
+    match *value {
+        Expression::Literal(Literal::Integer(_)) |
+        Expression::Literal(Literal::Float(_)) |
+        Expression::Literal(Literal::Suffixed(..)) => {
+            let lanes = vec![value.clone(); SIMD_LANES];
+            Some(Expression::Literal(Literal::Simd(lanes, ty.clone())))
+        }
+        _ => None,
+    }
+}
+
+/// Lane-wise arithmetic on two constant vectors of the same type. Integer
+/// lanes wrap, like the hardware instructions the runtime uses.
+fn simplify_simd(lhs: &Literal, op: BinOp, rhs: &Literal) -> Option<Expression> {
+    let (l, lty, r, rty) = match (lhs, rhs) {
+        (&Literal::Simd(ref l, ref lty), &Literal::Simd(ref r, ref rty)) => (l, lty, r, rty),
         _ => return None,
-    }))
+    };
+
+    let ty = lane_type(l, lty);
+    if ty.is_none() || ty != lane_type(r, rty) || l.len() != r.len() {
+        return None;
+    }
+
+    let mut lanes = Vec::with_capacity(l.len());
+    for (l, r) in l.iter().zip(r.iter()) {
+        let lane = match ty.unwrap() {
+            LaneType::F32 => {
+                let (l, r) = match (lane_f32(l), lane_f32(r)) {
+                    (Some(l), Some(r)) => (l, r),
+                    _ => return None,
+                };
+                match ops::lane_f32(l, op, r) {
+                    Some(f) => Literal::Float(f as f64),
+                    None => return None,
+                }
+            }
+            LaneType::I32 => {
+                let (l, r) = match (lane_int(l), lane_int(r)) {
+                    (Some(l), Some(r)) if fits_i32(l) && fits_i32(r) => (l as i32, r as i32),
+                    _ => return None,
+                };
+                match ops::lane_i32(l, op, r) {
+                    Some(i) => Literal::Integer(i as i64),
+                    None => return None,
+                }
+            }
+            LaneType::U32 => {
+                let (l, r) = match (lane_int(l), lane_int(r)) {
+                    (Some(l), Some(r)) if fits_u32(l) && fits_u32(r) => (l as u32, r as u32),
+                    _ => return None,
+                };
+                match ops::lane_u32(l, op, r) {
+                    Some(u) => Literal::Integer(u as i64),
+                    None => return None,
+                }
+            }
+        };
+        lanes.push(Expression::Literal(lane));
+    }
+
+    // The folded lanes have no suffixes, so name the type they picked
+    let ty = match *lty {
+        None if has_suffix(l) || has_suffix(r) => Some(ty.unwrap().name().into()),
+        ref lty => lty.clone(),
+    };
+    Some(Expression::Literal(Literal::Simd(lanes, ty)))
 }
 
-fn fmod(x: f64, y: f64) -> f64 {
-    x - y * (x / y).trunc()
+fn lane_f32(lane: &Expression) -> Option<f32> {
+    match *lane {
+        Expression::Literal(ref lit) => {
+            match *unsuffixed(lit) {
+                Literal::Integer(i) => Some(i as f32),
+                Literal::Float(f) => Some(f as f32),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
 }
 
-fn frem(x: f64, y: f64) -> f64 {
-    x - y * (x / y).round()
+fn lane_int(lane: &Expression) -> Option<i64> {
+    match *lane {
+        Expression::Literal(ref lit) => {
+            match *unsuffixed(lit) {
+                Literal::Integer(i) => Some(i),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn fits_i32(i: i64) -> bool {
+    i >= ::std::i32::MIN as i64 && i <= ::std::i32::MAX as i64
+}
+
+fn fits_u32(i: i64) -> bool {
+    i >= 0 && i <= ::std::u32::MAX as i64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::parse_Module;
+    use serde_json;
+    use std::fs::File;
+    use std::io::Read;
+
+    fn read(path: &str) -> String {
+        let mut text = String::new();
+        File::open(path).unwrap().read_to_string(&mut text).unwrap();
+        text
+    }
+
+    /// Each script in the corpus, parsed, matches its `.before.json`, and
+    /// folded, matches its `.after.json`. Regenerate them with `sealc
+    /// --no-fold` and `sealc` when a change to the output is intended.
+    #[test]
+    fn folding_corpus() {
+        for name in &["example", "folding"] {
+            let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts");
+            let source = read(&format!("{}/{}.seal", dir, name));
+            let mut ast = parse_Module(Lexer::new(&source)).unwrap();
+
+            let before: serde_json::Value =
+                serde_json::from_str(&read(&format!("{}/folding/{}.before.json", dir, name)))
+                    .unwrap();
+            assert!(serde_json::to_value(&ast).unwrap() == before,
+                    "{}.seal doesn't parse to {}.before.json",
+                    name,
+                    name);
+
+            fold_module(&mut ast);
+            let after: serde_json::Value =
+                serde_json::from_str(&read(&format!("{}/folding/{}.after.json", dir, name)))
+                    .unwrap();
+            assert!(serde_json::to_value(&ast).unwrap() == after,
+                    "{}.seal doesn't fold to {}.after.json",
+                    name,
+                    name);
+        }
+    }
 }
@@ -0,0 +1,495 @@
+//! Warnings for code that is valid but almost certainly a mistake.
+//!
+//! Every lint has a stable name, which is what `@allow(name)` on an item
+//! refers to. An `@allow` covers the item it is attached to, including every
+//! method of an `impl`.
+//!
+//! Assignment is a statement, so `if x = nil` doesn't parse, and only calls
+//! can stand alone, so neither does `x == nil;`. What `nil_comparison`
+//! catches instead is `x = nil;` right before a branch on `x`, which then
+//! always goes the same way.
+//!
+//! `seallint` runs the lints over a set of files.
+
+use ast::*;
+use ast::resolve::{BindingKind, Resolution};
+use ast::visit::{self, Visitor};
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    BreakOutsideLoop,
+    UnknownLabel,
+    SelfOutsideMethod,
+    TryInInfallible,
+    AssignToConst,
+    DuplicateKey,
+    NilComparison,
+    UnknownLint,
+}
+
+pub const ALL_LINTS: &'static [Lint] = &[Lint::UnusedVariable,
+                                         Lint::UnusedParameter,
+                                         Lint::BreakOutsideLoop,
+                                         Lint::UnknownLabel,
+                                         Lint::SelfOutsideMethod,
+                                         Lint::TryInInfallible,
+                                         Lint::AssignToConst,
+                                         Lint::DuplicateKey,
+                                         Lint::NilComparison,
+                                         Lint::UnknownLint];
+
+impl Lint {
+    /// The name used in `@allow`. These never change once released.
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedParameter => "unused_parameter",
+            Lint::BreakOutsideLoop => "break_outside_loop",
+            Lint::UnknownLabel => "unknown_label",
+            Lint::SelfOutsideMethod => "self_outside_method",
+            Lint::TryInInfallible => "try_in_infallible",
+            Lint::AssignToConst => "assign_to_const",
+            Lint::DuplicateKey => "duplicate_key",
+            Lint::NilComparison => "nil_comparison",
+            Lint::UnknownLint => "unknown_lint",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        ALL_LINTS.iter().cloned().find(|lint| lint.name() == name)
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.name())
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub location: Location,
+    pub lint: Lint,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} [{}]", self.message, self.lint)
+    }
+}
+
+/// Lints `root`, which `resolution` must have come from.
+pub fn lint_module(root: &Module, resolution: &Resolution) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        resolution: resolution,
+        allowed: vec![],
+        functions: vec![],
+        loops: vec![],
+        diagnostics: vec![],
+    };
+    linter.visit_module(root);
+    linter.diagnostics.sort_by_key(|diag| diag.location.index);
+    linter.diagnostics
+}
+
+struct FunctionContext {
+    is_member: bool,
+    can_error: bool,
+    /// The labels of the loops enclosing the current statement. A loop
+    /// without a label pushes `None`.
+    loops: Vec<Option<Label>>,
+}
+
+struct Linter<'a> {
+    resolution: &'a Resolution,
+    allowed: Vec<Lint>,
+    functions: Vec<FunctionContext>,
+    // Loops outside of any function, in const initializers
+    loops: Vec<Option<Label>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, location: Location, lint: Lint, message: String) {
+        if self.allowed.contains(&lint) {
+            return;
+        }
+
+        self.diagnostics.push(Diagnostic {
+            location: location,
+            lint: lint,
+            message: message,
+        });
+    }
+
+    /// Adds the lints named by `@allow` attributes, returning how many
+    /// entries to pop off `allowed` afterwards.
+    fn allow(&mut self, attrs: &[Attribute], location: Location) -> usize {
+        let before = self.allowed.len();
+        for attr in attrs {
+            let names = match *attr {
+                Attribute::Named(ref name, ref args) if name == "allow" => args,
+                _ => continue,
+            };
+
+            for name in names {
+                let lint = match *name {
+                    Attribute::Identifier(ref name) => Lint::from_name(name).ok_or(name),
+                    _ => continue,
+                };
+
+                match lint {
+                    Ok(lint) => self.allowed.push(lint),
+                    Err(name) => {
+                        let message = format!("there is no lint called `{}`", name);
+                        self.report(location, Lint::UnknownLint, message);
+                    }
+                }
+            }
+        }
+        self.allowed.len() - before
+    }
+
+    fn loops(&mut self) -> &mut Vec<Option<Label>> {
+        match self.functions.last_mut() {
+            Some(func) => &mut func.loops,
+            None => &mut self.loops,
+        }
+    }
+
+    fn enter_loop(&mut self, label: &Option<Label>) {
+        self.loops().push(label.clone());
+    }
+
+    fn exit_loop(&mut self) {
+        self.loops().pop();
+    }
+
+    fn check_jump(&mut self, keyword: &str, label: &Option<Label>, location: Location) {
+        let (in_loop, known) = {
+            let loops = self.loops();
+            let known = match *label {
+                Some(ref label) => loops.iter().any(|l| l.as_ref() == Some(label)),
+                None => true,
+            };
+            (!loops.is_empty(), known)
+        };
+
+        if !in_loop {
+            let message = format!("`{}` outside of a loop", keyword);
+            self.report(location, Lint::BreakOutsideLoop, message);
+        } else if !known {
+            let message = format!("no enclosing loop is labeled `'{}`",
+                                  label.as_ref().unwrap());
+            self.report(location, Lint::UnknownLabel, message);
+        }
+    }
+
+    fn check_unused(&mut self, name: &str, declaration: Location, lint: Lint) {
+        if name == "self" || name.starts_with('_') {
+            return;
+        }
+
+        if self.resolution.references(name, declaration) == 0 {
+            let what = match lint {
+                Lint::UnusedParameter => "parameter",
+                _ => "variable",
+            };
+            let message = format!("unused {} `{}`", what, name);
+            self.report(declaration, lint, message);
+        }
+    }
+
+    fn check_nil_comparison(&mut self, stmnt: &Statement, next: Option<&Statement>) {
+        let (name, loc) = match *stmnt {
+            Statement::Assignment(Expression::Identifier(ref name, loc),
+                                  ref extra,
+                                  AssignOp::Assign,
+                                  Expression::Nil) if extra.is_empty() => (name, loc),
+            _ => return,
+        };
+        let condition = match next {
+            Some(&Statement::IfElse(ref branch)) => &*branch.condition,
+            Some(&Statement::WhileLoop(ref l)) => &l.condition,
+            _ => return,
+        };
+
+        if tests_name(condition, name) {
+            let message = format!("`{}` is set to nil just before it's tested; did you mean \
+                                   `{} == nil`?",
+                                  name,
+                                  name);
+            self.report(loc, Lint::NilComparison, message);
+        }
+    }
+
+    fn enter_function(&mut self,
+                      is_member: bool,
+                      can_error: bool,
+                      params: &[Identifier],
+                      locations: &[Location]) {
+        for (param, &loc) in params.iter().zip(locations) {
+            self.check_unused(param, loc, Lint::UnusedParameter);
+        }
+
+        self.functions.push(FunctionContext {
+            is_member: is_member,
+            can_error: can_error,
+            loops: vec![],
+        });
+    }
+}
+
+/// Whether a condition is `name`, its negation, or compares it with `nil`.
+fn tests_name(condition: &Expression, name: &str) -> bool {
+    match *condition {
+        Expression::Identifier(ref id, _) => id == name,
+        Expression::Not(ref inner) => tests_name(inner, name),
+        Expression::BinaryOp(ref lhs, BinOp::Equal, ref rhs, _) |
+        Expression::BinaryOp(ref lhs, BinOp::NotEqual, ref rhs, _) => {
+            match (&**lhs, &**rhs) {
+                (&Expression::Identifier(ref id, _), &Expression::Nil) |
+                (&Expression::Nil, &Expression::Identifier(ref id, _)) => id == name,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+impl<'a> Visitor for Linter<'a> {
+    fn visit_item(&mut self, item: &Item) {
+        let allowed = match *item {
+            Item::TypeDecl(ref attrs, ref decl) => self.allow(attrs, decl.location),
+            Item::Const(ref attrs, _, _, loc) => self.allow(attrs, loc),
+            Item::TypeImpl(ref attrs, ref imp) => self.allow(attrs, imp.location),
+            Item::Function(ref attrs, ref func) => self.allow(attrs, func.location),
+            Item::Trait(ref attrs, ref tr) => self.allow(attrs, tr.location),
+            _ => 0,
+        };
+
+        visit::walk_item(self, item);
+
+        let len = self.allowed.len() - allowed;
+        self.allowed.truncate(len);
+    }
+
+    fn visit_function(&mut self, func: &Function) {
+        self.enter_function(func.is_member,
+                            func.can_error,
+                            &func.parameters,
+                            &func.parameter_locations);
+        visit::walk_function(self, func);
+        self.functions.pop();
+    }
+
+    fn visit_trait_function(&mut self, func: &TraitFunction) {
+        // A signature without a default body has nothing to use its
+        // parameters
+        if func.body.is_some() {
+            self.enter_function(func.is_member,
+                                func.can_error,
+                                &func.parameters,
+                                &func.parameter_locations);
+            visit::walk_trait_function(self, func);
+            self.functions.pop();
+        }
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        self.enter_function(lambda.is_member,
+                            lambda.can_error,
+                            &lambda.parameters,
+                            &lambda.parameter_locations);
+        visit::walk_lambda(self, lambda);
+        self.functions.pop();
+    }
+
+    fn visit_loop(&mut self, l: &Loop) {
+        self.enter_loop(&l.label);
+        visit::walk_loop(self, l);
+        self.exit_loop();
+    }
+
+    fn visit_for_loop(&mut self, l: &ForLoop) {
+        for (name, &loc) in l.bindings.iter().zip(&l.binding_locations) {
+            self.check_unused(name, loc, Lint::UnusedVariable);
+        }
+
+        self.visit_expression(&l.iterator);
+        self.enter_loop(&l.label);
+        self.visit_block(&l.block);
+        self.exit_loop();
+    }
+
+    fn visit_while_loop(&mut self, l: &WhileLoop) {
+        self.visit_expression(&l.condition);
+        self.enter_loop(&l.label);
+        self.visit_block(&l.block);
+        self.exit_loop();
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        for (i, stmnt) in block.statements.iter().enumerate() {
+            self.check_nil_comparison(stmnt, block.statements.get(i + 1));
+        }
+        visit::walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmnt: &Statement) {
+        match *stmnt {
+            Statement::Declaration(ref first, ref rest, _, _, loc) => {
+                self.check_unused(first, loc, Lint::UnusedVariable);
+                for &(ref name, loc) in rest {
+                    self.check_unused(name, loc, Lint::UnusedVariable);
+                }
+            }
+            Statement::Break(ref label, loc) => self.check_jump("break", label, loc),
+            Statement::Continue(ref label, loc) => self.check_jump("continue", label, loc),
+            Statement::Assignment(ref lhs, ref extra, _, _) => {
+                for target in Some(lhs).into_iter().chain(extra) {
+                    if let Expression::Identifier(ref name, loc) = *target {
+                        match self.resolution.binding(loc) {
+                            Some(binding) if binding.kind == BindingKind::Const => {
+                                let message = format!("assignment to const `{}`", name);
+                                self.report(loc, Lint::AssignToConst, message);
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        visit::walk_statement(self, stmnt);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Identifier(ref name, loc) if name == "self" => {
+                if !self.functions.iter().any(|func| func.is_member) {
+                    let message = "`self` used outside of a method".into();
+                    self.report(loc, Lint::SelfOutsideMethod, message);
+                }
+            }
+            Expression::Try(_, loc) => {
+                let fallible = self.functions.last().map(|func| func.can_error).unwrap_or(false);
+                if !fallible {
+                    let message = "`?` in a function that isn't declared with `?`".into();
+                    self.report(loc, Lint::TryInInfallible, message);
+                }
+            }
+            _ => (),
+        }
+
+        visit::walk_expression(self, expr);
+    }
+
+    fn visit_object_literal(&mut self, obj: &ObjectLiteral) {
+        for (i, field) in obj.iter().enumerate() {
+            if obj[..i].iter().any(|prev| prev.key == field.key) {
+                let message = format!("`{}` is set more than once", field.key);
+                self.report(field.location, Lint::DuplicateKey, message);
+            }
+        }
+
+        visit::walk_object_literal(self, obj);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::resolve::resolve_module;
+    use lexer::Lexer;
+    use parser::parse_Module;
+
+    fn lint(source: &str) -> Vec<(Lint, usize, usize)> {
+        let ast = parse_Module(Lexer::new(source)).unwrap();
+        let resolution = resolve_module(&ast, &[]);
+        lint_module(&ast, &resolution)
+            .into_iter()
+            .map(|diag| (diag.lint, diag.location.line, diag.location.column))
+            .collect()
+    }
+
+    #[test]
+    fn unused_parameters_point_at_the_parameter() {
+        assert_eq!(lint("fn f(a,\n     b) {\n    return a;\n}"),
+                   vec![(Lint::UnusedParameter, 2, 6)]);
+    }
+
+    #[test]
+    fn writes_are_not_uses() {
+        assert_eq!(lint("fn f() {\n    let x, y = 1;\n    x = y;\n}"),
+                   vec![(Lint::UnusedVariable, 2, 9)]);
+    }
+
+    #[test]
+    fn allow_silences_a_lint() {
+        assert_eq!(lint("@allow(unused_parameter)\nfn f(a) {}"), vec![]);
+    }
+
+    #[test]
+    fn break_outside_loop() {
+        assert_eq!(lint("fn f() {\n    break;\n    loop { continue; }\n    continue;\n}"),
+                   vec![(Lint::BreakOutsideLoop, 2, 5), (Lint::BreakOutsideLoop, 4, 5)]);
+        // A lambda's body isn't inside the loop it's written in
+        assert_eq!(lint("fn f() {\n    loop {\n        let g = fn() { break; };\n        g();\n    \
+                         }\n}"),
+                   vec![(Lint::BreakOutsideLoop, 3, 24)]);
+    }
+
+    #[test]
+    fn unknown_label() {
+        assert_eq!(lint("fn f() {\n    'outer: loop {\n        loop { break 'outer; }\n        \
+                         continue 'inner;\n    }\n}"),
+                   vec![(Lint::UnknownLabel, 4, 9)]);
+    }
+
+    #[test]
+    fn self_outside_method() {
+        assert_eq!(lint("type T;\nimpl T {\n    fn get(self) { return self; }\n    \
+                         fn make() { return self; }\n}"),
+                   vec![(Lint::SelfOutsideMethod, 4, 24)]);
+    }
+
+    #[test]
+    fn try_in_infallible() {
+        assert_eq!(lint("fn f(x)? {\n    return x?;\n}\nfn g(x) {\n    return x?;\n}"),
+                   vec![(Lint::TryInInfallible, 5, 13)]);
+    }
+
+    #[test]
+    fn assign_to_const() {
+        assert_eq!(lint("const N = 1;\nfn f() {\n    N = 2;\n    let N = 3;\n    N = 4;\n    \
+                         return N;\n}"),
+                   vec![(Lint::AssignToConst, 3, 5)]);
+    }
+
+    #[test]
+    fn duplicate_key() {
+        assert_eq!(lint("fn f() {\n    return { a: 1, b: 2, a: 3 };\n}"),
+                   vec![(Lint::DuplicateKey, 2, 26)]);
+    }
+
+    #[test]
+    fn nil_comparison() {
+        assert_eq!(lint("fn f(x) {\n    x = nil;\n    if x == nil { return 1; }\n    x = nil;\n    \
+                         return x;\n}"),
+                   vec![(Lint::NilComparison, 2, 5)]);
+        assert_eq!(lint("fn f(x) {\n    x = nil;\n    while !x { return; }\n}"),
+                   vec![(Lint::NilComparison, 2, 5)]);
+    }
+
+    #[test]
+    fn unknown_lint() {
+        assert_eq!(lint("@allow(unused_parameter, unused_paramter)\nfn f(a) {}"),
+                   vec![(Lint::UnknownLint, 2, 4)]);
+    }
+}
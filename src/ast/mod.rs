@@ -1,32 +1,41 @@
-use std::collections::BTreeMap;
+pub use lexer::Location;
+use lexer::NumberError;
+use num::{BigInt, ToPrimitive};
 
+pub mod cfg;
 pub mod constant_folding;
+pub mod lint;
+pub mod optimize;
+pub mod printer;
+pub mod resolve;
+pub mod typeck;
+pub mod visit;
 
 pub type Identifier = String;
 pub type Label = String;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Module {
     Root { items: Vec<Item> },
-    Inline { name: Identifier, items: Vec<Item> },
-    Extern { name: Identifier },
+    Inline { name: Identifier, location: Location, items: Vec<Item> },
+    Extern { name: Identifier, location: Location },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Item {
     Use(Expression),
-    Extern(Identifier),
+    Extern(Identifier, Location),
     Module(Module),
-    TypeDecl(Vec<Attribute>, Identifier),
+    TypeDecl(Vec<Attribute>, TypeDecl),
     TypeImpl(Vec<Attribute>, TypeImpl),
     Function(Vec<Attribute>, Function),
     Trait(Vec<Attribute>, Trait),
-    Const(Vec<Attribute>, Identifier, Expression),
+    Const(Vec<Attribute>, Identifier, Expression, Location),
     DocComment(String),
     ModuleDocComment(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Attribute {
     Identifier(Identifier),
     Named(Identifier, Vec<Attribute>),
@@ -34,136 +43,326 @@ pub enum Attribute {
     Map(Box<Attribute>, Box<Attribute>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Function {
     pub name: Identifier,
+    pub location: Location,
     pub parameters: Vec<Identifier>,
+    /// Where each parameter is named
+    pub parameter_locations: Vec<Location>,
+    /// The annotation of each parameter, if it has one
+    pub parameter_types: Vec<Option<TypeExpr>>,
+    pub return_type: Option<TypeExpr>,
     pub can_error: bool,
     pub is_member: bool,
     pub body: Block,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TypeDecl {
+    pub name: Identifier,
+    pub location: Location,
+    /// `None` for `type Name;`, whose objects may have any fields
+    pub fields: Option<Vec<FieldDecl>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FieldDecl {
+    pub name: Identifier,
+    pub location: Location,
+    pub default: Option<Expression>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TypeImpl {
     pub name: Identifier,
+    pub location: Location,
     pub interface: Option<Identifier>,
+    pub constants: Vec<AssocConst>,
     pub methods: Vec<Function>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Trait {
     pub name: Identifier,
+    pub location: Location,
+    /// The traits every implementor of this one must also implement
+    pub supertraits: Vec<Identifier>,
+    pub constants: Vec<AssocConst>,
     pub methods: Vec<TraitFunction>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A `const` in an `impl` or `trait`. Only a trait may leave out the value,
+/// which every implementation then has to provide.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AssocConst {
+    pub name: Identifier,
+    pub location: Location,
+    pub value: Option<Expression>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TraitFunction {
     pub name: Identifier,
+    pub location: Location,
     pub parameters: Vec<Identifier>,
+    pub parameter_locations: Vec<Location>,
+    pub parameter_types: Vec<Option<TypeExpr>>,
+    pub return_type: Option<TypeExpr>,
     pub can_error: bool,
     pub is_member: bool,
+    /// The default implementation, used by impls that leave this out
+    pub body: Option<Block>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Block {
     pub statements: Vec<Statement>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Statement {
     Use(Expression),
     Expression(Expression),
-    Declaration(Identifier, Vec<Identifier>, Option<Expression>),
+    /// The location is that of the first name, and each other name has its
+    /// own. Only a lone name can be annotated.
+    Declaration(Identifier, Vec<(Identifier, Location)>, Option<TypeExpr>, Option<Expression>, Location),
     Assignment(Expression, Vec<Expression>, AssignOp, Expression),
     IfElse(IfElse),
     Loop(Loop),
     ForLoop(ForLoop),
     WhileLoop(WhileLoop),
-    Return(Vec<Expression>),
-    Throw(Expression),
-    Break(Option<Label>),
-    Continue(Option<Label>),
+    Return(Vec<Expression>, Location),
+    Throw(Expression, Location),
+    Break(Option<Label>, Location),
+    Continue(Option<Label>, Location),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IfElse {
     pub condition: Box<Expression>,
     pub if_block: Box<Block>,
     pub else_block: Option<Box<Block>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Loop {
     pub label: Option<Label>,
     pub block: Block,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ForLoop {
     pub label: Option<Label>,
     pub bindings: Vec<Identifier>,
+    /// Where each binding is named
+    pub binding_locations: Vec<Location>,
+    /// The location of the `for`
+    pub location: Location,
     pub iterator: Expression,
     pub block: Block,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WhileLoop {
     pub label: Option<Label>,
     pub condition: Expression,
     pub block: Block,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Expression {
     Nil,
     Literal(Literal),
-    Identifier(Identifier),
+    Identifier(Identifier, Location),
     MemberAccess(Box<Expression>, Identifier),
     IndexAccess(Box<Expression>, Vec<Expression>),
     FunctionCall(Box<Expression>, Vec<Expression>),
-    ObjectConstructor(Identifier, ObjectLiteral),
-    BinaryOp(Box<Expression>, BinOp, Box<Expression>),
+    ObjectConstructor(Identifier, ObjectLiteral, Location),
+    /// The location is that of the operator
+    BinaryOp(Box<Expression>, BinOp, Box<Expression>, Location),
     Negate(Box<Expression>),
     Not(Box<Expression>),
-    Try(Box<Expression>),
+    /// The location is that of the `?`
+    Try(Box<Expression>, Location),
     Lambda(Box<Lambda>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Lambda {
+    pub location: Location,
     pub parameters: Vec<Identifier>,
+    pub parameter_locations: Vec<Location>,
+    pub parameter_types: Vec<Option<TypeExpr>>,
     pub can_error: bool,
     pub is_member: bool,
     pub body: Block,
 }
 
-pub fn lambda(mut params: Vec<Identifier>, err: Option<&str>, block: Block) -> Expression {
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TypeExpr {
+    /// A builtin like `int`, or the name of a `type` or trait
+    Named(Identifier, Location),
+    /// `[T]`, an array of `T`
+    Array(Box<TypeExpr>),
+}
+
+pub type Param = (Identifier, Location, Option<TypeExpr>);
+
+/// Splits parsed parameters into names, locations and annotations, taking
+/// off a leading `self`. The flag is whether there was one.
+pub fn split_params(params: Vec<Param>)
+                    -> (Vec<Identifier>, Vec<Location>, Vec<Option<TypeExpr>>, bool) {
     let mut is_member = false;
-    if let Some("self") = params.first().map(|s| &s[..]) {
-        params.remove(0);
-        is_member = true;
+    let mut names = Vec::with_capacity(params.len());
+    let mut locations = Vec::with_capacity(params.len());
+    let mut types = Vec::with_capacity(params.len());
+    for (i, (name, loc, ty)) in params.into_iter().enumerate() {
+        if i == 0 && name == "self" {
+            is_member = true;
+            continue;
+        }
+        names.push(name);
+        locations.push(loc);
+        types.push(ty);
+    }
+    (names, locations, types, is_member)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NumSuffix {
+    I32,
+    U32,
+    I64,
+    F32,
+    F64,
+}
+
+impl NumSuffix {
+    pub fn from_str(s: &str) -> Option<NumSuffix> {
+        Some(match s {
+            "i32" => NumSuffix::I32,
+            "u32" => NumSuffix::U32,
+            "i64" => NumSuffix::I64,
+            "f32" => NumSuffix::F32,
+            "f64" => NumSuffix::F64,
+            _ => return None,
+        })
     }
 
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NumSuffix::I32 => "i32",
+            NumSuffix::U32 => "u32",
+            NumSuffix::I64 => "i64",
+            NumSuffix::F32 => "f32",
+            NumSuffix::F64 => "f64",
+        }
+    }
+
+    pub fn is_float(self) -> bool {
+        match self {
+            NumSuffix::F32 | NumSuffix::F64 => true,
+            _ => false,
+        }
+    }
+}
+
+/// Splits a number literal into its digits, without `_` separators, and
+/// its suffix.
+fn split_number(text: &str, radix: u32) -> Result<(String, Option<NumSuffix>), NumberError> {
+    let end = text.find(|c| c == 'i' || c == 'u' || (c == 'f' && radix != 16))
+        .unwrap_or(text.len());
+    let digits = text[..end].chars().filter(|&c| c != '_').collect();
+    let suffix = match &text[end..] {
+        "" => None,
+        s => Some(NumSuffix::from_str(s).ok_or_else(|| NumberError::UnknownSuffix(s.into()))?),
+    };
+    Ok((digits, suffix))
+}
+
+/// An integer literal's value, from its text after any radix prefix and
+/// whether a `-` came before it. It's a `BigInt` only if it has to be.
+pub fn integer_literal(text: &str, radix: u32, negative: bool) -> Result<Literal, NumberError> {
+    let text = if negative { format!("-{}", text) } else { text.to_string() };
+    let (digits, suffix) = split_number(&text, radix)?;
+    let big = BigInt::parse_bytes(digits.as_bytes(), radix)
+        .ok_or_else(|| NumberError::Malformed(text.clone()))?;
+
+    let suffix = match suffix {
+        Some(suffix) => suffix,
+        None => {
+            return Ok(match big.to_i64() {
+                Some(i) => Literal::Integer(i),
+                None => Literal::BigInt(big.to_string()),
+            })
+        }
+    };
+
+    let value = match suffix {
+        NumSuffix::I32 => big.to_i32().map(|i| Literal::Integer(i as i64)),
+        NumSuffix::U32 => big.to_u32().map(|i| Literal::Integer(i as i64)),
+        NumSuffix::I64 => big.to_i64().map(Literal::Integer),
+        NumSuffix::F32 => big.to_f32().map(|f| Literal::Float(f as f64)),
+        NumSuffix::F64 => big.to_f64().map(Literal::Float),
+    };
+
+    match value {
+        Some(Literal::Float(f)) if !f.is_finite() => Err(NumberError::OutOfRange(text)),
+        Some(value) => Ok(Literal::Suffixed(Box::new(value), suffix)),
+        None => Err(NumberError::OutOfRange(text)),
+    }
+}
+
+/// A float literal's value.
+pub fn float_literal(text: &str) -> Result<Literal, NumberError> {
+    let (digits, suffix) = split_number(text, 10)?;
+    let f: f64 = digits.parse().map_err(|_| NumberError::Malformed(text.into()))?;
+
+    let value = match suffix {
+        None => return Ok(Literal::Float(f)),
+        Some(NumSuffix::F32) => (f as f32) as f64,
+        Some(NumSuffix::F64) => f,
+        Some(suffix) => return Err(NumberError::FloatSuffix(suffix.as_str().into())),
+    };
+
+    if value.is_finite() {
+        Ok(Literal::Suffixed(Box::new(Literal::Float(value)), suffix.unwrap()))
+    } else {
+        Err(NumberError::OutOfRange(text.into()))
+    }
+}
+
+pub fn lambda(loc: Location, params: Vec<Param>, err: Option<&str>, block: Block) -> Expression {
+    let (params, locations, types, is_member) = split_params(params);
+
     Expression::Lambda(Box::new(Lambda {
+        location: loc,
         parameters: params,
+        parameter_locations: locations,
+        parameter_types: types,
         can_error: err.is_some(),
         is_member: is_member,
         body: block,
     }))
 }
 
-pub fn expr_lambda(mut params: Vec<Identifier>, err: Option<&str>, expr: Expression) -> Expression {
-    let mut is_member = false;
-    if let Some("self") = params.first().map(|s| &s[..]) {
-        params.remove(0);
-        is_member = true;
-    }
+pub fn expr_lambda(loc: Location,
+                   params: Vec<Param>,
+                   err: Option<&str>,
+                   ret: Location,
+                   expr: Expression)
+                   -> Expression {
+    let (params, locations, types, is_member) = split_params(params);
 
     Expression::Lambda(Box::new(Lambda {
+        location: loc,
         parameters: params,
+        parameter_locations: locations,
+        parameter_types: types,
         can_error: err.is_some(),
         is_member: is_member,
-        body: Block { statements: vec![Statement::Return(vec![expr])] },
+        body: Block { statements: vec![Statement::Return(vec![expr], ret)] },
     }))
 }
 
@@ -228,21 +427,37 @@ pub enum AssignOp {
     Mod,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Literal {
     Integer(i64),
+    /// An integer that doesn't fit in an `i64`, in decimal
+    BigInt(String),
+    /// A number written with a type suffix, like `10u32`. The literal has
+    /// already been checked against the type's range and, for `f32`,
+    /// rounded to its precision.
+    Suffixed(Box<Literal>, NumSuffix),
     Float(f64),
     Bool(bool),
     String(String),
+    /// `:name`
+    Symbol(Identifier),
     Object(ObjectLiteral),
     Array(ArrayLiteral),
     Simd(Vec<Expression>, Option<Identifier>),
     SimdSplat(Box<Expression>, Option<Identifier>),
 }
 
-pub type ObjectLiteral = BTreeMap<String, Expression>;
+/// Fields in source order. A key may appear more than once; the last one wins.
+pub type ObjectLiteral = Vec<ObjectField>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ObjectField {
+    pub key: Identifier,
+    pub location: Location,
+    pub value: Expression,
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ArrayLiteral {
     List(Vec<Expression>),
     Splat(Box<Expression>, Box<Expression>),
@@ -0,0 +1,493 @@
+//! Constant propagation and dead code elimination.
+//!
+//! On top of `constant_folding`, this pass substitutes `const` items whose
+//! initializers fold to a literal, folds indexing into constant arrays,
+//! drops branches and loops whose condition is a constant `false`, and
+//! removes statements that follow a `return`, `throw`, `break` or `continue`.
+//!
+//! Consts are visible in the module that declares them and in its inline
+//! child modules, unless another item, `use`, parameter or `let` of the same
+//! name shadows them. This matches the scoping in `resolve`. Only scalars
+//! (numbers, booleans, strings and symbols) are substituted for a bare
+//! name; arrays are shared values, so a constant array is only ever
+//! replaced by an element when it is indexed.
+
+use ast::*;
+use ast::constant_folding;
+use ast::resolve::{item_binding, use_binding};
+use ast::visit::{self, VisitorMut};
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub enum Diagnostic {
+    Fold(constant_folding::Diagnostic),
+    /// Statements after the jump at this location can never run
+    Unreachable(Location),
+}
+
+pub fn optimize_module(root: &mut Module) -> Vec<Diagnostic> {
+    let mut optimizer = Optimizer {
+        scopes: vec![],
+        diagnostics: vec![],
+    };
+    optimizer.visit_module(root);
+    optimizer.diagnostics
+}
+
+#[derive(Clone, Debug)]
+enum Constant {
+    Scalar(Literal),
+    List(Vec<Literal>),
+    Splat(Literal, i64),
+}
+
+impl Constant {
+    fn from_expr(expr: &Expression) -> Option<Constant> {
+        let lit = match *expr {
+            Expression::Literal(ref lit) => lit,
+            _ => return None,
+        };
+
+        if is_scalar(lit) {
+            return Some(Constant::Scalar(lit.clone()));
+        }
+
+        match *lit {
+            Literal::Array(ArrayLiteral::List(ref exprs)) => {
+                let mut items = Vec::with_capacity(exprs.len());
+                for expr in exprs {
+                    match *expr {
+                        Expression::Literal(ref lit) if is_scalar(lit) => items.push(lit.clone()),
+                        _ => return None,
+                    }
+                }
+                Some(Constant::List(items))
+            }
+            Literal::Array(ArrayLiteral::Splat(ref value, ref count)) => {
+                match (&**value, &**count) {
+                    (&Expression::Literal(ref lit), &Expression::Literal(Literal::Integer(n)))
+                        if is_scalar(lit) && n >= 0 => Some(Constant::Splat(lit.clone(), n)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn index(&self, i: i64) -> Option<Literal> {
+        match *self {
+            Constant::List(ref items) if i >= 0 && (i as u64) < items.len() as u64 => {
+                Some(items[i as usize].clone())
+            }
+            Constant::Splat(ref lit, n) if i >= 0 && i < n => Some(lit.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn is_scalar(lit: &Literal) -> bool {
+    match *lit {
+        Literal::Integer(_) | Literal::BigInt(_) | Literal::Suffixed(..) | Literal::Float(_) |
+        Literal::Bool(_) | Literal::String(_) | Literal::Symbol(_) => true,
+        _ => false,
+    }
+}
+
+fn declares_names(block: &Block) -> bool {
+    block.statements.iter().any(|stmnt| match *stmnt {
+        Statement::Declaration(..) | Statement::Use(_) => true,
+        _ => false,
+    })
+}
+
+fn jump_location(stmnt: &Statement) -> Option<Location> {
+    match *stmnt {
+        Statement::Return(_, loc) |
+        Statement::Throw(_, loc) |
+        Statement::Break(_, loc) |
+        Statement::Continue(_, loc) => Some(loc),
+        _ => None,
+    }
+}
+
+struct Optimizer {
+    // `None` marks a name shadowed by a local binding
+    scopes: Vec<HashMap<Identifier, Option<Constant>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Optimizer {
+    fn lookup(&self, name: &str) -> Option<&Constant> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.get(name) {
+                return binding.as_ref();
+            }
+        }
+        None
+    }
+
+    fn bind(&mut self, name: &str, value: Option<Constant>) {
+        self.scopes.last_mut().unwrap().insert(name.into(), value);
+    }
+
+    fn bind_locals(&mut self, names: &[Identifier]) {
+        for name in names {
+            self.bind(name, None);
+        }
+    }
+
+    fn simplify(&mut self, expr: &mut Expression) {
+        if let Some(diag) = constant_folding::simplify(expr) {
+            self.diagnostics.push(Diagnostic::Fold(diag));
+        }
+    }
+
+    /// Visits an assignment target without replacing the target itself.
+    fn visit_target(&mut self, target: &mut Expression) {
+        match *target {
+            Expression::Identifier(..) => (),
+            Expression::MemberAccess(ref mut obj, _) => self.visit_target(obj),
+            Expression::IndexAccess(ref mut obj, ref mut index) => {
+                self.visit_target(obj);
+                for expr in index {
+                    self.visit_expression(expr);
+                }
+            }
+            _ => self.visit_expression(target),
+        }
+    }
+
+    /// Visits a statement in a block, returning the statements that replace
+    /// it once constant conditions are resolved.
+    fn optimize_statement(&mut self, mut stmnt: Statement) -> Vec<Statement> {
+        self.visit_statement(&mut stmnt);
+
+        let taken = match stmnt {
+            Statement::IfElse(IfElse { ref condition, ref mut if_block, ref mut else_block }) => {
+                match **condition {
+                    Expression::Literal(Literal::Bool(true)) => {
+                        Some(::std::mem::replace(&mut **if_block, Block { statements: vec![] }))
+                    }
+                    Expression::Literal(Literal::Bool(false)) => {
+                        match else_block.take() {
+                            Some(block) => Some(*block),
+                            None => return vec![],
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            Statement::WhileLoop(WhileLoop { condition: Expression::Literal(Literal::Bool(false)), .. }) => {
+                return vec![];
+            }
+            _ => None,
+        };
+
+        match taken {
+            // Splicing a block that declares names would change their scope,
+            // so keep it behind an `if true` instead
+            Some(block) if declares_names(&block) => {
+                vec![Statement::IfElse(IfElse {
+                    condition: Box::new(Expression::Literal(Literal::Bool(true))),
+                    if_block: Box::new(block),
+                    else_block: None,
+                })]
+            }
+            Some(block) => block.statements,
+            None => vec![stmnt],
+        }
+    }
+}
+
+impl VisitorMut for Optimizer {
+    fn visit_module(&mut self, module: &mut Module) {
+        let items = match *module {
+            Module::Root { ref mut items } |
+            Module::Inline { ref mut items, .. } => items,
+            Module::Extern { .. } => return,
+        };
+
+        self.scopes.push(HashMap::new());
+
+        for item in items.iter() {
+            match *item {
+                Item::Const(..) => (),
+                _ => {
+                    if let Some((name, _)) = item_binding(item) {
+                        self.bind(name, None);
+                    }
+                }
+            }
+        }
+
+        // Consts are bound up front so functions see those declared later
+        // in the module, but each initializer only sees the ones above it.
+        for item in items.iter_mut() {
+            if let Item::Const(_, ref name, ref mut expr, _) = *item {
+                self.visit_expression(expr);
+                let value = Constant::from_expr(expr);
+                self.bind(name, value);
+            }
+        }
+
+        for item in items.iter_mut() {
+            match *item {
+                Item::Const(..) | Item::Use(_) => (),
+                _ => self.visit_item(item),
+            }
+        }
+
+        self.scopes.pop();
+    }
+
+    fn visit_function(&mut self, func: &mut Function) {
+        self.scopes.push(HashMap::new());
+        self.bind_locals(&func.parameters);
+        visit::walk_function_mut(self, func);
+        self.scopes.pop();
+    }
+
+    fn visit_trait_function(&mut self, func: &mut TraitFunction) {
+        self.scopes.push(HashMap::new());
+        self.bind_locals(&func.parameters);
+        visit::walk_trait_function_mut(self, func);
+        self.scopes.pop();
+    }
+
+    fn visit_lambda(&mut self, lambda: &mut Lambda) {
+        self.scopes.push(HashMap::new());
+        self.bind_locals(&lambda.parameters);
+        visit::walk_lambda_mut(self, lambda);
+        self.scopes.pop();
+    }
+
+    fn visit_for_loop(&mut self, l: &mut ForLoop) {
+        self.visit_expression(&mut l.iterator);
+        self.scopes.push(HashMap::new());
+        self.bind_locals(&l.bindings);
+        self.visit_block(&mut l.block);
+        self.scopes.pop();
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        self.scopes.push(HashMap::new());
+
+        let statements = ::std::mem::replace(&mut block.statements, vec![]);
+        let mut statements = statements.into_iter();
+        while let Some(stmnt) = statements.next() {
+            for stmnt in self.optimize_statement(stmnt) {
+                match stmnt {
+                    Statement::Declaration(ref first, ref rest, ..) => {
+                        self.bind(first, None);
+                        for &(ref name, _) in rest {
+                            self.bind(name, None);
+                        }
+                    }
+                    Statement::Use(ref path) => {
+                        if let Some((name, _)) = use_binding(path) {
+                            self.bind(name, None);
+                        }
+                    }
+                    _ => (),
+                }
+
+                let jump = jump_location(&stmnt);
+                block.statements.push(stmnt);
+
+                if let Some(loc) = jump {
+                    if statements.next().is_some() {
+                        self.diagnostics.push(Diagnostic::Unreachable(loc));
+                    }
+                    self.scopes.pop();
+                    return;
+                }
+            }
+        }
+
+        self.scopes.pop();
+    }
+
+    fn visit_statement(&mut self, stmnt: &mut Statement) {
+        match *stmnt {
+            Statement::Assignment(ref mut lhs, ref mut extra, _, ref mut rhs) => {
+                self.visit_target(lhs);
+                for target in extra {
+                    self.visit_target(target);
+                }
+                self.visit_expression(rhs);
+            }
+            _ => visit::walk_statement_mut(self, stmnt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        visit::walk_expression_mut(self, expr);
+
+        let replacement = match *expr {
+            Expression::Identifier(ref name, _) => {
+                match self.lookup(name) {
+                    Some(&Constant::Scalar(ref lit)) => Some(lit.clone()),
+                    _ => None,
+                }
+            }
+            Expression::IndexAccess(ref obj, ref index) if index.len() == 1 => {
+                let i = match index[0] {
+                    Expression::Literal(Literal::Integer(i)) => i,
+                    _ => return,
+                };
+                let array = match **obj {
+                    Expression::Identifier(ref name, _) => self.lookup(name).cloned(),
+                    ref obj => Constant::from_expr(obj),
+                };
+                array.and_then(|array| array.index(i))
+            }
+            _ => None,
+        };
+
+        match replacement {
+            Some(lit) => *expr = Expression::Literal(lit),
+            None => self.simplify(expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::printer::{print_module, Config};
+    use lexer::Lexer;
+    use parser::parse_Module;
+    use std::fs::File;
+    use std::io::Read;
+
+    fn optimize(source: &str) -> (String, Vec<Diagnostic>) {
+        let mut ast = parse_Module(Lexer::new(source)).unwrap();
+        let diagnostics = optimize_module(&mut ast);
+        (print_module(&ast, &Config::default()), diagnostics)
+    }
+
+    fn optimized(source: &str) -> String {
+        let (printed, diagnostics) = optimize(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        printed
+    }
+
+    fn unreachable_lines(source: &str) -> Vec<usize> {
+        optimize(source)
+            .1
+            .iter()
+            .map(|diag| match *diag {
+                Diagnostic::Unreachable(loc) => loc.line,
+                ref diag => panic!("unexpected {:?}", diag),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn constant_array_index_in_example() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/example.seal");
+        let mut source = String::new();
+        File::open(path).unwrap().read_to_string(&mut source).unwrap();
+        let mut ast = parse_Module(Lexer::new(&source)).unwrap();
+        optimize_module(&mut ast);
+
+        let items = match ast {
+            Module::Root { items } => items,
+            _ => unreachable!(),
+        };
+        let wow = items.iter()
+            .filter_map(|item| match *item {
+                Item::Function(_, ref func) if func.name == "wow" => Some(func),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        match wow.body.statements[0] {
+            Statement::Return(ref exprs, _) => {
+                // `FOO[8 << 1]`
+                match exprs[1] {
+                    Expression::Literal(Literal::Integer(1)) => (),
+                    ref expr => panic!("not folded: {:?}", expr),
+                }
+            }
+            ref stmnt => panic!("{:?}", stmnt),
+        }
+    }
+
+    #[test]
+    fn shadowed_consts_are_kept() {
+        let printed = optimized("const N = 4;
+fn param(N) { return N; }
+fn local() { let N = 2; return N; }
+fn global() { return N; }
+mod inner { fn child() { return N; } }
+");
+        assert!(printed.contains("fn param(N) {\n    return N;\n}"), "{}", printed);
+        assert!(printed.contains("let N = 2;\n    return N;\n}"), "{}", printed);
+        assert!(printed.contains("fn global() {\n    return 4;\n}"), "{}", printed);
+        assert!(printed.contains("fn child() {\n        return 4;\n    }"), "{}", printed);
+    }
+
+    #[test]
+    fn false_branches_are_removed() {
+        let printed = optimized("fn f(x) {
+    if false { x = 1; }
+    if false { x = 2; } else { x = 3; }
+    if 1 > 2 { x = 4; }
+    return x;
+}
+");
+        assert_eq!(printed, "fn f(x) {\n    x = 3;\n    return x;\n}\n");
+    }
+
+    #[test]
+    fn true_branches_that_declare_names_stay_blocks() {
+        let printed = optimized("fn f() {
+    if true { let y = 1; print(y); }
+    if true { print(2); } else { print(3); }
+}
+");
+        assert_eq!(printed,
+                   "fn f() {\n    if true {\n        let y = 1;\n        print(y);\n    }\n    \
+                    print(2);\n}\n");
+    }
+
+    #[test]
+    fn false_loops_are_dropped() {
+        let printed = optimized("fn f(x) {
+    while false { x += 1; }
+    while !true { x += 2; }
+    while x { x -= 1; }
+}
+");
+        assert_eq!(printed, "fn f(x) {\n    while x {\n        x -= 1;\n    }\n}\n");
+    }
+
+    #[test]
+    fn code_after_jumps_is_unreachable() {
+        assert_eq!(unreachable_lines("fn f(x) {
+    return x;
+    print(x);
+}
+fn g(x) {
+    throw x;
+    print(x);
+}
+fn h(x) {
+    loop {
+        if x { break; print(1); }
+        continue;
+        x += 1;
+    }
+}
+fn fine(x) {
+    if x { return 1; }
+    return 2;
+}
+"),
+                   vec![2, 6, 11, 12]);
+
+        let (printed, _) = optimize("fn f(x) {\n    return x;\n    print(x);\n}\n");
+        assert_eq!(printed, "fn f(x) {\n    return x;\n}\n");
+    }
+}
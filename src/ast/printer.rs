@@ -0,0 +1,848 @@
+//! Pretty-printer from the `ast` back to Seal source.
+//!
+//! Parentheses are only emitted where the precedence tiers in
+//! `parser.lalrpop` require them, so the printed source parses back to the
+//! same tree. Comma-separated lists are kept on one line when they fit in
+//! `Config::max_width` and are otherwise broken one element per line with a
+//! trailing comma.
+
+use ast::*;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Number of spaces per indentation level (ignored with `hard_tabs`)
+    pub indent_width: usize,
+    pub hard_tabs: bool,
+    pub max_width: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            indent_width: 4,
+            hard_tabs: false,
+            max_width: 100,
+        }
+    }
+}
+
+pub fn print_module(module: &Module, config: &Config) -> String {
+    let mut printer = Printer::new(config);
+    printer.module(module);
+    printer.out
+}
+
+pub fn print_statement(stmnt: &Statement, config: &Config) -> String {
+    let mut printer = Printer::new(config);
+    printer.statement(stmnt);
+    printer.out
+}
+
+pub fn print_expression(expr: &Expression, config: &Config) -> String {
+    let mut printer = Printer::new(config);
+    printer.expr(expr);
+    printer.out
+}
+
+// Expression precedence, mirroring the tiers in the grammar from the
+// loosest-binding (`Expression`) to the tightest (`Term`).
+const PREC_EXPRESSION: u8 = 0;
+const PREC_UNARY: u8 = 10;
+const PREC_POSTFIX: u8 = 11;
+const PREC_TERM: u8 = 12;
+
+fn binop_prec(op: BinOp) -> u8 {
+    use ast::BinOp::*;
+    match op {
+        Implements => 1,
+        RangeExclusive | RangeInclusive => 2,
+        LogicalOr => 3,
+        LogicalAnd => 4,
+        Equal | NotEqual | LessThan | GreaterThan | LessOrEqual | GreaterOrEqual => 5,
+        BitOr | BitXor | BitAnd => 6,
+        LShiftLeft | LShiftRight | AShiftRight => 7,
+        Add | Sub => 8,
+        Mul | Div | Rem | DivRem | Mod => 9,
+    }
+}
+
+fn expr_prec(expr: &Expression) -> u8 {
+    match *expr {
+        Expression::ObjectConstructor(..) |
+        Expression::Try(..) |
+        Expression::Lambda(_) => PREC_EXPRESSION,
+        Expression::BinaryOp(_, op, _, _) => binop_prec(op),
+        Expression::Negate(_) | Expression::Not(_) => PREC_UNARY,
+        Expression::MemberAccess(..) |
+        Expression::IndexAccess(..) |
+        Expression::FunctionCall(..) => PREC_POSTFIX,
+        Expression::Literal(ref lit) if is_negative(lit) => PREC_UNARY,
+        _ => PREC_TERM,
+    }
+}
+
+/// Folded negative literals print with a leading `-`
+fn is_negative(lit: &Literal) -> bool {
+    match *lit {
+        Literal::Integer(i) => i < 0,
+        Literal::BigInt(ref s) => s.starts_with('-'),
+        Literal::Float(f) => f.is_finite() && f.is_sign_negative(),
+        Literal::Suffixed(ref lit, _) => is_negative(lit),
+        _ => false,
+    }
+}
+
+pub fn binop_str(op: BinOp) -> &'static str {
+    use ast::BinOp::*;
+    match op {
+        Implements => "impls",
+        RangeExclusive => "..",
+        RangeInclusive => "...",
+        LogicalOr => "||",
+        LogicalAnd => "&&",
+        Equal => "==",
+        NotEqual => "!=",
+        LessThan => "<",
+        GreaterThan => ">",
+        LessOrEqual => "<=",
+        GreaterOrEqual => ">=",
+        BitOr => "|",
+        BitXor => "^",
+        BitAnd => "&",
+        LShiftLeft => "<<",
+        LShiftRight => ">>>",
+        AShiftRight => ">>",
+        Add => "+",
+        Sub => "-",
+        Mul => "*",
+        Div => "/",
+        Rem => "%",
+        DivRem => "/%",
+        Mod => "mod",
+    }
+}
+
+pub fn type_str(ty: &TypeExpr) -> String {
+    match *ty {
+        TypeExpr::Named(ref name, _) => name.clone(),
+        TypeExpr::Array(ref elem) => format!("[{}]", type_str(elem)),
+    }
+}
+
+pub fn assign_op_str(op: AssignOp) -> &'static str {
+    use ast::AssignOp::*;
+    match op {
+        Assign => "=",
+        LogicalOr => "||=",
+        LogicalAnd => "&&=",
+        BitOr => "|=",
+        BitXor => "^=",
+        BitAnd => "&=",
+        LShiftLeft => "<<=",
+        LShiftRight => ">>>=",
+        AShiftRight => ">>=",
+        Add => "+=",
+        Sub => "-=",
+        Mul => "*=",
+        Div => "/=",
+        Rem => "%=",
+        Mod => "mod=",
+    }
+}
+
+pub fn float_str(f: f64) -> String {
+    if f.is_nan() {
+        return "(0.0 / 0.0)".into();
+    } else if f.is_infinite() {
+        return if f > 0.0 { "(1.0 / 0.0)" } else { "(-1.0 / 0.0)" }.into();
+    }
+
+    with_point(f.to_string())
+}
+
+/// `Display` never uses an exponent, but the lexer needs a `.`
+pub fn with_point(s: String) -> String {
+    if s.contains('.') { s } else { s + ".0" }
+}
+
+fn needs_blank_line(prev: &Item, next: &Item) -> bool {
+    match (prev, next) {
+        (&Item::DocComment(_), _) => false,
+        (&Item::ModuleDocComment(_), &Item::ModuleDocComment(_)) => false,
+        (&Item::Use(_), &Item::Use(_)) => false,
+        (&Item::Extern(..), &Item::Extern(..)) => false,
+        (&Item::TypeDecl(..), &Item::TypeDecl(..)) => false,
+        (&Item::Const(..), &Item::Const(..)) => false,
+        _ => true,
+    }
+}
+
+struct Printer<'a> {
+    config: &'a Config,
+    out: String,
+    indent: usize,
+    // Never break lists; used to measure the one-line form
+    flat: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn new(config: &'a Config) -> Self {
+        Printer {
+            config: config,
+            out: String::new(),
+            indent: 0,
+            flat: false,
+        }
+    }
+
+    fn flat_printer(&self) -> Printer<'a> {
+        Printer {
+            config: self.config,
+            out: String::new(),
+            indent: self.indent,
+            flat: true,
+        }
+    }
+
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            if self.config.hard_tabs {
+                self.out.push('\t');
+            } else {
+                for _ in 0..self.config.indent_width {
+                    self.out.push(' ');
+                }
+            }
+        }
+    }
+
+    fn column(&self) -> usize {
+        let line_start = self.out.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        self.out[line_start..].chars().count()
+    }
+
+    /// Writes `items` between `open` and `close`, on one line if it fits and
+    /// one item per line otherwise. `pad` puts spaces inside the delimiters
+    /// in the one-line form, as in `{ a, b }`.
+    fn list<T, F>(&mut self, open: &str, close: &str, pad: bool, items: &[T], mut item: F)
+        where F: FnMut(&mut Printer<'a>, &T)
+    {
+        if items.is_empty() {
+            self.push(open);
+            self.push(close);
+            return;
+        }
+
+        let mut flat = self.flat_printer();
+        let mut last_start = 0;
+        flat.push(open);
+        if pad {
+            flat.push(" ");
+        }
+        for (i, it) in items.iter().enumerate() {
+            if i > 0 {
+                flat.push(", ");
+            }
+            last_start = flat.out.len();
+            item(&mut flat, it);
+        }
+        if pad {
+            flat.push(" ");
+        }
+        flat.push(close);
+
+        // Only the last item may span lines, e.g. a trailing `fn() { ... }`
+        let column = self.column();
+        let max_width = self.config.max_width;
+        let fits = self.flat ||
+                   (!flat.out[..last_start].contains('\n') &&
+                    flat.out.lines().enumerate().all(|(i, line)| {
+            let start = if i == 0 { column } else { 0 };
+            start + line.chars().count() <= max_width
+        }));
+
+        if fits {
+            self.push(&flat.out);
+            return;
+        }
+
+        self.push(open);
+        self.indent += 1;
+        for it in items {
+            self.newline();
+            self.write_indent();
+            item(self, it);
+            self.push(",");
+        }
+        self.indent -= 1;
+        self.newline();
+        self.write_indent();
+        self.push(close);
+    }
+
+    fn module(&mut self, module: &Module) {
+        match *module {
+            Module::Root { ref items } => self.items(items),
+            Module::Inline { ref name, ref items, .. } => {
+                self.push("mod ");
+                self.push(name);
+                if items.is_empty() {
+                    self.push(" {}");
+                    return;
+                }
+
+                self.push(" {");
+                self.newline();
+                self.indent += 1;
+                self.items(items);
+                self.indent -= 1;
+                self.write_indent();
+                self.push("}");
+            }
+            Module::Extern { ref name, .. } => {
+                self.push("mod ");
+                self.push(name);
+            }
+        }
+    }
+
+    fn items(&mut self, items: &[Item]) {
+        let mut prev = None;
+        for item in items {
+            if let Some(prev) = prev {
+                if needs_blank_line(prev, item) {
+                    self.newline();
+                }
+            }
+
+            self.item(item);
+            self.newline();
+            prev = Some(item);
+        }
+    }
+
+    fn item(&mut self, item: &Item) {
+        match *item {
+            Item::Use(ref expr) => {
+                self.write_indent();
+                self.push("use ");
+                self.expr(expr);
+                self.push(";");
+            }
+            Item::Extern(ref name, _) => {
+                self.write_indent();
+                self.push("extern mod ");
+                self.push(name);
+                self.push(";");
+            }
+            Item::Module(ref module) => {
+                self.write_indent();
+                self.module(module);
+            }
+            Item::TypeDecl(ref attrs, ref decl) => {
+                self.attributes(attrs);
+                self.push("type ");
+                self.push(&decl.name);
+                match decl.fields {
+                    Some(ref fields) => {
+                        self.push(" ");
+                        self.list("{", "}", true, fields, |p, field| {
+                            p.push(&field.name);
+                            if let Some(ref default) = field.default {
+                                p.push(" = ");
+                                p.expr(default);
+                            }
+                        });
+                    }
+                    None => self.push(";"),
+                }
+            }
+            Item::TypeImpl(ref attrs, ref imp) => {
+                self.attributes(attrs);
+                self.push("impl ");
+                if let Some(ref interface) = imp.interface {
+                    self.push(interface);
+                    self.push(" for ");
+                }
+                self.push(&imp.name);
+                self.members(&imp.constants, &imp.methods, |p, func| p.function(func));
+            }
+            Item::Function(ref attrs, ref func) => {
+                self.attributes(attrs);
+                self.function(func);
+            }
+            Item::Trait(ref attrs, ref tr) => {
+                self.attributes(attrs);
+                self.push("trait ");
+                self.push(&tr.name);
+                for (i, sup) in tr.supertraits.iter().enumerate() {
+                    self.push(if i == 0 { ": " } else { " + " });
+                    self.push(sup);
+                }
+                self.members(&tr.constants, &tr.methods, |p, func| p.trait_function(func));
+            }
+            Item::Const(ref attrs, ref name, ref expr, _) => {
+                self.attributes(attrs);
+                self.push("const ");
+                self.push(name);
+                self.push(" = ");
+                self.expr(expr);
+                self.push(";");
+            }
+            Item::DocComment(ref text) |
+            Item::ModuleDocComment(ref text) => {
+                self.write_indent();
+                self.push(text);
+            }
+        }
+    }
+
+    /// The `{ ... }` body of an `impl` or `trait`: the consts, then each
+    /// method one blank line apart.
+    fn members<T, F>(&mut self, consts: &[AssocConst], members: &[T], mut member: F)
+        where F: FnMut(&mut Printer<'a>, &T)
+    {
+        if consts.is_empty() && members.is_empty() {
+            self.push(" {}");
+            return;
+        }
+
+        self.push(" {");
+        self.newline();
+        self.indent += 1;
+        for c in consts {
+            self.write_indent();
+            self.push("const ");
+            self.push(&c.name);
+            if let Some(ref value) = c.value {
+                self.push(" = ");
+                self.expr(value);
+            }
+            self.push(";");
+            self.newline();
+        }
+        for (i, m) in members.iter().enumerate() {
+            if i > 0 || !consts.is_empty() {
+                self.newline();
+            }
+            self.write_indent();
+            member(self, m);
+            self.newline();
+        }
+        self.indent -= 1;
+        self.write_indent();
+        self.push("}");
+    }
+
+    /// Writes each attribute on its own line and leaves the cursor indented
+    /// for the item that follows.
+    fn attributes(&mut self, attrs: &[Attribute]) {
+        for attr in attrs {
+            self.write_indent();
+            self.push("@");
+            self.attribute(attr);
+            self.newline();
+        }
+        self.write_indent();
+    }
+
+    fn attribute(&mut self, attr: &Attribute) {
+        match *attr {
+            Attribute::Identifier(ref id) => self.push(id),
+            Attribute::Named(ref id, ref args) => {
+                self.push(id);
+                self.list("(", ")", false, args, |p, arg| p.attribute(arg));
+            }
+            Attribute::String(ref s) => self.push(s),
+            Attribute::Map(ref key, ref value) => {
+                self.attribute(key);
+                self.push(": ");
+                self.attribute(value);
+            }
+        }
+    }
+
+    fn param_list(is_member: bool, params: &[Identifier], types: &[Option<TypeExpr>]) -> Vec<String> {
+        let mut all = Vec::with_capacity(params.len() + 1);
+        if is_member {
+            all.push("self".to_string());
+        }
+        for (param, ty) in params.iter().zip(types) {
+            match *ty {
+                Some(ref ty) => all.push(format!("{}: {}", param, type_str(ty))),
+                None => all.push(param.clone()),
+            }
+        }
+        all
+    }
+
+    fn params(&mut self, is_member: bool, params: &[Identifier], types: &[Option<TypeExpr>]) {
+        let all = Printer::param_list(is_member, params, types);
+        self.list("(", ")", false, &all, |p, param| p.push(param));
+    }
+
+    fn signature_end(&mut self, can_error: bool, ret: &Option<TypeExpr>) {
+        if can_error {
+            self.push("?");
+        }
+        if let Some(ref ret) = *ret {
+            self.push(" -> ");
+            self.push(&type_str(ret));
+        }
+    }
+
+    fn function(&mut self, func: &Function) {
+        self.push("fn ");
+        self.push(&func.name);
+        self.params(func.is_member, &func.parameters, &func.parameter_types);
+        self.signature_end(func.can_error, &func.return_type);
+        self.push(" ");
+        self.block(&func.body);
+    }
+
+    fn trait_function(&mut self, func: &TraitFunction) {
+        self.push("fn ");
+        self.push(&func.name);
+        self.params(func.is_member, &func.parameters, &func.parameter_types);
+        self.signature_end(func.can_error, &func.return_type);
+        match func.body {
+            Some(ref body) => {
+                self.push(" ");
+                self.block(body);
+            }
+            None => self.push(";"),
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        if block.statements.is_empty() {
+            self.push("{}");
+            return;
+        }
+
+        self.push("{");
+        self.newline();
+        self.indent += 1;
+        for stmnt in &block.statements {
+            self.write_indent();
+            self.statement(stmnt);
+            self.newline();
+        }
+        self.indent -= 1;
+        self.write_indent();
+        self.push("}");
+    }
+
+    fn label(&mut self, label: &Option<Label>) {
+        if let Some(ref label) = *label {
+            self.push("'");
+            self.push(label);
+            self.push(": ");
+        }
+    }
+
+    fn statement(&mut self, stmnt: &Statement) {
+        match *stmnt {
+            Statement::Use(ref expr) => {
+                self.push("use ");
+                self.expr(expr);
+                self.push(";");
+            }
+            Statement::Expression(ref expr) => {
+                self.expr_at(expr, PREC_POSTFIX);
+                self.push(";");
+            }
+            Statement::Declaration(ref first, ref rest, ref ty, ref init, _) => {
+                self.push("let ");
+                self.push(first);
+                for &(ref name, _) in rest {
+                    self.push(", ");
+                    self.push(name);
+                }
+                if let Some(ref ty) = *ty {
+                    self.push(": ");
+                    self.push(&type_str(ty));
+                }
+                if let Some(ref init) = *init {
+                    self.push(" = ");
+                    self.expr(init);
+                }
+                self.push(";");
+            }
+            Statement::Assignment(ref lhs, ref rest, op, ref rhs) => {
+                self.expr_at(lhs, PREC_POSTFIX);
+                for target in rest {
+                    self.push(", ");
+                    self.expr_at(target, PREC_POSTFIX);
+                }
+                self.push(" ");
+                self.push(assign_op_str(op));
+                self.push(" ");
+                self.expr(rhs);
+                self.push(";");
+            }
+            Statement::IfElse(ref if_else) => self.if_else(if_else),
+            Statement::Loop(ref l) => {
+                self.label(&l.label);
+                self.push("loop ");
+                self.block(&l.block);
+            }
+            Statement::ForLoop(ref l) => {
+                self.label(&l.label);
+                self.push("for ");
+                for (i, name) in l.bindings.iter().enumerate() {
+                    if i > 0 {
+                        self.push(", ");
+                    }
+                    self.push(name);
+                }
+                self.push(" in ");
+                self.expr(&l.iterator);
+                self.push(" ");
+                self.block(&l.block);
+            }
+            Statement::WhileLoop(ref l) => {
+                self.label(&l.label);
+                self.push("while ");
+                self.expr(&l.condition);
+                self.push(" ");
+                self.block(&l.block);
+            }
+            Statement::Return(ref exprs, _) => {
+                self.push("return");
+                for (i, expr) in exprs.iter().enumerate() {
+                    self.push(if i == 0 { " " } else { ", " });
+                    self.expr(expr);
+                }
+                self.push(";");
+            }
+            Statement::Throw(ref expr, _) => {
+                self.push("throw ");
+                self.expr(expr);
+                self.push(";");
+            }
+            Statement::Break(ref label, _) => {
+                self.push("break");
+                self.jump_label(label);
+            }
+            Statement::Continue(ref label, _) => {
+                self.push("continue");
+                self.jump_label(label);
+            }
+        }
+    }
+
+    fn jump_label(&mut self, label: &Option<Label>) {
+        if let Some(ref label) = *label {
+            self.push(" '");
+            self.push(label);
+        }
+        self.push(";");
+    }
+
+    fn if_else(&mut self, if_else: &IfElse) {
+        self.push("if ");
+        self.expr(&if_else.condition);
+        self.push(" ");
+        self.block(&if_else.if_block);
+
+        let else_block = match if_else.else_block {
+            Some(ref block) => block,
+            None => return,
+        };
+
+        // `else if` is parsed as an else block holding a single `if`
+        if else_block.statements.len() == 1 {
+            if let Statement::IfElse(ref chain) = else_block.statements[0] {
+                self.push(" else ");
+                self.if_else(chain);
+                return;
+            }
+        }
+
+        self.push(" else ");
+        self.block(else_block);
+    }
+
+    fn expr(&mut self, expr: &Expression) {
+        self.expr_at(expr, PREC_EXPRESSION);
+    }
+
+    /// Writes `expr`, parenthesized if it binds more loosely than `min_prec`.
+    fn expr_at(&mut self, expr: &Expression, min_prec: u8) {
+        if expr_prec(expr) < min_prec {
+            self.push("(");
+            self.expr_inner(expr);
+            self.push(")");
+        } else {
+            self.expr_inner(expr);
+        }
+    }
+
+    fn expr_inner(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Nil => self.push("nil"),
+            Expression::Literal(ref lit) => self.literal(lit),
+            Expression::Identifier(ref id, _) => self.push(id),
+            Expression::MemberAccess(ref obj, ref member) => {
+                self.expr_at(obj, PREC_POSTFIX);
+                self.push(".");
+                self.push(member);
+            }
+            Expression::IndexAccess(ref obj, ref index) => {
+                self.expr_at(obj, PREC_POSTFIX);
+                self.list("[", "]", false, index, |p, e| p.expr(e));
+            }
+            Expression::FunctionCall(ref func, ref args) => {
+                self.expr_at(func, PREC_POSTFIX);
+                self.list("(", ")", false, args, |p, e| p.expr(e));
+            }
+            Expression::ObjectConstructor(ref name, ref obj, _) => {
+                self.push("new_object ");
+                self.push(name);
+                self.push(" ");
+                self.object_literal(obj);
+            }
+            Expression::BinaryOp(ref lhs, op, ref rhs, _) => {
+                // Ranges read better tight: `0..10`
+                let space = match op {
+                    BinOp::RangeExclusive | BinOp::RangeInclusive => "",
+                    _ => " ",
+                };
+                let prec = binop_prec(op);
+                self.expr_at(lhs, prec);
+                self.push(space);
+                self.push(binop_str(op));
+                self.push(space);
+                self.expr_at(rhs, prec + 1);
+            }
+            Expression::Negate(ref rhs) => {
+                self.push("-");
+                self.expr_at(rhs, PREC_UNARY);
+            }
+            Expression::Not(ref rhs) => {
+                self.push("!");
+                self.expr_at(rhs, PREC_UNARY);
+            }
+            Expression::Try(ref lhs, _) => {
+                self.expr_at(lhs, PREC_POSTFIX);
+                self.push("?");
+            }
+            Expression::Lambda(ref lambda) => self.lambda(lambda),
+        }
+    }
+
+    fn lambda(&mut self, lambda: &Lambda) {
+        // Lambdas whose body is a lone `return` came from `|x| expr`
+        if lambda.body.statements.len() == 1 {
+            if let Statement::Return(ref exprs, _) = lambda.body.statements[0] {
+                if exprs.len() == 1 {
+                    if lambda.parameters.is_empty() && !lambda.is_member {
+                        self.push("||");
+                    } else {
+                        let params = Printer::param_list(lambda.is_member,
+                                                         &lambda.parameters,
+                                                         &lambda.parameter_types);
+                        self.push("|");
+                        self.push(&params.join(", "));
+                        self.push("|");
+                    }
+                    if lambda.can_error {
+                        self.push("?");
+                    }
+                    self.push(" ");
+                    self.expr(&exprs[0]);
+                    return;
+                }
+            }
+        }
+
+        self.push("fn");
+        self.params(lambda.is_member, &lambda.parameters, &lambda.parameter_types);
+        if lambda.can_error {
+            self.push("?");
+        }
+        self.push(" ");
+        self.block(&lambda.body);
+    }
+
+    fn literal(&mut self, lit: &Literal) {
+        match *lit {
+            Literal::Integer(i) => self.push(&i.to_string()),
+            Literal::BigInt(ref s) => self.push(s),
+            Literal::Suffixed(ref lit, suffix) => {
+                match **lit {
+                    // The shortest digits that round to the same `f32`
+                    Literal::Float(f) if suffix == NumSuffix::F32 => {
+                        self.push(&with_point((f as f32).to_string()))
+                    }
+                    ref lit => self.literal(lit),
+                }
+                self.push(suffix.as_str());
+            }
+            Literal::Float(f) => self.push(&float_str(f)),
+            Literal::Bool(b) => self.push(if b { "true" } else { "false" }),
+            Literal::String(ref s) => self.push(s),
+            Literal::Symbol(ref name) => {
+                self.push(":");
+                self.push(name);
+            }
+            Literal::Object(ref obj) => self.object_literal(obj),
+            Literal::Array(ArrayLiteral::List(ref exprs)) => {
+                self.list("[", "]", false, exprs, |p, e| p.expr(e));
+            }
+            Literal::Array(ArrayLiteral::Splat(ref value, ref count)) => {
+                self.push("[");
+                self.expr(value);
+                self.push("; ");
+                self.expr(count);
+                self.push("]");
+            }
+            Literal::Simd(ref lanes, ref ty) => {
+                self.push("<");
+                for (i, lane) in lanes.iter().enumerate() {
+                    if i > 0 {
+                        self.push(", ");
+                    }
+                    self.expr_at(lane, PREC_UNARY);
+                }
+                self.simd_suffix(ty);
+            }
+            Literal::SimdSplat(ref value, ref ty) => {
+                self.push("<");
+                self.expr_at(value, PREC_UNARY);
+                self.push("...");
+                self.simd_suffix(ty);
+            }
+        }
+    }
+
+    fn simd_suffix(&mut self, ty: &Option<Identifier>) {
+        if let Some(ref ty) = *ty {
+            self.push(":");
+            self.push(ty);
+        }
+        self.push(">");
+    }
+
+    fn object_literal(&mut self, obj: &ObjectLiteral) {
+        self.list("{", "}", true, obj, |p, field| {
+            p.push(&field.key);
+            match field.value {
+                Expression::Identifier(ref id, _) if *id == field.key => (),
+                _ => {
+                    p.push(": ");
+                    p.expr(&field.value);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,508 @@
+//! Name resolution.
+//!
+//! `resolve_module` binds every `Expression::Identifier` to whatever it names
+//! and reports names that refer to nothing, names declared twice in the same
+//! scope, and locals that hide another local.
+//!
+//! Module items are visible throughout the module that declares them and in
+//! its inline child modules, which is also where `optimize` substitutes
+//! consts. A const's initializer only sees the consts declared above it.
+//! Locals are visible from the statement after their `let` to the end of the
+//! block, and a lambda that names a local of an enclosing function captures
+//! it as an upvalue.
+
+use ast::*;
+use ast::visit::{self, Visitor};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    Local,
+    Parameter,
+    /// A local or parameter of an enclosing function, captured by a lambda
+    Upvalue,
+    Const,
+    Import,
+    /// A module, type, trait, function or `extern mod`
+    Item,
+    /// One of the globals passed to `resolve_module`
+    Global,
+}
+
+impl fmt::Display for BindingKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match *self {
+            BindingKind::Local => "local",
+            BindingKind::Parameter => "parameter",
+            BindingKind::Upvalue => "captured local",
+            BindingKind::Const => "const",
+            BindingKind::Import => "import",
+            BindingKind::Item => "item",
+            BindingKind::Global => "global",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Binding {
+    pub kind: BindingKind,
+    /// Where the name is declared, or `None` for a global
+    pub declaration: Option<Location>,
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    Undefined(Identifier),
+    /// Declared again in the same scope; the location is the first declaration
+    Duplicate(Identifier, Location),
+    /// Hides the local or parameter declared at the location
+    Shadowed(Identifier, Location),
+    /// Assigned to, but isn't a local or parameter. Assigning to a const is
+    /// left to the `assign_to_const` lint.
+    NotAssignable(Identifier, BindingKind),
+}
+
+impl ResolveError {
+    pub fn is_warning(&self) -> bool {
+        match *self {
+            ResolveError::Shadowed(..) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResolveError::Undefined(ref name) => write!(fmt, "`{}` is not defined", name),
+            ResolveError::Duplicate(ref name, loc) => {
+                write!(fmt,
+                       "`{}` is already declared in this scope at {}:{}",
+                       name,
+                       loc.line,
+                       loc.column)
+            }
+            ResolveError::Shadowed(ref name, loc) => {
+                write!(fmt,
+                       "`{}` shadows the one declared at {}:{}",
+                       name,
+                       loc.line,
+                       loc.column)
+            }
+            ResolveError::NotAssignable(ref name, kind) => {
+                write!(fmt, "can't assign to {} `{}`", kind, name)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub location: Location,
+    pub error: ResolveError,
+}
+
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// Keyed by the location of each resolved `Expression::Identifier`
+    pub bindings: HashMap<Location, Binding>,
+    /// How many times each declared name is read, keyed by the name and the
+    /// location of its declaration. Assigning to a name doesn't count.
+    pub references: HashMap<(Identifier, Location), usize>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Resolution {
+    pub fn binding(&self, loc: Location) -> Option<&Binding> {
+        self.bindings.get(&loc)
+    }
+
+    pub fn references(&self, name: &str, declaration: Location) -> usize {
+        self.references.get(&(name.into(), declaration)).cloned().unwrap_or(0)
+    }
+}
+
+/// Resolves the names in `root`. `globals` are names the runtime provides,
+/// like `print`, which are visible everywhere unless shadowed.
+pub fn resolve_module(root: &Module, globals: &[&str]) -> Resolution {
+    let mut resolver = Resolver {
+        globals: globals,
+        scopes: vec![],
+        resolution: Resolution::default(),
+    };
+    resolver.visit_module(root);
+
+    // Items are declared before anything is resolved, so put the reports
+    // back in source order
+    resolver.resolution.diagnostics.sort_by_key(|diag| diag.location.index);
+    resolver.resolution
+}
+
+/// The name an item declares in its module, and where.
+pub fn item_binding(item: &Item) -> Option<(&str, Location)> {
+    match *item {
+        Item::Use(ref path) => use_binding(path),
+        Item::Extern(ref name, loc) |
+        Item::Const(_, ref name, _, loc) => Some((name, loc)),
+        Item::Module(Module::Inline { ref name, location, .. }) |
+        Item::Module(Module::Extern { ref name, location }) => Some((name, location)),
+        Item::TypeDecl(_, ref decl) => Some((&decl.name, decl.location)),
+        Item::Function(_, ref func) => Some((&func.name, func.location)),
+        Item::Trait(_, ref tr) => Some((&tr.name, tr.location)),
+        Item::Module(Module::Root { .. }) |
+        Item::TypeImpl(..) |
+        Item::DocComment(_) |
+        Item::ModuleDocComment(_) => None,
+    }
+}
+
+/// The name a `use` path brings into scope: `use std.rand;` binds `rand`.
+/// The location is that of the first name in the path.
+pub fn use_binding(path: &Expression) -> Option<(&str, Location)> {
+    match *path {
+        Expression::Identifier(ref name, loc) => Some((name, loc)),
+        Expression::MemberAccess(ref obj, ref name) => {
+            let mut root = &**obj;
+            while let Expression::MemberAccess(ref obj, _) = *root {
+                root = obj;
+            }
+            match *root {
+                Expression::Identifier(_, loc) => Some((name, loc)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ScopeKind {
+    Module,
+    /// The parameters of a function or lambda
+    Function,
+    Block,
+}
+
+struct Scope {
+    kind: ScopeKind,
+    names: HashMap<Identifier, Binding>,
+}
+
+struct Resolver<'a> {
+    globals: &'a [&'a str],
+    scopes: Vec<Scope>,
+    resolution: Resolution,
+}
+
+impl<'a> Resolver<'a> {
+    fn push_scope(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope {
+            kind: kind,
+            names: HashMap::new(),
+        });
+    }
+
+    fn report(&mut self, location: Location, error: ResolveError) {
+        self.resolution.diagnostics.push(Diagnostic {
+            location: location,
+            error: error,
+        });
+    }
+
+    fn declare(&mut self, name: &str, location: Location, kind: BindingKind) {
+        // `_` discards a value rather than naming it
+        if name == "_" {
+            return;
+        }
+
+        let previous = self.scopes.last().unwrap().names.get(name).and_then(|b| b.declaration);
+        if let Some(previous) = previous {
+            self.report(location, ResolveError::Duplicate(name.into(), previous));
+            return;
+        }
+
+        if self.scopes.last().unwrap().kind != ScopeKind::Module {
+            let shadowed = self.scopes
+                .iter()
+                .rev()
+                .skip(1)
+                .take_while(|scope| scope.kind != ScopeKind::Module)
+                .filter_map(|scope| scope.names.get(name))
+                .next()
+                .and_then(|b| b.declaration);
+            if let Some(shadowed) = shadowed {
+                self.report(location, ResolveError::Shadowed(name.into(), shadowed));
+            }
+        }
+
+        let binding = Binding {
+            kind: kind,
+            declaration: Some(location),
+        };
+        self.scopes.last_mut().unwrap().names.insert(name.into(), binding);
+        self.resolution.references.insert((name.into(), location), 0);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding> {
+        let mut captured = false;
+        for scope in self.scopes.iter().rev() {
+            if let Some(&binding) = scope.names.get(name) {
+                let mut binding = binding;
+                match binding.kind {
+                    BindingKind::Local | BindingKind::Parameter if captured => {
+                        binding.kind = BindingKind::Upvalue
+                    }
+                    _ => (),
+                }
+                return Some(binding);
+            }
+
+            // Anything found past a function's parameters is in an enclosing
+            // function, which only a lambda can see
+            if scope.kind == ScopeKind::Function {
+                captured = true;
+            }
+        }
+
+        if self.globals.contains(&name) {
+            return Some(Binding {
+                kind: BindingKind::Global,
+                declaration: None,
+            });
+        }
+
+        None
+    }
+
+    /// Binds the name at `location`, counting it as a read if `read` is set.
+    fn resolve(&mut self, name: &str, location: Location, read: bool) -> Option<Binding> {
+        let binding = self.lookup(name);
+        match binding {
+            Some(binding) => {
+                self.resolution.bindings.insert(location, binding);
+                if let (true, Some(declaration)) = (read, binding.declaration) {
+                    if let Some(count) = self.resolution
+                        .references
+                        .get_mut(&(name.into(), declaration)) {
+                        *count += 1;
+                    }
+                }
+            }
+            // A stray `self` is left to the `self_outside_method` lint
+            None if name == "self" => (),
+            None => self.report(location, ResolveError::Undefined(name.into())),
+        }
+        binding
+    }
+
+    fn visit_target(&mut self, target: &Expression) {
+        match *target {
+            Expression::Identifier(ref name, loc) => {
+                let kind = match self.resolve(name, loc, false) {
+                    Some(binding) => binding.kind,
+                    None => return,
+                };
+                match kind {
+                    BindingKind::Local |
+                    BindingKind::Parameter |
+                    BindingKind::Upvalue |
+                    BindingKind::Const => (),
+                    _ => self.report(loc, ResolveError::NotAssignable(name.clone(), kind)),
+                }
+            }
+            _ => self.visit_expression(target),
+        }
+    }
+
+    /// `self` is declared at the function's location, since it isn't kept
+    /// in `params`.
+    fn declare_parameters(&mut self,
+                          is_member: bool,
+                          params: &[Identifier],
+                          locations: &[Location],
+                          location: Location) {
+        if is_member {
+            self.declare("self", location, BindingKind::Parameter);
+        }
+        for (param, &loc) in params.iter().zip(locations) {
+            self.declare(param, loc, BindingKind::Parameter);
+        }
+    }
+}
+
+impl<'a> Visitor for Resolver<'a> {
+    fn visit_module(&mut self, module: &Module) {
+        let items = match *module {
+            Module::Root { ref items } |
+            Module::Inline { ref items, .. } => items,
+            Module::Extern { .. } => return,
+        };
+
+        self.push_scope(ScopeKind::Module);
+
+        for item in items {
+            match *item {
+                Item::Const(..) => (),
+                _ => {
+                    if let Some((name, loc)) = item_binding(item) {
+                        let kind = match *item {
+                            Item::Use(_) => BindingKind::Import,
+                            _ => BindingKind::Item,
+                        };
+                        self.declare(name, loc, kind);
+                    }
+                }
+            }
+        }
+
+        // Consts are declared in order, so each initializer only sees the
+        // ones above it, the same as in `optimize`
+        for item in items {
+            if let Item::Const(ref attrs, ref name, ref expr, loc) = *item {
+                for attr in attrs {
+                    self.visit_attribute(attr);
+                }
+                self.visit_expression(expr);
+                self.declare(name, loc, BindingKind::Const);
+            }
+        }
+
+        for item in items {
+            match *item {
+                Item::Const(..) => (),
+                _ => self.visit_item(item),
+            }
+        }
+
+        self.scopes.pop();
+    }
+
+    fn visit_function(&mut self, func: &Function) {
+        self.push_scope(ScopeKind::Function);
+        self.declare_parameters(func.is_member,
+                                &func.parameters,
+                                &func.parameter_locations,
+                                func.location);
+        visit::walk_function(self, func);
+        self.scopes.pop();
+    }
+
+    fn visit_trait_function(&mut self, func: &TraitFunction) {
+        self.push_scope(ScopeKind::Function);
+        self.declare_parameters(func.is_member,
+                                &func.parameters,
+                                &func.parameter_locations,
+                                func.location);
+        visit::walk_trait_function(self, func);
+        self.scopes.pop();
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        self.push_scope(ScopeKind::Function);
+        self.declare_parameters(lambda.is_member,
+                                &lambda.parameters,
+                                &lambda.parameter_locations,
+                                lambda.location);
+        visit::walk_lambda(self, lambda);
+        self.scopes.pop();
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.push_scope(ScopeKind::Block);
+        visit::walk_block(self, block);
+        self.scopes.pop();
+    }
+
+    fn visit_for_loop(&mut self, l: &ForLoop) {
+        self.visit_expression(&l.iterator);
+        self.push_scope(ScopeKind::Block);
+        for (name, &loc) in l.bindings.iter().zip(&l.binding_locations) {
+            self.declare(name, loc, BindingKind::Local);
+        }
+        self.visit_block(&l.block);
+        self.scopes.pop();
+    }
+
+    fn visit_statement(&mut self, stmnt: &Statement) {
+        match *stmnt {
+            Statement::Declaration(ref first, ref rest, _, ref init, loc) => {
+                if let Some(ref init) = *init {
+                    self.visit_expression(init);
+                }
+                self.declare(first, loc, BindingKind::Local);
+                for &(ref name, loc) in rest {
+                    self.declare(name, loc, BindingKind::Local);
+                }
+            }
+            Statement::Use(ref path) => {
+                self.visit_expression(path);
+                if let Some((name, loc)) = use_binding(path) {
+                    self.declare(name, loc, BindingKind::Import);
+                }
+            }
+            Statement::Assignment(ref lhs, ref extra, _, ref rhs) => {
+                self.visit_expression(rhs);
+                self.visit_target(lhs);
+                for target in extra {
+                    self.visit_target(target);
+                }
+            }
+            _ => visit::walk_statement(self, stmnt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        match *expr {
+            Expression::Identifier(ref name, loc) => {
+                self.resolve(name, loc, true);
+            }
+            Expression::ObjectConstructor(ref name, ref obj, loc) => {
+                self.resolve(name, loc, true);
+                self.visit_object_literal(obj);
+            }
+            _ => visit::walk_expression(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::parse_Module;
+
+    fn resolve(source: &str) -> Resolution {
+        resolve_module(&parse_Module(Lexer::new(source)).unwrap(), &[])
+    }
+
+    fn at(line: usize, column: usize, source: &str) -> Location {
+        let index = source.split('\n').take(line - 1).map(|l| l.len() + 1).sum::<usize>() +
+                    column - 1;
+        Location {
+            line: line,
+            column: column,
+            index: index,
+        }
+    }
+
+    #[test]
+    fn each_binding_has_its_own_location() {
+        let source = "fn f(a, b) {\n    let x, y;\n    for i, j in a {}\n}\n";
+        let resolution = resolve(source);
+        assert!(resolution.diagnostics.is_empty(), "{:?}", resolution.diagnostics);
+        for &(name, line, column) in &[("a", 1, 6), ("b", 1, 9), ("x", 2, 9), ("y", 2, 12),
+                                        ("i", 3, 9), ("j", 3, 12)] {
+            let key = (name.to_string(), at(line, column, source));
+            assert!(resolution.references.contains_key(&key), "{} at {}:{}", name, line, column);
+        }
+    }
+
+    #[test]
+    fn only_reads_are_references() {
+        let source = "fn f() {\n    let x, y;\n    x = 1;\n    y += 1;\n    x, y = y;\n    return x;\n}\n";
+        let resolution = resolve(source);
+        assert_eq!(resolution.references("x", at(2, 9, source)), 1);
+        assert_eq!(resolution.references("y", at(2, 12, source)), 1);
+    }
+}
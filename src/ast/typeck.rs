@@ -0,0 +1,819 @@
+//! Gradual type checking.
+//!
+//! Annotations are optional. Parameters, `let`s and return values without
+//! one have type `any`, which is compatible with everything, so unannotated
+//! code stays dynamic. Types are inferred bottom-up from literals, operators,
+//! consts and calls to annotated functions, and checked where they meet an
+//! annotation: `let` initializers, assignments to annotated locals, call
+//! arguments and `return`s.
+//!
+//! The builtin types are `any`, `nil`, `bool`, `int`, `float`, `string`,
+//! `object`, `function` and `[T]`. The name of a `type` is the type of the
+//! objects `new_object` makes from it, and the name of a trait is the type of
+//! anything that implements it.
+//!
+//! `new_object` is also checked against the field list of the `type` it
+//! names, when that is declared in the same file. Types reached through a
+//! `use` are checked when the object is made, by `Runtime::new_object`.
+
+use ast::*;
+use ast::printer;
+use ast::resolve::Resolution;
+use ast::visit::{self, Visitor};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    Int,
+    Float,
+    String,
+    Symbol,
+    Object,
+    Function,
+    Array(Box<Type>),
+    /// Objects made with `new_object` from a `type`
+    Named(Identifier),
+    /// Anything that implements the trait
+    Trait(Identifier),
+}
+
+impl Type {
+    fn is_primitive(&self) -> bool {
+        match *self {
+            Type::Nil | Type::Bool | Type::Int | Type::Float | Type::String |
+            Type::Symbol => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Any => fmt.write_str("any"),
+            Type::Nil => fmt.write_str("nil"),
+            Type::Bool => fmt.write_str("bool"),
+            Type::Int => fmt.write_str("int"),
+            Type::Float => fmt.write_str("float"),
+            Type::String => fmt.write_str("string"),
+            Type::Symbol => fmt.write_str("symbol"),
+            Type::Object => fmt.write_str("object"),
+            Type::Function => fmt.write_str("function"),
+            Type::Array(ref elem) => write!(fmt, "[{}]", elem),
+            Type::Named(ref name) |
+            Type::Trait(ref name) => fmt.write_str(name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TypeError {
+    Mismatch { expected: Type, found: Type },
+    UnknownType(Identifier),
+    ArgumentCount { expected: usize, found: usize },
+    BadOperands(BinOp, Type, Type),
+    UnknownField(Identifier, Identifier),
+    MissingField(Identifier, Identifier),
+    /// A type implements the first trait but not its supertrait, the second
+    MissingSupertrait(Identifier, Identifier, Identifier),
+    DuplicateField(Identifier),
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TypeError::Mismatch { ref expected, ref found } => {
+                write!(fmt, "expected `{}`, found `{}`", expected, found)
+            }
+            TypeError::UnknownType(ref name) => write!(fmt, "unknown type `{}`", name),
+            TypeError::ArgumentCount { expected, found } => {
+                write!(fmt, "expected {} arguments, found {}", expected, found)
+            }
+            TypeError::BadOperands(op, ref lhs, ref rhs) => {
+                write!(fmt,
+                       "can't apply `{}` to `{}` and `{}`",
+                       printer::binop_str(op),
+                       lhs,
+                       rhs)
+            }
+            TypeError::UnknownField(ref ty, ref field) => {
+                write!(fmt, "`{}` has no field `{}`", ty, field)
+            }
+            TypeError::MissingField(ref ty, ref field) => {
+                write!(fmt, "missing field `{}`, which `{}` has no default for", field, ty)
+            }
+            TypeError::MissingSupertrait(ref ty, ref tr, ref sup) => {
+                write!(fmt,
+                       "`{}` implements `{}` but not its supertrait `{}`",
+                       ty,
+                       tr,
+                       sup)
+            }
+            TypeError::DuplicateField(ref field) => {
+                write!(fmt, "field `{}` is declared more than once", field)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub location: Location,
+    pub error: TypeError,
+}
+
+/// Type checks `root`, which `resolution` must have come from.
+pub fn check_module(root: &Module, resolution: &Resolution) -> Vec<Diagnostic> {
+    let mut hierarchy = Hierarchy {
+        impls: HashSet::new(),
+        supertraits: HashMap::new(),
+    };
+    hierarchy.visit_module(root);
+
+    let mut checker = Checker {
+        resolution: resolution,
+        impls: hierarchy.impls,
+        supertraits: hierarchy.supertraits,
+        types: vec![],
+        names: HashMap::new(),
+        functions: HashMap::new(),
+        layouts: HashMap::new(),
+        returns: vec![],
+        diagnostics: vec![],
+    };
+    checker.visit_module(root);
+    checker.diagnostics.sort_by_key(|diag| diag.location.index);
+    checker.diagnostics
+}
+
+/// Collects `impl Trait for Name` pairs and the supertraits of each trait.
+struct Hierarchy {
+    impls: HashSet<(Identifier, Identifier)>,
+    supertraits: HashMap<Identifier, Vec<Identifier>>,
+}
+
+impl Visitor for Hierarchy {
+    fn visit_type_impl(&mut self, imp: &TypeImpl) {
+        if let Some(ref interface) = imp.interface {
+            self.impls.insert((imp.name.clone(), interface.clone()));
+        }
+    }
+
+    fn visit_trait(&mut self, tr: &Trait) {
+        self.supertraits.insert(tr.name.clone(), tr.supertraits.clone());
+    }
+}
+
+struct Signature {
+    params: Vec<Type>,
+    ret: Type,
+}
+
+struct Checker<'a> {
+    resolution: &'a Resolution,
+    impls: HashSet<(Identifier, Identifier)>,
+    supertraits: HashMap<Identifier, Vec<Identifier>>,
+    /// The `type`s and traits declared in each enclosing module
+    types: Vec<HashMap<Identifier, Type>>,
+    /// Annotated locals and parameters, and consts, keyed like
+    /// `Resolution::references`
+    names: HashMap<(Identifier, Location), Type>,
+    functions: HashMap<(Identifier, Location), Signature>,
+    /// The declared fields of each `type` that has a field list, and whether
+    /// each has a default
+    layouts: HashMap<(Identifier, Location), Vec<(Identifier, bool)>>,
+    /// The declared return type of each enclosing function
+    returns: Vec<Type>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, location: Location, error: TypeError) {
+        self.diagnostics.push(Diagnostic {
+            location: location,
+            error: error,
+        });
+    }
+
+    fn lower(&mut self, ty: &TypeExpr) -> Type {
+        match *ty {
+            TypeExpr::Array(ref elem) => Type::Array(Box::new(self.lower(elem))),
+            TypeExpr::Named(ref name, loc) => {
+                for scope in self.types.iter().rev() {
+                    if let Some(ty) = scope.get(name) {
+                        return ty.clone();
+                    }
+                }
+
+                match &name[..] {
+                    "any" => Type::Any,
+                    "nil" => Type::Nil,
+                    "bool" => Type::Bool,
+                    "int" => Type::Int,
+                    "float" => Type::Float,
+                    "string" => Type::String,
+                    "symbol" => Type::Symbol,
+                    "object" => Type::Object,
+                    "function" => Type::Function,
+                    _ => {
+                        self.report(loc, TypeError::UnknownType(name.clone()));
+                        Type::Any
+                    }
+                }
+            }
+        }
+    }
+
+    fn lower_opt(&mut self, ty: &Option<TypeExpr>) -> Type {
+        match *ty {
+            Some(ref ty) => self.lower(ty),
+            None => Type::Any,
+        }
+    }
+
+    /// Whether `name` implements `interface`, directly, through a trait
+    /// that does, or by being a subtrait of it.
+    fn implements(&self, name: &str, interface: &str) -> bool {
+        self.reaches(name, interface, true)
+    }
+
+    /// Whether `interface` can be reached from `name` through `impl`s, and
+    /// supertraits if `inherit` is set.
+    fn reaches(&self, name: &str, interface: &str, inherit: bool) -> bool {
+        let mut seen = HashSet::new();
+        let mut pending = vec![name];
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name) {
+                continue;
+            }
+            for &(ref ty, ref tr) in &self.impls {
+                if ty == name {
+                    pending.push(tr);
+                }
+            }
+            if inherit {
+                if let Some(supers) = self.supertraits.get(name) {
+                    pending.extend(supers.iter().map(|sup| &sup[..]));
+                }
+            }
+            if pending.contains(&interface) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn assignable(&self, from: &Type, to: &Type) -> bool {
+        match (from, to) {
+            (_, &Type::Any) | (&Type::Any, _) => true,
+            (&Type::Int, &Type::Float) => true,
+            (&Type::Array(ref from), &Type::Array(ref to)) => self.assignable(from, to),
+            (&Type::Named(_), &Type::Object) => true,
+            (&Type::Named(ref name), &Type::Trait(ref tr)) |
+            (&Type::Trait(ref name), &Type::Trait(ref tr)) => {
+                name == tr || self.implements(name, tr)
+            }
+            (from, to) => from == to,
+        }
+    }
+
+    fn expect(&mut self, found: Type, expected: &Type, location: Location) {
+        if !self.assignable(&found, expected) {
+            self.report(location,
+                        TypeError::Mismatch {
+                            expected: expected.clone(),
+                            found: found,
+                        });
+        }
+    }
+
+    fn declare_params(&mut self, params: &[Identifier], locations: &[Location], types: &[Type]) {
+        for ((param, &loc), ty) in params.iter().zip(locations).zip(types) {
+            if *ty != Type::Any {
+                self.names.insert((param.clone(), loc), ty.clone());
+            }
+        }
+    }
+
+    fn declare_layout(&mut self, decl: &TypeDecl, fields: &[FieldDecl]) {
+        let mut layout: Vec<(Identifier, bool)> = vec![];
+        for field in fields {
+            if layout.iter().any(|&(ref name, _)| *name == field.name) {
+                self.report(field.location, TypeError::DuplicateField(field.name.clone()));
+                continue;
+            }
+            layout.push((field.name.clone(), field.default.is_some()));
+        }
+        self.layouts.insert((decl.name.clone(), decl.location), layout);
+    }
+
+    fn check_fields(&mut self, name: &str, obj: &ObjectLiteral, location: Location) {
+        let key = match self.resolution.binding(location).and_then(|b| b.declaration) {
+            Some(declaration) => (name.to_string(), declaration),
+            None => return,
+        };
+        let layout = match self.layouts.get(&key) {
+            Some(layout) => layout.clone(),
+            None => return,
+        };
+
+        for field in obj {
+            if !layout.iter().any(|&(ref name, _)| *name == field.key) {
+                self.report(field.location,
+                            TypeError::UnknownField(name.into(), field.key.clone()));
+            }
+        }
+        for (field, has_default) in layout {
+            if !has_default && !obj.iter().any(|given| given.key == field) {
+                self.report(location, TypeError::MissingField(name.into(), field));
+            }
+        }
+    }
+
+    fn binary(&mut self, lhs: Type, op: BinOp, rhs: Type, location: Location) -> Type {
+        use ast::BinOp::*;
+
+        match op {
+            Implements | Equal | NotEqual => return Type::Bool,
+            RangeExclusive | RangeInclusive | LogicalOr | LogicalAnd | DivRem => return Type::Any,
+            _ => (),
+        }
+
+        let result = match (op, &lhs, &rhs) {
+            (_, &Type::Any, _) | (_, _, &Type::Any) => Some(Type::Any),
+
+            (LessThan, _, _) | (GreaterThan, _, _) | (LessOrEqual, _, _) |
+            (GreaterOrEqual, _, _) => {
+                match (&lhs, &rhs) {
+                    (&Type::Int, &Type::Int) | (&Type::Int, &Type::Float) |
+                    (&Type::Float, &Type::Int) | (&Type::Float, &Type::Float) |
+                    (&Type::String, &Type::String) => Some(Type::Bool),
+                    _ => None,
+                }
+            }
+
+            (Add, &Type::String, &Type::String) => Some(Type::String),
+            (Add, _, _) | (Sub, _, _) | (Mul, _, _) | (Div, _, _) | (Rem, _, _) |
+            (Mod, _, _) => {
+                match (&lhs, &rhs) {
+                    (&Type::Int, &Type::Int) => Some(Type::Int),
+                    (&Type::Int, &Type::Float) | (&Type::Float, &Type::Int) |
+                    (&Type::Float, &Type::Float) => Some(Type::Float),
+                    _ => None,
+                }
+            }
+
+            (_, &Type::Int, &Type::Int) => Some(Type::Int),
+            _ => None,
+        };
+
+        match result {
+            Some(ty) => ty,
+            // Objects may overload operators, so only primitives are checked
+            None if lhs.is_primitive() && rhs.is_primitive() => {
+                self.report(location, TypeError::BadOperands(op, lhs, rhs));
+                Type::Any
+            }
+            None => Type::Any,
+        }
+    }
+
+    fn infer(&mut self, expr: &Expression) -> Type {
+        match *expr {
+            Expression::Nil => Type::Nil,
+            Expression::Literal(ref lit) => self.infer_literal(lit),
+            Expression::Identifier(ref name, loc) => {
+                let declaration = match self.resolution.binding(loc).and_then(|b| b.declaration) {
+                    Some(declaration) => declaration,
+                    None => return Type::Any,
+                };
+                let key = (name.clone(), declaration);
+                if let Some(ty) = self.names.get(&key) {
+                    return ty.clone();
+                }
+                if self.functions.contains_key(&key) {
+                    return Type::Function;
+                }
+                Type::Any
+            }
+            Expression::MemberAccess(ref obj, _) => {
+                self.infer(obj);
+                Type::Any
+            }
+            Expression::IndexAccess(ref obj, ref index) => {
+                let obj = self.infer(obj);
+                for expr in index {
+                    self.infer(expr);
+                }
+                match obj {
+                    Type::Array(elem) => *elem,
+                    _ => Type::Any,
+                }
+            }
+            Expression::FunctionCall(ref callee, ref args) => self.infer_call(callee, args),
+            Expression::ObjectConstructor(ref name, ref obj, loc) => {
+                for field in obj {
+                    self.infer(&field.value);
+                }
+                self.check_fields(name, obj, loc);
+                let declared = self.types.iter().rev().filter_map(|scope| scope.get(name)).next();
+                match declared {
+                    Some(&Type::Named(ref name)) => Type::Named(name.clone()),
+                    _ => Type::Object,
+                }
+            }
+            Expression::BinaryOp(ref lhs, op, ref rhs, loc) => {
+                let lhs = self.infer(lhs);
+                let rhs = self.infer(rhs);
+                self.binary(lhs, op, rhs, loc)
+            }
+            Expression::Negate(ref expr) => {
+                match self.infer(expr) {
+                    Type::Int => Type::Int,
+                    Type::Float => Type::Float,
+                    _ => Type::Any,
+                }
+            }
+            Expression::Not(ref expr) => {
+                self.infer(expr);
+                Type::Bool
+            }
+            Expression::Try(ref expr, _) => {
+                self.infer(expr);
+                Type::Any
+            }
+            Expression::Lambda(ref lambda) => {
+                self.visit_lambda(lambda);
+                Type::Function
+            }
+        }
+    }
+
+    fn infer_literal(&mut self, lit: &Literal) -> Type {
+        match *lit {
+            Literal::Integer(_) | Literal::BigInt(_) => Type::Int,
+            Literal::Suffixed(_, suffix) if suffix.is_float() => Type::Float,
+            Literal::Suffixed(..) => Type::Int,
+            Literal::Float(_) => Type::Float,
+            Literal::Bool(_) => Type::Bool,
+            Literal::String(_) => Type::String,
+            Literal::Symbol(_) => Type::Symbol,
+            Literal::Object(ref obj) => {
+                for field in obj {
+                    self.infer(&field.value);
+                }
+                Type::Object
+            }
+            Literal::Array(ArrayLiteral::List(ref items)) => {
+                let mut elem = None;
+                for item in items {
+                    let ty = self.infer(item);
+                    elem = match elem {
+                        None => Some(ty),
+                        Some(prev) => Some(if prev == ty { prev } else { Type::Any }),
+                    };
+                }
+                Type::Array(Box::new(elem.unwrap_or(Type::Any)))
+            }
+            Literal::Array(ArrayLiteral::Splat(ref value, ref count)) => {
+                self.infer(count);
+                Type::Array(Box::new(self.infer(value)))
+            }
+            Literal::Simd(ref lanes, _) => {
+                for lane in lanes {
+                    self.infer(lane);
+                }
+                Type::Any
+            }
+            Literal::SimdSplat(ref value, _) => {
+                self.infer(value);
+                Type::Any
+            }
+        }
+    }
+
+    fn infer_call(&mut self, callee: &Expression, args: &[Expression]) -> Type {
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.infer(arg)).collect();
+
+        let (name, loc) = match *callee {
+            Expression::Identifier(ref name, loc) => (name, loc),
+            _ => {
+                self.infer(callee);
+                return Type::Any;
+            }
+        };
+
+        let key = match self.resolution.binding(loc).and_then(|b| b.declaration) {
+            Some(declaration) => (name.clone(), declaration),
+            None => return Type::Any,
+        };
+
+        let (params, ret) = match self.functions.get(&key) {
+            Some(sig) => (sig.params.clone(), sig.ret.clone()),
+            None => return Type::Any,
+        };
+
+        if params.len() != args.len() {
+            self.report(loc,
+                        TypeError::ArgumentCount {
+                            expected: params.len(),
+                            found: args.len(),
+                        });
+        }
+
+        for (ty, param) in arg_types.into_iter().zip(&params) {
+            self.expect(ty, param, loc);
+        }
+
+        ret
+    }
+}
+
+fn assign_binop(op: AssignOp) -> Option<BinOp> {
+    Some(match op {
+        AssignOp::Assign => return None,
+        AssignOp::LogicalOr => BinOp::LogicalOr,
+        AssignOp::LogicalAnd => BinOp::LogicalAnd,
+        AssignOp::BitOr => BinOp::BitOr,
+        AssignOp::BitXor => BinOp::BitXor,
+        AssignOp::BitAnd => BinOp::BitAnd,
+        AssignOp::LShiftLeft => BinOp::LShiftLeft,
+        AssignOp::LShiftRight => BinOp::LShiftRight,
+        AssignOp::AShiftRight => BinOp::AShiftRight,
+        AssignOp::Add => BinOp::Add,
+        AssignOp::Sub => BinOp::Sub,
+        AssignOp::Mul => BinOp::Mul,
+        AssignOp::Div => BinOp::Div,
+        AssignOp::Rem => BinOp::Rem,
+        AssignOp::Mod => BinOp::Mod,
+    })
+}
+
+impl<'a> Visitor for Checker<'a> {
+    fn visit_module(&mut self, module: &Module) {
+        let items = match *module {
+            Module::Root { ref items } |
+            Module::Inline { ref items, .. } => items,
+            Module::Extern { .. } => return,
+        };
+
+        let mut types = HashMap::new();
+        for item in items {
+            match *item {
+                Item::TypeDecl(_, ref decl) => {
+                    types.insert(decl.name.clone(), Type::Named(decl.name.clone()));
+                    if let Some(ref fields) = decl.fields {
+                        self.declare_layout(decl, fields);
+                    }
+                }
+                Item::Trait(_, ref tr) => {
+                    types.insert(tr.name.clone(), Type::Trait(tr.name.clone()));
+                }
+                _ => (),
+            }
+        }
+        self.types.push(types);
+
+        // Signatures first, so calls to functions declared further down
+        // are checked too
+        for item in items {
+            if let Item::Function(_, ref func) = *item {
+                let params = func.parameter_types.iter().map(|ty| self.lower_opt(ty)).collect();
+                let ret = self.lower_opt(&func.return_type);
+                let signature = Signature {
+                    params: params,
+                    ret: ret,
+                };
+                self.functions.insert((func.name.clone(), func.location), signature);
+            }
+        }
+
+        // Consts are immutable, so their inferred type is safe to rely on
+        for item in items {
+            if let Item::Const(_, ref name, ref expr, loc) = *item {
+                let ty = self.infer(expr);
+                self.names.insert((name.clone(), loc), ty);
+            }
+        }
+
+        for item in items {
+            match *item {
+                Item::Const(..) => (),
+                _ => self.visit_item(item),
+            }
+        }
+
+        self.types.pop();
+    }
+
+    fn visit_type_impl(&mut self, imp: &TypeImpl) {
+        if let Some(ref interface) = imp.interface {
+            // Only explicit impls count here, or every subtrait impl would
+            // satisfy its own supertraits
+            let supers = self.supertraits.get(interface).cloned().unwrap_or_default();
+            for sup in supers {
+                if !self.reaches(&imp.name, &sup, false) {
+                    let error = TypeError::MissingSupertrait(imp.name.clone(),
+                                                             interface.clone(),
+                                                             sup);
+                    self.report(imp.location, error);
+                }
+            }
+        }
+
+        visit::walk_type_impl(self, imp);
+    }
+
+    fn visit_trait_function(&mut self, func: &TraitFunction) {
+        let params: Vec<Type> = func.parameter_types.iter().map(|ty| self.lower_opt(ty)).collect();
+        let ret = self.lower_opt(&func.return_type);
+
+        self.declare_params(&func.parameters, &func.parameter_locations, &params);
+        self.returns.push(ret);
+        visit::walk_trait_function(self, func);
+        self.returns.pop();
+    }
+
+    fn visit_function(&mut self, func: &Function) {
+        let key = (func.name.clone(), func.location);
+        let (params, ret) = match self.functions.get(&key) {
+            Some(sig) => (sig.params.clone(), sig.ret.clone()),
+            // Methods aren't callable by name, so they have no signature
+            None => {
+                let params = func.parameter_types.iter().map(|ty| self.lower_opt(ty)).collect();
+                (params, self.lower_opt(&func.return_type))
+            }
+        };
+
+        self.declare_params(&func.parameters, &func.parameter_locations, &params);
+        self.returns.push(ret);
+        visit::walk_function(self, func);
+        self.returns.pop();
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        let params: Vec<Type> = lambda.parameter_types.iter().map(|ty| self.lower_opt(ty)).collect();
+        self.declare_params(&lambda.parameters, &lambda.parameter_locations, &params);
+        self.returns.push(Type::Any);
+        visit::walk_lambda(self, lambda);
+        self.returns.pop();
+    }
+
+    fn visit_statement(&mut self, stmnt: &Statement) {
+        match *stmnt {
+            Statement::Declaration(ref name, _, ref ty, ref init, loc) => {
+                let found = init.as_ref().map(|init| self.infer(init));
+                if let Some(ref ty) = *ty {
+                    let ty = self.lower(ty);
+                    if let Some(found) = found {
+                        self.expect(found, &ty, loc);
+                    }
+                    self.names.insert((name.clone(), loc), ty);
+                }
+            }
+            Statement::Assignment(ref lhs, ref extra, op, ref rhs) => {
+                let found = self.infer(rhs);
+                let target = self.infer(lhs);
+                for target in extra {
+                    self.infer(target);
+                }
+
+                if let Expression::Identifier(_, loc) = *lhs {
+                    if extra.is_empty() {
+                        let found = match assign_binop(op) {
+                            Some(op) => self.binary(target.clone(), op, found, loc),
+                            None => found,
+                        };
+                        self.expect(found, &target, loc);
+                    }
+                }
+            }
+            Statement::Return(ref exprs, loc) => {
+                let found: Vec<Type> = exprs.iter().map(|expr| self.infer(expr)).collect();
+                let expected = self.returns.last().cloned().unwrap_or(Type::Any);
+                match found.len() {
+                    0 => self.expect(Type::Nil, &expected, loc),
+                    1 => self.expect(found[0].clone(), &expected, loc),
+                    // Several values can't be annotated yet
+                    _ => (),
+                }
+            }
+            _ => visit::walk_statement(self, stmnt),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        self.infer(expr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::resolve::resolve_module;
+    use lexer::Lexer;
+    use parser::parse_Module;
+
+    fn check(source: &str) -> Vec<(usize, String)> {
+        let ast = parse_Module(Lexer::new(source)).unwrap();
+        let resolution = resolve_module(&ast, &["print"]);
+        check_module(&ast, &resolution)
+            .into_iter()
+            .map(|diag| (diag.location.line, diag.error.to_string()))
+            .collect()
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        check(source).into_iter().map(|(_, msg)| msg).collect()
+    }
+
+    #[test]
+    fn annotations_are_checked() {
+        assert_eq!(check("fn f(x: int, y: [string]) -> float {
+    let a: string = x;
+    let b: int = 1.5;
+    let c: float = 1;
+    let d: [string] = y;
+    a = 2;
+    return \"no\";
+}
+fn g() {
+    f(1, [\"a\"]);
+    f(\"1\", [2]);
+    f(1);
+}"),
+                   vec![(2, "expected `string`, found `int`".into()),
+                        (3, "expected `int`, found `float`".into()),
+                        (6, "expected `string`, found `int`".into()),
+                        (7, "expected `float`, found `string`".into()),
+                        (11, "expected `int`, found `string`".into()),
+                        (11, "expected `[string]`, found `[int]`".into()),
+                        (12, "expected 2 arguments, found 1".into())]);
+    }
+
+    #[test]
+    fn unannotated_code_is_any() {
+        assert_eq!(errors("fn id(x) { return x; }
+fn f(n: int) -> int {
+    let s = \"text\";
+    let i: int = id(s);
+    let j: int = s;
+    return id(\"also text\");
+}
+fn g(x) {
+    return x + 1 + \"a\";
+}"),
+                   Vec::<String>::new());
+
+        // Inferred literals are still checked in operators
+        assert_eq!(errors("fn f() { return 1 + \"a\"; }"),
+                   vec!["can't apply `+` to `int` and `string`"]);
+    }
+
+    #[test]
+    fn traits_are_interface_types() {
+        assert_eq!(check("trait Shape { fn area(self); }
+trait Polygon: Shape { }
+type Square;
+type Triangle;
+type Circle;
+impl Shape for Square { fn area(self) { return 1; } }
+impl Shape for Triangle { fn area(self) { return 1; } }
+impl Polygon for Triangle { }
+fn measure(s: Shape) -> int { return 1; }
+fn corners(p: Polygon) -> int { return 3; }
+fn f() {
+    measure(new_object Square {});
+    measure(new_object Triangle {});
+    measure(new_object Circle {});
+    corners(new_object Square {});
+    corners(new_object Triangle {});
+    let p: Polygon = new_object Triangle {};
+    let s: Shape = p;
+}"),
+                   vec![(14, "expected `Shape`, found `Circle`".into()),
+                        (15, "expected `Polygon`, found `Square`".into())]);
+    }
+
+    #[test]
+    fn object_fields_are_checked() {
+        assert_eq!(check("type Person { name, can_fly = false }
+type Loose;
+fn f() {
+    let a = new_object Person { name: \"a\" };
+    let b = new_object Person { name: \"b\", can_fly: true };
+    let c = new_object Person { name: \"c\", cna_fly: true };
+    let d = new_object Person { can_fly: true };
+    let e = new_object Loose { anything: 1 };
+    return [a, b, c, d, e];
+}"),
+                   vec![(6, "`Person` has no field `cna_fly`".into()),
+                        (7, "missing field `name`, which `Person` has no default for".into())]);
+
+        assert_eq!(errors("type T { a, a }"), vec!["field `a` is declared more than once"]);
+    }
+}
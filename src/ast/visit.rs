@@ -0,0 +1,617 @@
+//! Traversal of the AST.
+//!
+//! `Visitor` walks the tree by shared reference and `VisitorMut` by mutable
+//! reference. Every `visit_*` method defaults to the matching `walk_*`
+//! function, which visits the node's children, so a pass only overrides the
+//! nodes it cares about. An override that still wants the children visited
+//! calls the `walk_*` function itself, before or after its own work.
+
+use ast::*;
+
+pub trait Visitor {
+    fn visit_module(&mut self, module: &Module) {
+        walk_module(self, module)
+    }
+
+    fn visit_item(&mut self, item: &Item) {
+        walk_item(self, item)
+    }
+
+    fn visit_attribute(&mut self, attr: &Attribute) {
+        walk_attribute(self, attr)
+    }
+
+    fn visit_type_decl(&mut self, decl: &TypeDecl) {
+        walk_type_decl(self, decl)
+    }
+
+    fn visit_type_impl(&mut self, imp: &TypeImpl) {
+        walk_type_impl(self, imp)
+    }
+
+    fn visit_trait(&mut self, tr: &Trait) {
+        walk_trait(self, tr)
+    }
+
+    fn visit_trait_function(&mut self, func: &TraitFunction) {
+        walk_trait_function(self, func)
+    }
+
+    fn visit_function(&mut self, func: &Function) {
+        walk_function(self, func)
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_statement(&mut self, stmnt: &Statement) {
+        walk_statement(self, stmnt)
+    }
+
+    fn visit_if_else(&mut self, if_else: &IfElse) {
+        walk_if_else(self, if_else)
+    }
+
+    fn visit_loop(&mut self, l: &Loop) {
+        walk_loop(self, l)
+    }
+
+    fn visit_for_loop(&mut self, l: &ForLoop) {
+        walk_for_loop(self, l)
+    }
+
+    fn visit_while_loop(&mut self, l: &WhileLoop) {
+        walk_while_loop(self, l)
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr)
+    }
+
+    fn visit_lambda(&mut self, lambda: &Lambda) {
+        walk_lambda(self, lambda)
+    }
+
+    fn visit_literal(&mut self, lit: &Literal) {
+        walk_literal(self, lit)
+    }
+
+    fn visit_array_literal(&mut self, arr: &ArrayLiteral) {
+        walk_array_literal(self, arr)
+    }
+
+    fn visit_object_literal(&mut self, obj: &ObjectLiteral) {
+        walk_object_literal(self, obj)
+    }
+}
+
+pub fn walk_module<V: Visitor + ?Sized>(v: &mut V, module: &Module) {
+    match *module {
+        Module::Root { ref items } |
+        Module::Inline { ref items, .. } => {
+            for item in items {
+                v.visit_item(item);
+            }
+        }
+        Module::Extern { .. } => (),
+    }
+}
+
+pub fn walk_item<V: Visitor + ?Sized>(v: &mut V, item: &Item) {
+    match *item {
+        Item::Use(ref expr) => v.visit_expression(expr),
+        Item::Module(ref module) => v.visit_module(module),
+        Item::TypeDecl(ref attrs, ref decl) => {
+            walk_attributes(v, attrs);
+            v.visit_type_decl(decl);
+        }
+        Item::TypeImpl(ref attrs, ref imp) => {
+            walk_attributes(v, attrs);
+            v.visit_type_impl(imp);
+        }
+        Item::Function(ref attrs, ref func) => {
+            walk_attributes(v, attrs);
+            v.visit_function(func);
+        }
+        Item::Trait(ref attrs, ref tr) => {
+            walk_attributes(v, attrs);
+            v.visit_trait(tr);
+        }
+        Item::Const(ref attrs, _, ref expr, _) => {
+            walk_attributes(v, attrs);
+            v.visit_expression(expr);
+        }
+        Item::Extern(..) |
+        Item::DocComment(_) |
+        Item::ModuleDocComment(_) => (),
+    }
+}
+
+fn walk_attributes<V: Visitor + ?Sized>(v: &mut V, attrs: &[Attribute]) {
+    for attr in attrs {
+        v.visit_attribute(attr);
+    }
+}
+
+pub fn walk_attribute<V: Visitor + ?Sized>(v: &mut V, attr: &Attribute) {
+    match *attr {
+        Attribute::Named(_, ref args) => walk_attributes(v, args),
+        Attribute::Map(ref key, ref value) => {
+            v.visit_attribute(key);
+            v.visit_attribute(value);
+        }
+        Attribute::Identifier(_) |
+        Attribute::String(_) => (),
+    }
+}
+
+pub fn walk_type_decl<V: Visitor + ?Sized>(v: &mut V, decl: &TypeDecl) {
+    for field in decl.fields.iter().flat_map(|fields| fields) {
+        if let Some(ref default) = field.default {
+            v.visit_expression(default);
+        }
+    }
+}
+
+pub fn walk_type_impl<V: Visitor + ?Sized>(v: &mut V, imp: &TypeImpl) {
+    walk_assoc_consts(v, &imp.constants);
+    for func in &imp.methods {
+        v.visit_function(func);
+    }
+}
+
+pub fn walk_trait<V: Visitor + ?Sized>(v: &mut V, tr: &Trait) {
+    walk_assoc_consts(v, &tr.constants);
+    for func in &tr.methods {
+        v.visit_trait_function(func);
+    }
+}
+
+fn walk_assoc_consts<V: Visitor + ?Sized>(v: &mut V, consts: &[AssocConst]) {
+    for value in consts.iter().filter_map(|c| c.value.as_ref()) {
+        v.visit_expression(value);
+    }
+}
+
+pub fn walk_trait_function<V: Visitor + ?Sized>(v: &mut V, func: &TraitFunction) {
+    if let Some(ref body) = func.body {
+        v.visit_block(body);
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(v: &mut V, func: &Function) {
+    v.visit_block(&func.body);
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(v: &mut V, block: &Block) {
+    for stmnt in &block.statements {
+        v.visit_statement(stmnt);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(v: &mut V, stmnt: &Statement) {
+    match *stmnt {
+        Statement::Use(ref expr) |
+        Statement::Expression(ref expr) |
+        Statement::Throw(ref expr, _) => v.visit_expression(expr),
+        Statement::Declaration(_, _, _, ref init, _) => {
+            if let Some(ref init) = *init {
+                v.visit_expression(init);
+            }
+        }
+        Statement::Assignment(ref lhs, ref extra, _, ref rhs) => {
+            v.visit_expression(lhs);
+            for expr in extra {
+                v.visit_expression(expr);
+            }
+            v.visit_expression(rhs);
+        }
+        Statement::IfElse(ref if_else) => v.visit_if_else(if_else),
+        Statement::Loop(ref l) => v.visit_loop(l),
+        Statement::ForLoop(ref l) => v.visit_for_loop(l),
+        Statement::WhileLoop(ref l) => v.visit_while_loop(l),
+        Statement::Return(ref exprs, _) => {
+            for expr in exprs {
+                v.visit_expression(expr);
+            }
+        }
+        Statement::Break(..) |
+        Statement::Continue(..) => (),
+    }
+}
+
+pub fn walk_if_else<V: Visitor + ?Sized>(v: &mut V, if_else: &IfElse) {
+    v.visit_expression(&if_else.condition);
+    v.visit_block(&if_else.if_block);
+    if let Some(ref else_block) = if_else.else_block {
+        v.visit_block(else_block);
+    }
+}
+
+pub fn walk_loop<V: Visitor + ?Sized>(v: &mut V, l: &Loop) {
+    v.visit_block(&l.block);
+}
+
+pub fn walk_for_loop<V: Visitor + ?Sized>(v: &mut V, l: &ForLoop) {
+    v.visit_expression(&l.iterator);
+    v.visit_block(&l.block);
+}
+
+pub fn walk_while_loop<V: Visitor + ?Sized>(v: &mut V, l: &WhileLoop) {
+    v.visit_expression(&l.condition);
+    v.visit_block(&l.block);
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(v: &mut V, expr: &Expression) {
+    match *expr {
+        Expression::Literal(ref lit) => v.visit_literal(lit),
+        Expression::MemberAccess(ref lhs, _) |
+        Expression::Negate(ref lhs) |
+        Expression::Not(ref lhs) |
+        Expression::Try(ref lhs, _) => v.visit_expression(lhs),
+        Expression::IndexAccess(ref lhs, ref args) |
+        Expression::FunctionCall(ref lhs, ref args) => {
+            v.visit_expression(lhs);
+            for arg in args {
+                v.visit_expression(arg);
+            }
+        }
+        Expression::ObjectConstructor(_, ref obj, _) => v.visit_object_literal(obj),
+        Expression::BinaryOp(ref lhs, _, ref rhs, _) => {
+            v.visit_expression(lhs);
+            v.visit_expression(rhs);
+        }
+        Expression::Lambda(ref lambda) => v.visit_lambda(lambda),
+        Expression::Nil |
+        Expression::Identifier(..) => (),
+    }
+}
+
+pub fn walk_lambda<V: Visitor + ?Sized>(v: &mut V, lambda: &Lambda) {
+    v.visit_block(&lambda.body);
+}
+
+pub fn walk_literal<V: Visitor + ?Sized>(v: &mut V, lit: &Literal) {
+    match *lit {
+        Literal::Object(ref obj) => v.visit_object_literal(obj),
+        Literal::Array(ref arr) => v.visit_array_literal(arr),
+        Literal::Simd(ref lanes, _) => {
+            for lane in lanes {
+                v.visit_expression(lane);
+            }
+        }
+        Literal::SimdSplat(ref value, _) => v.visit_expression(value),
+        Literal::Integer(_) |
+        Literal::BigInt(_) |
+        Literal::Suffixed(..) |
+        Literal::Float(_) |
+        Literal::Bool(_) |
+        Literal::String(_) |
+        Literal::Symbol(_) => (),
+    }
+}
+
+pub fn walk_array_literal<V: Visitor + ?Sized>(v: &mut V, arr: &ArrayLiteral) {
+    match *arr {
+        ArrayLiteral::List(ref exprs) => {
+            for expr in exprs {
+                v.visit_expression(expr);
+            }
+        }
+        ArrayLiteral::Splat(ref value, ref count) => {
+            v.visit_expression(value);
+            v.visit_expression(count);
+        }
+    }
+}
+
+pub fn walk_object_literal<V: Visitor + ?Sized>(v: &mut V, obj: &ObjectLiteral) {
+    for field in obj {
+        v.visit_expression(&field.value);
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_module(&mut self, module: &mut Module) {
+        walk_module_mut(self, module)
+    }
+
+    fn visit_item(&mut self, item: &mut Item) {
+        walk_item_mut(self, item)
+    }
+
+    fn visit_attribute(&mut self, attr: &mut Attribute) {
+        walk_attribute_mut(self, attr)
+    }
+
+    fn visit_type_decl(&mut self, decl: &mut TypeDecl) {
+        walk_type_decl_mut(self, decl)
+    }
+
+    fn visit_type_impl(&mut self, imp: &mut TypeImpl) {
+        walk_type_impl_mut(self, imp)
+    }
+
+    fn visit_trait(&mut self, tr: &mut Trait) {
+        walk_trait_mut(self, tr)
+    }
+
+    fn visit_trait_function(&mut self, func: &mut TraitFunction) {
+        walk_trait_function_mut(self, func)
+    }
+
+    fn visit_function(&mut self, func: &mut Function) {
+        walk_function_mut(self, func)
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_statement(&mut self, stmnt: &mut Statement) {
+        walk_statement_mut(self, stmnt)
+    }
+
+    fn visit_if_else(&mut self, if_else: &mut IfElse) {
+        walk_if_else_mut(self, if_else)
+    }
+
+    fn visit_loop(&mut self, l: &mut Loop) {
+        walk_loop_mut(self, l)
+    }
+
+    fn visit_for_loop(&mut self, l: &mut ForLoop) {
+        walk_for_loop_mut(self, l)
+    }
+
+    fn visit_while_loop(&mut self, l: &mut WhileLoop) {
+        walk_while_loop_mut(self, l)
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr)
+    }
+
+    fn visit_lambda(&mut self, lambda: &mut Lambda) {
+        walk_lambda_mut(self, lambda)
+    }
+
+    fn visit_literal(&mut self, lit: &mut Literal) {
+        walk_literal_mut(self, lit)
+    }
+
+    fn visit_array_literal(&mut self, arr: &mut ArrayLiteral) {
+        walk_array_literal_mut(self, arr)
+    }
+
+    fn visit_object_literal(&mut self, obj: &mut ObjectLiteral) {
+        walk_object_literal_mut(self, obj)
+    }
+}
+
+pub fn walk_module_mut<V: VisitorMut + ?Sized>(v: &mut V, module: &mut Module) {
+    match *module {
+        Module::Root { ref mut items } |
+        Module::Inline { ref mut items, .. } => {
+            for item in items {
+                v.visit_item(item);
+            }
+        }
+        Module::Extern { .. } => (),
+    }
+}
+
+pub fn walk_item_mut<V: VisitorMut + ?Sized>(v: &mut V, item: &mut Item) {
+    match *item {
+        Item::Use(ref mut expr) => v.visit_expression(expr),
+        Item::Module(ref mut module) => v.visit_module(module),
+        Item::TypeDecl(ref mut attrs, ref mut decl) => {
+            walk_attributes_mut(v, attrs);
+            v.visit_type_decl(decl);
+        }
+        Item::TypeImpl(ref mut attrs, ref mut imp) => {
+            walk_attributes_mut(v, attrs);
+            v.visit_type_impl(imp);
+        }
+        Item::Function(ref mut attrs, ref mut func) => {
+            walk_attributes_mut(v, attrs);
+            v.visit_function(func);
+        }
+        Item::Trait(ref mut attrs, ref mut tr) => {
+            walk_attributes_mut(v, attrs);
+            v.visit_trait(tr);
+        }
+        Item::Const(ref mut attrs, _, ref mut expr, _) => {
+            walk_attributes_mut(v, attrs);
+            v.visit_expression(expr);
+        }
+        Item::Extern(..) |
+        Item::DocComment(_) |
+        Item::ModuleDocComment(_) => (),
+    }
+}
+
+fn walk_attributes_mut<V: VisitorMut + ?Sized>(v: &mut V, attrs: &mut [Attribute]) {
+    for attr in attrs {
+        v.visit_attribute(attr);
+    }
+}
+
+pub fn walk_attribute_mut<V: VisitorMut + ?Sized>(v: &mut V, attr: &mut Attribute) {
+    match *attr {
+        Attribute::Named(_, ref mut args) => walk_attributes_mut(v, args),
+        Attribute::Map(ref mut key, ref mut value) => {
+            v.visit_attribute(key);
+            v.visit_attribute(value);
+        }
+        Attribute::Identifier(_) |
+        Attribute::String(_) => (),
+    }
+}
+
+pub fn walk_type_decl_mut<V: VisitorMut + ?Sized>(v: &mut V, decl: &mut TypeDecl) {
+    for field in decl.fields.iter_mut().flat_map(|fields| fields) {
+        if let Some(ref mut default) = field.default {
+            v.visit_expression(default);
+        }
+    }
+}
+
+pub fn walk_type_impl_mut<V: VisitorMut + ?Sized>(v: &mut V, imp: &mut TypeImpl) {
+    walk_assoc_consts_mut(v, &mut imp.constants);
+    for func in &mut imp.methods {
+        v.visit_function(func);
+    }
+}
+
+pub fn walk_trait_mut<V: VisitorMut + ?Sized>(v: &mut V, tr: &mut Trait) {
+    walk_assoc_consts_mut(v, &mut tr.constants);
+    for func in &mut tr.methods {
+        v.visit_trait_function(func);
+    }
+}
+
+fn walk_assoc_consts_mut<V: VisitorMut + ?Sized>(v: &mut V, consts: &mut [AssocConst]) {
+    for value in consts.iter_mut().filter_map(|c| c.value.as_mut()) {
+        v.visit_expression(value);
+    }
+}
+
+pub fn walk_trait_function_mut<V: VisitorMut + ?Sized>(v: &mut V, func: &mut TraitFunction) {
+    if let Some(ref mut body) = func.body {
+        v.visit_block(body);
+    }
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(v: &mut V, func: &mut Function) {
+    v.visit_block(&mut func.body);
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(v: &mut V, block: &mut Block) {
+    for stmnt in &mut block.statements {
+        v.visit_statement(stmnt);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(v: &mut V, stmnt: &mut Statement) {
+    match *stmnt {
+        Statement::Use(ref mut expr) |
+        Statement::Expression(ref mut expr) |
+        Statement::Throw(ref mut expr, _) => v.visit_expression(expr),
+        Statement::Declaration(_, _, _, ref mut init, _) => {
+            if let Some(ref mut init) = *init {
+                v.visit_expression(init);
+            }
+        }
+        Statement::Assignment(ref mut lhs, ref mut extra, _, ref mut rhs) => {
+            v.visit_expression(lhs);
+            for expr in extra {
+                v.visit_expression(expr);
+            }
+            v.visit_expression(rhs);
+        }
+        Statement::IfElse(ref mut if_else) => v.visit_if_else(if_else),
+        Statement::Loop(ref mut l) => v.visit_loop(l),
+        Statement::ForLoop(ref mut l) => v.visit_for_loop(l),
+        Statement::WhileLoop(ref mut l) => v.visit_while_loop(l),
+        Statement::Return(ref mut exprs, _) => {
+            for expr in exprs {
+                v.visit_expression(expr);
+            }
+        }
+        Statement::Break(..) |
+        Statement::Continue(..) => (),
+    }
+}
+
+pub fn walk_if_else_mut<V: VisitorMut + ?Sized>(v: &mut V, if_else: &mut IfElse) {
+    v.visit_expression(&mut if_else.condition);
+    v.visit_block(&mut if_else.if_block);
+    if let Some(ref mut else_block) = if_else.else_block {
+        v.visit_block(else_block);
+    }
+}
+
+pub fn walk_loop_mut<V: VisitorMut + ?Sized>(v: &mut V, l: &mut Loop) {
+    v.visit_block(&mut l.block);
+}
+
+pub fn walk_for_loop_mut<V: VisitorMut + ?Sized>(v: &mut V, l: &mut ForLoop) {
+    v.visit_expression(&mut l.iterator);
+    v.visit_block(&mut l.block);
+}
+
+pub fn walk_while_loop_mut<V: VisitorMut + ?Sized>(v: &mut V, l: &mut WhileLoop) {
+    v.visit_expression(&mut l.condition);
+    v.visit_block(&mut l.block);
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(v: &mut V, expr: &mut Expression) {
+    match *expr {
+        Expression::Literal(ref mut lit) => v.visit_literal(lit),
+        Expression::MemberAccess(ref mut lhs, _) |
+        Expression::Negate(ref mut lhs) |
+        Expression::Not(ref mut lhs) |
+        Expression::Try(ref mut lhs, _) => v.visit_expression(lhs),
+        Expression::IndexAccess(ref mut lhs, ref mut args) |
+        Expression::FunctionCall(ref mut lhs, ref mut args) => {
+            v.visit_expression(lhs);
+            for arg in args {
+                v.visit_expression(arg);
+            }
+        }
+        Expression::ObjectConstructor(_, ref mut obj, _) => v.visit_object_literal(obj),
+        Expression::BinaryOp(ref mut lhs, _, ref mut rhs, _) => {
+            v.visit_expression(lhs);
+            v.visit_expression(rhs);
+        }
+        Expression::Lambda(ref mut lambda) => v.visit_lambda(lambda),
+        Expression::Nil |
+        Expression::Identifier(..) => (),
+    }
+}
+
+pub fn walk_lambda_mut<V: VisitorMut + ?Sized>(v: &mut V, lambda: &mut Lambda) {
+    v.visit_block(&mut lambda.body);
+}
+
+pub fn walk_literal_mut<V: VisitorMut + ?Sized>(v: &mut V, lit: &mut Literal) {
+    match *lit {
+        Literal::Object(ref mut obj) => v.visit_object_literal(obj),
+        Literal::Array(ref mut arr) => v.visit_array_literal(arr),
+        Literal::Simd(ref mut lanes, _) => {
+            for lane in lanes {
+                v.visit_expression(lane);
+            }
+        }
+        Literal::SimdSplat(ref mut value, _) => v.visit_expression(value),
+        Literal::Integer(_) |
+        Literal::BigInt(_) |
+        Literal::Suffixed(..) |
+        Literal::Float(_) |
+        Literal::Bool(_) |
+        Literal::String(_) |
+        Literal::Symbol(_) => (),
+    }
+}
+
+pub fn walk_array_literal_mut<V: VisitorMut + ?Sized>(v: &mut V, arr: &mut ArrayLiteral) {
+    match *arr {
+        ArrayLiteral::List(ref mut exprs) => {
+            for expr in exprs {
+                v.visit_expression(expr);
+            }
+        }
+        ArrayLiteral::Splat(ref mut value, ref mut count) => {
+            v.visit_expression(value);
+            v.visit_expression(count);
+        }
+    }
+}
+
+pub fn walk_object_literal_mut<V: VisitorMut + ?Sized>(v: &mut V, obj: &mut ObjectLiteral) {
+    for field in obj {
+        v.visit_expression(&mut field.value);
+    }
+}
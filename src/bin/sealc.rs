@@ -1,12 +1,108 @@
 extern crate seal_lang;
 extern crate serde_json;
 
+use seal_lang::ast::{optimize, resolve, typeck};
+use seal_lang::ast::cfg::Cfg;
+use seal_lang::ast::visit::Visitor;
+use seal_lang::lexer::Location;
+use seal_lang::parser::ParseError;
+use seal_lang::vm::runtime::PRELUDE;
+use std::env;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+fn warn(name: &str, loc: Location, msg: &Display) {
+    writeln!(io::stderr(), "{}:{}:{}: warning: {}", name, loc.line, loc.column, msg).unwrap();
+}
+
+fn error(name: &str, loc: Location, msg: &Display) {
+    writeln!(io::stderr(), "{}:{}:{}: error: {}", name, loc.line, loc.column, msg).unwrap();
+}
+
+/// Prints the control-flow graph of every function and method.
+struct DotDump;
+
+impl Visitor for DotDump {
+    fn visit_function(&mut self, func: &seal_lang::ast::Function) {
+        print!("{}", Cfg::build(func).to_dot(&func.name));
+    }
+}
+
 fn main() {
-    let input = include_str!("../../scripts/example.seal");
-    let lexer = seal_lang::lexer::Lexer::new(input);
+    let mut fold = true;
+    let mut optimize = false;
+    let mut dot = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match &arg[..] {
+            "--no-fold" => fold = false,
+            "-O" => optimize = true,
+            "--cfg" => dot = true,
+            _ => path = Some(arg),
+        }
+    }
+
+    let mut input = String::new();
+    let name = match path {
+        Some(path) => {
+            if let Err(err) = File::open(&path).and_then(|mut f| f.read_to_string(&mut input)) {
+                writeln!(io::stderr(), "{}: {}", path, err).unwrap();
+                process::exit(2);
+            }
+            path
+        }
+        None => {
+            input.push_str(include_str!("../../scripts/example.seal"));
+            "example.seal".into()
+        }
+    };
+
+    let lexer = seal_lang::lexer::Lexer::new(&input);
+
+    let mut ast = match seal_lang::parser::parse_Module(lexer) {
+        Ok(ast) => ast,
+        Err(ParseError::User { error: err }) => {
+            error(&name, err.location(), &err);
+            process::exit(1);
+        }
+        Err(err) => {
+            writeln!(io::stderr(), "{}: parse error: {:?}", name, err).unwrap();
+            process::exit(1);
+        }
+    };
+
+    let resolution = resolve::resolve_module(&ast, PRELUDE);
+    for diag in &resolution.diagnostics {
+        if diag.error.is_warning() {
+            warn(&name, diag.location, &diag.error);
+        } else {
+            error(&name, diag.location, &diag.error);
+        }
+    }
+    for diag in typeck::check_module(&ast, &resolution) {
+        error(&name, diag.location, &diag.error);
+    }
 
-    let mut ast = seal_lang::parser::parse_Module(lexer).unwrap();
-    seal_lang::ast::constant_folding::fold_module(&mut ast);
+    if optimize {
+        for diag in optimize::optimize_module(&mut ast) {
+            match diag {
+                optimize::Diagnostic::Fold(diag) => warn(&name, diag.location, &diag.error),
+                optimize::Diagnostic::Unreachable(loc) => {
+                    warn(&name, loc, &"statements after this are unreachable")
+                }
+            }
+        }
+    } else if fold {
+        for diag in seal_lang::ast::constant_folding::fold_module(&mut ast) {
+            warn(&name, diag.location, &diag.error);
+        }
+    }
 
-    println!("{}", serde_json::to_string_pretty(&ast).unwrap());
+    if dot {
+        DotDump.visit_module(&ast);
+    } else {
+        println!("{}", serde_json::to_string_pretty(&ast).unwrap());
+    }
 }
@@ -0,0 +1,171 @@
+extern crate seal_lang;
+extern crate serde_json;
+
+use seal_lang::ast::printer::{self, Config};
+use seal_lang::cst::comments;
+use seal_lang::lexer::Lexer;
+use serde_json::Value;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+const USAGE: &'static str = "usage: sealfmt [--check] [--width N] [--indent N] [--tabs] [FILE...]
+
+Formats Seal source files in place, or stdin to stdout when no files are given.
+With --check nothing is written; files that aren't formatted are listed and
+the exit status is 1.";
+
+struct Options {
+    check: bool,
+    config: Config,
+    files: Vec<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options {
+        check: false,
+        config: Config::default(),
+        files: vec![],
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--check" => opts.check = true,
+            "--tabs" => opts.config.hard_tabs = true,
+            "--width" => opts.config.max_width = numeric_arg(&arg, args.next())?,
+            "--indent" => opts.config.indent_width = numeric_arg(&arg, args.next())?,
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ if arg.starts_with("-") => return Err(format!("unknown option `{}`\n\n{}", arg, USAGE)),
+            _ => opts.files.push(arg),
+        }
+    }
+
+    Ok(opts)
+}
+
+fn numeric_arg(flag: &str, value: Option<String>) -> Result<usize, String> {
+    value.as_ref()
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| format!("`{}` expects a number", flag))
+}
+
+/// Formats `source`, checking that the output parses back to the same tree.
+fn format_source(source: &str, config: &Config) -> Result<String, String> {
+    let ast = seal_lang::parser::parse_Module(Lexer::new(source))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+    // The printer works from the AST, which doesn't keep ordinary comments
+    let printed = printer::print_module(&ast, config);
+    let formatted = comments::reattach(source, &printed)
+        .map_err(|e| format!("couldn't reattach comments: {:?}", e))?;
+
+    let reparsed = seal_lang::parser::parse_Module(Lexer::new(&formatted))
+        .map_err(|e| format!("formatted output doesn't parse: {:?}", e))?;
+    let same = match (serde_json::to_value(&ast), serde_json::to_value(&reparsed)) {
+        (Ok(mut a), Ok(mut b)) => {
+            strip_locations(&mut a);
+            strip_locations(&mut b);
+            a == b
+        }
+        _ => false,
+    };
+    if !same {
+        return Err("formatted output parses to a different tree".into());
+    }
+
+    Ok(formatted)
+}
+
+/// Blanks out source locations, which naturally move when reformatting.
+fn strip_locations(value: &mut Value) {
+    let is_location = match *value {
+        Value::Object(ref map) => {
+            map.len() == 3 && map.contains_key("line") && map.contains_key("column") &&
+            map.contains_key("index")
+        }
+        _ => false,
+    };
+
+    if is_location {
+        *value = Value::Null;
+        return;
+    }
+
+    match *value {
+        Value::Object(ref mut map) => {
+            for (_, v) in map.iter_mut() {
+                strip_locations(v);
+            }
+        }
+        Value::Array(ref mut vec) => {
+            for v in vec.iter_mut() {
+                strip_locations(v);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn read_file(path: &str) -> io::Result<String> {
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+    Ok(source)
+}
+
+fn main() {
+    let opts = match parse_args() {
+        Ok(opts) => opts,
+        Err(msg) => {
+            writeln!(io::stderr(), "{}", msg).unwrap();
+            process::exit(2);
+        }
+    };
+
+    if opts.files.is_empty() {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).unwrap();
+        match format_source(&source, &opts.config) {
+            Ok(ref formatted) if opts.check && *formatted != source => process::exit(1),
+            Ok(ref formatted) if !opts.check => print!("{}", formatted),
+            Ok(_) => (),
+            Err(msg) => {
+                writeln!(io::stderr(), "<stdin>: {}", msg).unwrap();
+                process::exit(2);
+            }
+        }
+        return;
+    }
+
+    let mut status = 0;
+    for path in &opts.files {
+        let result = read_file(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| format_source(&source, &opts.config).map(|f| (source, f)));
+
+        let (source, formatted) = match result {
+            Ok(pair) => pair,
+            Err(msg) => {
+                writeln!(io::stderr(), "{}: {}", path, msg).unwrap();
+                status = 2;
+                continue;
+            }
+        };
+
+        if formatted == source {
+            continue;
+        }
+
+        if opts.check {
+            println!("{}", path);
+            if status == 0 {
+                status = 1;
+            }
+        } else if let Err(e) = File::create(path).and_then(|mut f| f.write_all(formatted.as_bytes())) {
+            writeln!(io::stderr(), "{}: {}", path, e).unwrap();
+            status = 2;
+        }
+    }
+
+    process::exit(status);
+}
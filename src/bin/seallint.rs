@@ -0,0 +1,109 @@
+extern crate seal_lang;
+
+use seal_lang::ast::{lint, resolve};
+use seal_lang::lexer::{Lexer, Location};
+use seal_lang::vm::runtime::PRELUDE;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::process;
+
+const USAGE: &'static str = "usage: seallint [--global NAME]... [FILE...]
+
+Checks Seal source files, or stdin when no files are given, for code that is
+valid but almost certainly a mistake. Names the runtime provides, like `print`,
+are always defined; --global adds another. The exit status is 1 if anything
+was reported, and 2 if a file couldn't be read or parsed.";
+
+struct Options {
+    globals: Vec<String>,
+    files: Vec<String>,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut opts = Options {
+        globals: PRELUDE.iter().map(|&name| name.to_string()).collect(),
+        files: vec![],
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--global" => {
+                match args.next() {
+                    Some(name) => opts.globals.push(name),
+                    None => return Err("`--global` expects a name".into()),
+                }
+            }
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ if arg.starts_with("-") => return Err(format!("unknown option `{}`\n\n{}", arg, USAGE)),
+            _ => opts.files.push(arg),
+        }
+    }
+
+    Ok(opts)
+}
+
+/// Lints `source`, returning whether anything was reported.
+fn lint_source(name: &str, source: &str, globals: &[&str]) -> Result<bool, String> {
+    let ast = seal_lang::parser::parse_Module(Lexer::new(source))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+
+    let resolution = resolve::resolve_module(&ast, globals);
+    let mut reports: Vec<(Location, &str, String)> = vec![];
+    for diag in &resolution.diagnostics {
+        let severity = if diag.error.is_warning() { "warning" } else { "error" };
+        reports.push((diag.location, severity, diag.error.to_string()));
+    }
+    for diag in lint::lint_module(&ast, &resolution) {
+        reports.push((diag.location, "warning", diag.to_string()));
+    }
+
+    reports.sort_by_key(|&(loc, _, _)| loc.index);
+    for &(loc, severity, ref msg) in &reports {
+        println!("{}:{}:{}: {}: {}", name, loc.line, loc.column, severity, msg);
+    }
+    Ok(!reports.is_empty())
+}
+
+fn read_file(path: &str) -> io::Result<String> {
+    let mut source = String::new();
+    File::open(path)?.read_to_string(&mut source)?;
+    Ok(source)
+}
+
+fn main() {
+    let opts = match parse_args() {
+        Ok(opts) => opts,
+        Err(msg) => {
+            writeln!(io::stderr(), "{}", msg).unwrap();
+            process::exit(2);
+        }
+    };
+    let globals: Vec<&str> = opts.globals.iter().map(|name| &name[..]).collect();
+
+    let inputs: Vec<(String, io::Result<String>)> = if opts.files.is_empty() {
+        let mut source = String::new();
+        let result = io::stdin().read_to_string(&mut source).map(|_| source);
+        vec![("<stdin>".into(), result)]
+    } else {
+        opts.files.iter().map(|path| (path.clone(), read_file(path))).collect()
+    };
+
+    let mut status = 0;
+    for (name, source) in inputs {
+        let result = source.map_err(|e| e.to_string())
+            .and_then(|source| lint_source(&name, &source, &globals));
+
+        match result {
+            Ok(true) if status == 0 => status = 1,
+            Ok(_) => (),
+            Err(msg) => {
+                writeln!(io::stderr(), "{}: {}", name, msg).unwrap();
+                status = 2;
+            }
+        }
+    }
+
+    process::exit(status);
+}
@@ -0,0 +1,227 @@
+//! Putting ordinary comments back into reformatted source.
+//!
+//! The printer works from the `ast`, which doesn't keep ordinary comments.
+//! `reattach` lines up the significant tokens of the original text with
+//! those of the printed text, and puts each comment in the same place
+//! relative to its token: on its own line above it, just before or after
+//! it, or at the end of its line. Tokens the printer added or dropped, like
+//! trailing commas, don't line up with anything, so comments on a dropped
+//! token move to the next token that does.
+
+use cst::{CstError, SyntaxToken, SyntaxTree, Trivia};
+use lexer::TokenType;
+
+/// How many tokens to skip on either side looking for the next pair that
+/// lines up.
+const LOOKAHEAD: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Placement {
+    /// On its own line, above the line the token starts on
+    Above,
+    /// Just before the token, on the same line
+    Before,
+    /// Just after the token, on the same line
+    After,
+    /// After everything else on the line the token ends on
+    EndOfLine,
+}
+
+struct Comment<'input> {
+    text: &'input str,
+    placement: Placement,
+}
+
+/// `printed` with the comments from `source` put back, where `printed` is
+/// `source` reformatted.
+pub fn reattach(source: &str, printed: &str) -> Result<String, CstError> {
+    let original = SyntaxTree::parse(source)?;
+    let output = SyntaxTree::parse(printed)?;
+    let from = original.tokens();
+    let to = output.tokens();
+
+    // Byte index in `printed` and the text to insert there, in order
+    let mut inserts: Vec<(usize, String)> = vec![];
+    let mut carried: Vec<Comment> = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < from.len() && j < to.len() {
+        let (skip_from, skip_to) = align(&from[i..], &to[j..]).unwrap_or((1, 1));
+        for tok in &from[i..i + skip_from] {
+            carried.extend(comments(tok).into_iter().map(|mut comment| {
+                if comment.placement == Placement::After {
+                    comment.placement = Placement::Before;
+                }
+                comment
+            }));
+        }
+        i += skip_from;
+        j += skip_to;
+        if i >= from.len() || j >= to.len() {
+            break;
+        }
+
+        carried.extend(comments(from[i]));
+        for comment in carried.drain(..) {
+            inserts.push(place(printed, to[j], &comment));
+        }
+        i += 1;
+        j += 1;
+    }
+
+    // Whatever didn't line up goes at the end, with the comments after the
+    // last token
+    for tok in &from[i..] {
+        carried.extend(comments(tok));
+    }
+    let rest = carried
+        .iter()
+        .map(|comment| comment.text)
+        .chain(original.trailing.iter().filter(|t| is_comment(t)).map(|t| t.text));
+
+    inserts.sort_by_key(|&(at, _)| at);
+    let mut out = String::with_capacity(printed.len());
+    let mut copied = 0;
+    for (at, text) in inserts {
+        out.push_str(&printed[copied..at]);
+        out.push_str(&text);
+        copied = at;
+    }
+    out.push_str(&printed[copied..]);
+
+    for text in rest {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(text.trim_right());
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+fn is_comment(trivia: &Trivia) -> bool {
+    trivia.kind == TokenType::Comment
+}
+
+fn same(a: &SyntaxToken, b: &SyntaxToken) -> bool {
+    a.kind == b.kind && a.text == b.text
+}
+
+/// How many tokens to skip in `from` and in `to` to reach the nearest pair
+/// that match.
+fn align(from: &[&SyntaxToken], to: &[&SyntaxToken]) -> Option<(usize, usize)> {
+    for distance in 0..LOOKAHEAD {
+        for skip_from in 0..distance + 1 {
+            let skip_to = distance - skip_from;
+            match (from.get(skip_from), to.get(skip_to)) {
+                (Some(a), Some(b)) if same(a, b) => return Some((skip_from, skip_to)),
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/// The comments around `tok`, in source order.
+fn comments<'input>(tok: &SyntaxToken<'input>) -> Vec<Comment<'input>> {
+    let mut out = vec![];
+    for (i, trivia) in tok.leading.iter().enumerate() {
+        if !is_comment(trivia) {
+            continue;
+        }
+        // A line comment ends its line; a block comment does if a newline
+        // comes before anything else
+        let own_line = trivia.text.starts_with("//") ||
+                       tok.leading.get(i + 1).map_or(false, |next| next.text.contains('\n'));
+        out.push(Comment {
+            text: trivia.text,
+            placement: if own_line { Placement::Above } else { Placement::Before },
+        });
+    }
+    for trivia in tok.trailing.iter().filter(|t| is_comment(t)) {
+        out.push(Comment {
+            text: trivia.text,
+            placement: if trivia.text.starts_with("//") {
+                Placement::EndOfLine
+            } else {
+                Placement::After
+            },
+        });
+    }
+    out
+}
+
+fn place(printed: &str, tok: &SyntaxToken, comment: &Comment) -> (usize, String) {
+    let text = comment.text.trim_right();
+    match comment.placement {
+        Placement::Above => {
+            let line_start = printed[..tok.start.index].rfind('\n').map_or(0, |n| n + 1);
+            let indent: String = printed[line_start..]
+                .chars()
+                .take_while(|&c| c == ' ' || c == '\t')
+                .collect();
+            (line_start, format!("{}{}\n", indent, text))
+        }
+        Placement::Before => (tok.start.index, format!("{} ", text)),
+        Placement::After => (tok.end.index, format!(" {}", text)),
+        Placement::EndOfLine => {
+            let end = tok.end.index;
+            let line_end = printed[end..].find('\n').map_or(printed.len(), |n| end + n);
+            (line_end, format!(" {}", text))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::printer::{print_module, Config};
+    use lexer::Lexer;
+    use parser::parse_Module;
+    use std::fs::File;
+    use std::io::Read;
+
+    fn print(source: &str) -> String {
+        let ast = parse_Module(Lexer::new(source)).unwrap();
+        print_module(&ast, &Config::default())
+    }
+
+    fn format(source: &str) -> String {
+        reattach(source, &print(source)).unwrap()
+    }
+
+    fn comment_texts(source: &str) -> Vec<String> {
+        Lexer::lossless(source)
+            .map(|tok| tok.unwrap().1)
+            .filter(|&(kind, _)| kind == TokenType::Comment)
+            .map(|(_, text)| text.trim_right().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn formats_example() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/example.seal");
+        let mut source = String::new();
+        File::open(path).unwrap().read_to_string(&mut source).unwrap();
+        assert!(!comment_texts(&source).is_empty());
+
+        let formatted = format(&source);
+        // Same tree, same comments, and formatting it again changes nothing
+        assert_eq!(print(&formatted), print(&source));
+        assert_eq!(comment_texts(&formatted), comment_texts(&source));
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn placements() {
+        let source = "// above\nfn f(a /* after */, /* before */ b) { // end\n    return a; /* x */\n}\n// last\n";
+        let formatted = format(source);
+        assert!(formatted.starts_with("// above\nfn f("), "{}", formatted);
+        assert!(formatted.contains("a /* after */,"), "{}", formatted);
+        assert!(formatted.contains("/* before */ b"), "{}", formatted);
+        assert!(formatted.contains("{ // end\n"), "{}", formatted);
+        assert!(formatted.contains("return a; /* x */\n"), "{}", formatted);
+        assert!(formatted.ends_with("}\n// last\n"), "{}", formatted);
+        assert_eq!(format(&formatted), formatted);
+    }
+}
@@ -0,0 +1,283 @@
+//! A lossless concrete syntax tree.
+//!
+//! The parser only ever sees significant tokens, so the `ast` can't be turned
+//! back into the exact text it came from. The tree in this module keeps every
+//! byte of the input: whitespace and comments are attached to the neighbouring
+//! tokens as trivia, and bracketed regions are grouped so tools can work on a
+//! whole `{ ... }` block at once. Printing a `SyntaxTree` always reproduces the
+//! source it was built from.
+
+use lexer::{LexicalError, Lexer, Location, TokenType};
+use std::fmt;
+
+pub mod comments;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trivia<'input> {
+    pub kind: TokenType,
+    pub text: &'input str,
+    pub start: Location,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxToken<'input> {
+    pub kind: TokenType,
+    pub text: &'input str,
+    pub start: Location,
+    pub end: Location,
+    /// Trivia between the previous token's trailing trivia and this token.
+    pub leading: Vec<Trivia<'input>>,
+    /// Trivia after this token up to (but not including) the next newline.
+    pub trailing: Vec<Trivia<'input>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyntaxElement<'input> {
+    Token(SyntaxToken<'input>),
+    Group(Group<'input>),
+}
+
+/// A bracketed region: `{ ... }`, `( ... )` or `[ ... ]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group<'input> {
+    pub open: SyntaxToken<'input>,
+    pub children: Vec<SyntaxElement<'input>>,
+    pub close: SyntaxToken<'input>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxTree<'input> {
+    pub elements: Vec<SyntaxElement<'input>>,
+    /// Trivia after the last token in the file.
+    pub trailing: Vec<Trivia<'input>>,
+}
+
+#[derive(Debug)]
+pub enum CstError {
+    Lexical(LexicalError),
+    /// A closing bracket that doesn't match the innermost open one.
+    Mismatched(Location),
+    /// An open bracket that is never closed.
+    Unclosed(Location),
+}
+
+impl From<LexicalError> for CstError {
+    fn from(err: LexicalError) -> CstError {
+        CstError::Lexical(err)
+    }
+}
+
+impl<'input> SyntaxTree<'input> {
+    pub fn parse(source: &'input str) -> Result<Self, CstError> {
+        let tokens = attach_trivia(Lexer::lossless(source))?;
+
+        // Each open bracket pushes a new frame; the root frame has no opener.
+        let mut stack: Vec<(Option<SyntaxToken<'input>>, Vec<SyntaxElement<'input>>)> =
+            vec![(None, vec![])];
+
+        for tok in tokens.tokens {
+            if closer_for(tok.kind).is_some() {
+                stack.push((Some(tok), vec![]));
+                continue;
+            }
+
+            if is_closer(tok.kind) {
+                let (open, children) = match stack.pop() {
+                    Some((Some(open), children)) => (open, children),
+                    _ => return Err(CstError::Mismatched(tok.start)),
+                };
+
+                if closer_for(open.kind) != Some(tok.kind) {
+                    return Err(CstError::Mismatched(tok.start));
+                }
+
+                let group = Group {
+                    open: open,
+                    children: children,
+                    close: tok,
+                };
+                stack.last_mut().unwrap().1.push(SyntaxElement::Group(group));
+                continue;
+            }
+
+            stack.last_mut().unwrap().1.push(SyntaxElement::Token(tok));
+        }
+
+        let (open, elements) = stack.pop().unwrap();
+        if let Some(open) = open {
+            return Err(CstError::Unclosed(open.start));
+        }
+
+        Ok(SyntaxTree {
+            elements: elements,
+            trailing: tokens.trailing,
+        })
+    }
+
+    /// Iterates every significant token in source order, descending into groups.
+    pub fn tokens(&self) -> Vec<&SyntaxToken<'input>> {
+        let mut out = vec![];
+        for elem in &self.elements {
+            elem.collect_tokens(&mut out);
+        }
+        out
+    }
+}
+
+impl<'input> SyntaxElement<'input> {
+    fn collect_tokens<'a>(&'a self, out: &mut Vec<&'a SyntaxToken<'input>>) {
+        match *self {
+            SyntaxElement::Token(ref tok) => out.push(tok),
+            SyntaxElement::Group(ref group) => {
+                out.push(&group.open);
+                for child in &group.children {
+                    child.collect_tokens(out);
+                }
+                out.push(&group.close);
+            }
+        }
+    }
+}
+
+fn closer_for(kind: TokenType) -> Option<TokenType> {
+    match kind {
+        TokenType::OpenCurly => Some(TokenType::CloseCurly),
+        TokenType::OpenParen => Some(TokenType::CloseParen),
+        TokenType::OpenBracket => Some(TokenType::CloseBracket),
+        _ => None,
+    }
+}
+
+fn is_closer(kind: TokenType) -> bool {
+    match kind {
+        TokenType::CloseCurly | TokenType::CloseParen | TokenType::CloseBracket => true,
+        _ => false,
+    }
+}
+
+struct TokenStream<'input> {
+    tokens: Vec<SyntaxToken<'input>>,
+    trailing: Vec<Trivia<'input>>,
+}
+
+fn attach_trivia<'input>(lexer: Lexer<'input>) -> Result<TokenStream<'input>, CstError> {
+    let mut tokens: Vec<SyntaxToken<'input>> = vec![];
+    let mut pending = vec![];
+    // Whether trivia may still be attached to the end of the last token
+    let mut same_line = false;
+
+    for spanned in lexer {
+        let (start, (kind, text), end) = spanned?;
+
+        if kind.is_trivia() {
+            let trivia = Trivia {
+                kind: kind,
+                text: text,
+                start: start,
+            };
+
+            if same_line && !text.contains('\n') {
+                tokens.last_mut().unwrap().trailing.push(trivia);
+            } else {
+                same_line = false;
+                pending.push(trivia);
+            }
+            continue;
+        }
+
+        tokens.push(SyntaxToken {
+            kind: kind,
+            text: text,
+            start: start,
+            end: end,
+            leading: ::std::mem::replace(&mut pending, vec![]),
+            trailing: vec![],
+        });
+        same_line = true;
+    }
+
+    Ok(TokenStream {
+        tokens: tokens,
+        trailing: pending,
+    })
+}
+
+impl<'input> fmt::Display for Trivia<'input> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.text)
+    }
+}
+
+impl<'input> fmt::Display for SyntaxToken<'input> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for trivia in &self.leading {
+            write!(fmt, "{}", trivia)?;
+        }
+        fmt.write_str(self.text)?;
+        for trivia in &self.trailing {
+            write!(fmt, "{}", trivia)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'input> fmt::Display for SyntaxElement<'input> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SyntaxElement::Token(ref tok) => write!(fmt, "{}", tok),
+            SyntaxElement::Group(ref group) => {
+                write!(fmt, "{}", group.open)?;
+                for child in &group.children {
+                    write!(fmt, "{}", child)?;
+                }
+                write!(fmt, "{}", group.close)
+            }
+        }
+    }
+}
+
+impl<'input> fmt::Display for SyntaxTree<'input> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for elem in &self.elements {
+            write!(fmt, "{}", elem)?;
+        }
+        for trivia in &self.trailing {
+            write!(fmt, "{}", trivia)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use std::path::Path;
+
+    #[test]
+    fn scripts_round_trip() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scripts");
+        let mut checked = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(true, |ext| ext != "seal") {
+                continue;
+            }
+
+            let mut source = String::new();
+            fs::File::open(&path).unwrap().read_to_string(&mut source).unwrap();
+            let tree = SyntaxTree::parse(&source)
+                .unwrap_or_else(|err| panic!("{}: {:?}", path.display(), err));
+            assert!(tree.to_string() == source, "{} didn't round trip", path.display());
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn trivia_round_trips() {
+        let source = "  /* lead */ fn f() { // trailing\r\n\t/** doc */ x; }  \n";
+        assert_eq!(SyntaxTree::parse(source).unwrap().to_string(), source);
+    }
+}
@@ -10,6 +10,7 @@ static KEYWORDS: &'static [(TT, &'static str)] = &[(TT::Break, "break"),
                                                    (TT::Else, "else"),
                                                    (TT::Enum, "enum"),
                                                    (TT::Extern, "extern"),
+                                                   (TT::False, "false"),
                                                    (TT::Function, "fn"),
                                                    (TT::For, "for"),
                                                    (TT::If, "if"),
@@ -24,6 +25,7 @@ static KEYWORDS: &'static [(TT, &'static str)] = &[(TT::Break, "break"),
                                                    (TT::Return, "return"),
                                                    (TT::Throw, "throw"),
                                                    (TT::Trait, "trait"),
+                                                   (TT::True, "true"),
                                                    (TT::Type, "type"),
                                                    (TT::Use, "use"),
                                                    (TT::While, "while")];
\ No newline at end of file
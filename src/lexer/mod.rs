@@ -1,3 +1,4 @@
+use std::fmt;
 use std::iter::Peekable;
 use std::str::CharIndices;
 
@@ -13,6 +14,7 @@ pub struct Lexer<'input> {
     source: &'input str,
     chars: Peekable<CharIndices<'input>>,
     loc: Location,
+    trivia: bool,
 }
 
 impl<'input> Lexer<'input> {
@@ -25,8 +27,17 @@ impl<'input> Lexer<'input> {
                 column: 1,
                 index: 0,
             },
+            trivia: false,
         }
     }
+
+    /// Creates a lexer that also yields whitespace and comment tokens, so
+    /// that concatenating every token span reproduces the input exactly.
+    pub fn lossless(input: &'input str) -> Self {
+        let mut lexer = Lexer::new(input);
+        lexer.trivia = true;
+        lexer
+    }
 }
 
 lazy_static!{
@@ -47,6 +58,10 @@ impl<'input> Lexer<'input> {
             None => return None,
         };
 
+        if self.source[start.index..].starts_with("/*") {
+            return Some(self.block_comment(start));
+        }
+
         loop {
             let (i, c) = match self.chars.next() {
                 None => break,
@@ -101,23 +116,75 @@ impl<'input> Lexer<'input> {
             }
         })
     }
+
+    /// Lexes a `/* */` comment starting at `start`. Block comments nest, so
+    /// `/* a /* b */ c */` is a single comment. `/** */` and `/*! */` are
+    /// doc comments for the following item and the enclosing module.
+    fn block_comment(&mut self, start: Location) -> <Self as Iterator>::Item {
+        let mut depth = 0usize;
+        let mut prev = None;
+
+        loop {
+            let (i, c) = match self.chars.next() {
+                Some(i) => i,
+                None => return Err(LexicalError::UnterminatedComment(start)),
+            };
+
+            self.loc.index = i + c.len_utf8();
+            self.loc.column += 1;
+
+            if c == '\n' {
+                self.loc.line += 1;
+                self.loc.column = 1;
+            }
+
+            match (prev, c) {
+                (Some('/'), '*') => {
+                    depth += 1;
+                    prev = None;
+                }
+                (Some('*'), '/') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                    prev = None;
+                }
+                _ => prev = Some(c),
+            }
+        }
+
+        let span = &self.source[start.index..self.loc.index];
+        let kind = if span.starts_with("/**") && !span.starts_with("/***") && span != "/**/" {
+            TokenType::DocComment
+        } else if span.starts_with("/*!") {
+            TokenType::ModuleDocComment
+        } else {
+            TokenType::Comment
+        };
+
+        Ok((start, (kind, span), self.loc))
+    }
 }
 
 impl<'input> Iterator for Lexer<'input> {
     type Item = Spanned<Tok<'input>, Location, LexicalError>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if self.trivia {
+            return self.do_next();
+        }
+
         loop {
             match self.do_next() {
-                Some(Ok((_, (TokenType::Whitespace, _), _))) => continue,
-                Some(Ok((_, (TokenType::Comment, _), _))) => continue,
+                Some(Ok((_, (kind, _), _))) if kind.is_trivia() => continue,
                 t => return t,
             }
         }
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct Location {
     pub line: usize,
     pub column: usize,
@@ -127,6 +194,57 @@ pub struct Location {
 #[derive(Debug)]
 pub enum LexicalError {
     Unexpected(char, Location),
+    /// A block comment was still open at the end of the input. The location
+    /// is where the outermost `/*` began.
+    UnterminatedComment(Location),
+    /// A number literal with no value. Reported by the parser, which
+    /// converts them, at the start of the literal.
+    InvalidNumber(NumberError, Location),
+}
+
+impl LexicalError {
+    pub fn location(&self) -> Location {
+        match *self {
+            LexicalError::Unexpected(_, loc) |
+            LexicalError::UnterminatedComment(loc) |
+            LexicalError::InvalidNumber(_, loc) => loc,
+        }
+    }
+}
+
+impl fmt::Display for LexicalError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LexicalError::Unexpected(c, _) => write!(fmt, "unexpected character `{}`", c),
+            LexicalError::UnterminatedComment(_) => write!(fmt, "unterminated block comment"),
+            LexicalError::InvalidNumber(ref err, _) => err.fmt(fmt),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumberError {
+    /// A suffix that isn't a number type, like the `u8` in `10u8`
+    UnknownSuffix(String),
+    /// An integer type suffix on a float, like `1.5u32`
+    FloatSuffix(String),
+    /// A value too big for its suffix, like `5000000000u32`
+    OutOfRange(String),
+    /// Digits that don't form a number
+    Malformed(String),
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NumberError::UnknownSuffix(ref s) => write!(fmt, "unknown number suffix `{}`", s),
+            NumberError::FloatSuffix(ref s) => {
+                write!(fmt, "a float literal can't have the integer suffix `{}`", s)
+            }
+            NumberError::OutOfRange(ref lit) => write!(fmt, "`{}` is out of range for its type", lit),
+            NumberError::Malformed(ref lit) => write!(fmt, "`{}` is not a valid number", lit),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash)]
@@ -135,6 +253,16 @@ pub struct Token<'input> {
     pub span: &'input str,
 }
 
+impl TokenType {
+    /// Whitespace and plain comments, which the parser never sees.
+    pub fn is_trivia(self) -> bool {
+        match self {
+            TokenType::Whitespace | TokenType::Comment => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TokenType {
     Invalid,
@@ -161,6 +289,7 @@ pub enum TokenType {
     Else,
     Enum,
     Extern,
+    False,
     Function, // `fn`
     For,
     If,
@@ -175,6 +304,7 @@ pub enum TokenType {
     Return,
     Throw,
     Trait,
+    True,
     Type,
     Use,
     While,
@@ -190,6 +320,7 @@ pub enum TokenType {
     Comma,
     Question,
     Dot,
+    Arrow,
 
     RangeExclusive,
     RangeInclusive,
@@ -242,3 +373,56 @@ pub enum TokenType {
     ShrAssign,
     LShrAssign,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(src: &str) -> Vec<Tok> {
+        Lexer::lossless(src).map(|tok| tok.unwrap().1).collect()
+    }
+
+    fn comment_kind(src: &str) -> TokenType {
+        let toks = tokens(src);
+        assert_eq!(toks.len(), 1, "{:?}", toks);
+        assert_eq!(toks[0].1, src);
+        toks[0].0
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        assert_eq!(tokens("/* a /* b */ c */ x"),
+                   vec![(TokenType::Comment, "/* a /* b */ c */"),
+                        (TokenType::Whitespace, " "),
+                        (TokenType::Identifier, "x")]);
+        assert_eq!(comment_kind("/* /* /* deep */ */ */"), TokenType::Comment);
+    }
+
+    #[test]
+    fn unterminated_comment_reports_its_start() {
+        let err = Lexer::new("x\n  /* a /* b */").filter_map(|tok| tok.err()).next();
+        match err {
+            Some(LexicalError::UnterminatedComment(loc)) => {
+                assert_eq!((loc.line, loc.column, loc.index), (2, 3, 4))
+            }
+            other => panic!("expected an unterminated comment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn doc_comment_kinds() {
+        assert_eq!(comment_kind("/* plain */"), TokenType::Comment);
+        assert_eq!(comment_kind("/** item */"), TokenType::DocComment);
+        assert_eq!(comment_kind("/*** banner ***/"), TokenType::Comment);
+        assert_eq!(comment_kind("/**/"), TokenType::Comment);
+        assert_eq!(comment_kind("/*! module */"), TokenType::ModuleDocComment);
+    }
+
+    #[test]
+    fn parser_skips_only_plain_comments() {
+        let kinds: Vec<TokenType> =
+            Lexer::new("/* a */ /** b */ /*! c */ x").map(|tok| (tok.unwrap().1).0).collect();
+        assert_eq!(kinds,
+                   vec![TokenType::DocComment, TokenType::ModuleDocComment, TokenType::Identifier]);
+    }
+}
@@ -35,18 +35,8 @@ pub fn create_dfa() -> Dfa<TT, char> {
     dfa.transition(mod_doc_comment, None, '\r');
     dfa.transition(mod_doc_comment, None, '\n');
 
-    // Multi-line comments
-    let multi_comment = dfa.create(TT::Comment);
-    let multi_body = dfa.create(TT::Comment);
-    let multi_maybe_end = dfa.create(TT::Comment);
-
-    dfa.transition_default(multi_body, multi_body);
-    dfa.transition_default(multi_maybe_end, multi_body);
-
-    dfa.transition(single_comment_begin, multi_body, '*');
-    dfa.transition(multi_body, multi_maybe_end, '*');
-    dfa.transition(multi_maybe_end, multi_comment, '\0');
-    dfa.transition(multi_body, multi_comment, '\0');
+    // Multi-line comments nest, which a DFA can't count, so `/*` is
+    // handled by `Lexer::block_comment` before the DFA is consulted.
 
     // Identifiers
     let identifier = dfa.create(TT::Identifier);
@@ -146,6 +136,33 @@ pub fn create_dfa() -> Dfa<TT, char> {
     dfa.transition(scientific_start, signed_scientific, '-');
     dfa.transition_complex(scientific_start, scientific_literal, |c| c.is_digit(10));
     dfa.transition_complex(signed_scientific, scientific_literal, |c| c.is_digit(10));
+    dfa.transition_complex(scientific_literal, scientific_literal, |c| c.is_digit(10));
+
+    // Type suffixes, like `10u32` and `1.5f32`. The parser decides which
+    // ones are valid. `f` is a hex digit, so hex literals can't take `f32`.
+    let int_suffix = dfa.create(TT::IntLiteral);
+    let hex_suffix = dfa.create(TT::HexLiteral);
+    let oct_suffix = dfa.create(TT::OctLiteral);
+    let bin_suffix = dfa.create(TT::BinLiteral);
+    let float_suffix = dfa.create(TT::FloatLiteral);
+
+    let suffix_edge = |dfa: &mut Dfa<TT, char>, from, to, starts: &[char]| {
+        for &c in starts {
+            dfa.transition(from, to, c);
+        }
+    };
+
+    suffix_edge(&mut dfa, zero_prefix, int_suffix, &['i', 'u', 'f']);
+    suffix_edge(&mut dfa, int_literal, int_suffix, &['i', 'u', 'f']);
+    suffix_edge(&mut dfa, hex_literal, hex_suffix, &['i', 'u']);
+    suffix_edge(&mut dfa, oct_literal, oct_suffix, &['i', 'u', 'f']);
+    suffix_edge(&mut dfa, bin_literal, bin_suffix, &['i', 'u', 'f']);
+    suffix_edge(&mut dfa, float_literal, float_suffix, &['i', 'u', 'f']);
+    suffix_edge(&mut dfa, scientific_literal, float_suffix, &['i', 'u', 'f']);
+
+    for &suffix in &[int_suffix, hex_suffix, oct_suffix, bin_suffix, float_suffix] {
+        dfa.transition_complex(suffix, suffix, |c| c.is_alphanumeric());
+    }
 
     // Strings
     let string_literal = dfa.create(TT::StringLiteral);
@@ -198,6 +215,7 @@ pub fn create_dfa() -> Dfa<TT, char> {
     dfa.insert_string(root, ",".chars(), TT::Comma);
     dfa.insert_string(root, "?".chars(), TT::Question);
     dfa.insert_string(root, ".".chars(), TT::Dot);
+    dfa.insert_string(root, "->".chars(), TT::Arrow);
 
     dfa.insert_string(root, "..".chars(), TT::RangeExclusive);
     dfa.insert_string(root, "...".chars(), TT::RangeInclusive);
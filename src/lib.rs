@@ -5,12 +5,17 @@ extern crate serde;
 #[macro_use]
 extern crate lazy_static;
 
+extern crate lalrpop_util;
 extern crate unicode_xid;
 extern crate simd;
 extern crate num;
 
+#[cfg(test)]
+extern crate serde_json;
+
 pub mod lexer;
 #[allow(unused_imports)]
 pub mod parser;
 pub mod ast;
+pub mod cst;
 pub mod vm;
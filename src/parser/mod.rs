@@ -1,3 +1,117 @@
+pub use lalrpop_util::ParseError;
+use lexer::{LexicalError, Location, NumberError};
+
 pub use self::parser::parse_Module;
 
 pub mod parser;
+
+/// A number literal that failed to convert, as an error at its start.
+fn number_error<T>(err: NumberError, loc: Location) -> ParseError<Location, T, LexicalError> {
+    ParseError::User { error: LexicalError::InvalidNumber(err, loc) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::{Expression, Item, Module};
+    use lexer::Lexer;
+
+    /// The value of `const X = <src>;`, as its `Debug` text.
+    fn literal(src: &str) -> Result<String, LexicalError> {
+        let source = format!("const X = {};", src);
+        let items = match parse_Module(Lexer::new(&source)) {
+            Ok(Module::Root { items }) => items,
+            Ok(module) => panic!("unexpected module {:?}", module),
+            Err(ParseError::User { error }) => return Err(error),
+            Err(err) => panic!("{}: {:?}", src, err),
+        };
+        match items[0] {
+            Item::Const(_, _, Expression::Literal(ref lit), _) => Ok(format!("{:?}", lit)),
+            ref item => panic!("{}: not a literal: {:?}", src, item),
+        }
+    }
+
+    fn number_error(src: &str) -> NumberError {
+        match literal(src) {
+            Err(LexicalError::InvalidNumber(err, loc)) => {
+                assert_eq!(loc.index, "const X = ".len(), "{}", src);
+                err
+            }
+            other => panic!("{}: expected a number error, got {:?}", src, other),
+        }
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(literal("1_000_000").unwrap(), "Integer(1000000)");
+        assert_eq!(literal("0xff_ff").unwrap(), "Integer(65535)");
+        assert_eq!(literal("0o7_7").unwrap(), "Integer(63)");
+        assert_eq!(literal("0b1010_1010").unwrap(), "Integer(170)");
+        assert_eq!(literal("1_000.5").unwrap(), "Float(1000.5)");
+        assert_eq!(literal("1_0i32").unwrap(), "Suffixed(Integer(10), I32)");
+    }
+
+    #[test]
+    fn suffixes() {
+        assert_eq!(literal("7i32").unwrap(), "Suffixed(Integer(7), I32)");
+        assert_eq!(literal("7u32").unwrap(), "Suffixed(Integer(7), U32)");
+        assert_eq!(literal("7i64").unwrap(), "Suffixed(Integer(7), I64)");
+        assert_eq!(literal("7f32").unwrap(), "Suffixed(Float(7.0), F32)");
+        assert_eq!(literal("7f64").unwrap(), "Suffixed(Float(7.0), F64)");
+        assert_eq!(literal("0.1f32").unwrap(),
+                   format!("Suffixed(Float({:?}), F32)", 0.1f32 as f64));
+        assert_eq!(literal("0.1f64").unwrap(), "Suffixed(Float(0.1), F64)");
+        assert_eq!(literal("0xffu32").unwrap(), "Suffixed(Integer(255), U32)");
+    }
+
+    #[test]
+    fn negative_bounds() {
+        assert_eq!(literal("-2147483648i32").unwrap(), "Suffixed(Integer(-2147483648), I32)");
+        assert_eq!(literal("-9223372036854775808i64").unwrap(),
+                   "Suffixed(Integer(-9223372036854775808), I64)");
+        assert_eq!(literal("-9223372036854775808").unwrap(), "Integer(-9223372036854775808)");
+        assert_eq!(literal("-9223372036854775809").unwrap(),
+                   "BigInt(\"-9223372036854775809\")");
+        assert_eq!(literal("-0u32").unwrap(), "Suffixed(Integer(0), U32)");
+    }
+
+    #[test]
+    fn out_of_range() {
+        let cases = ["2147483648i32",
+                     "-2147483649i32",
+                     "4294967296u32",
+                     "-1u32",
+                     "9223372036854775808i64",
+                     "-9223372036854775809i64",
+                     "1.0e39f32",
+                     "1.0e309f64"];
+        for src in &cases {
+            assert_eq!(number_error(src), NumberError::OutOfRange(src.to_string()));
+        }
+        assert_eq!(literal("2147483647i32").unwrap(), "Suffixed(Integer(2147483647), I32)");
+        assert_eq!(literal("4294967295u32").unwrap(), "Suffixed(Integer(4294967295), U32)");
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(number_error("1_0u8"), NumberError::UnknownSuffix("u8".into()));
+        assert_eq!(number_error("10i16"), NumberError::UnknownSuffix("i16".into()));
+        assert_eq!(number_error("1.5u32"), NumberError::FloatSuffix("u32".into()));
+        assert_eq!(number_error("2.5e5i64"), NumberError::FloatSuffix("i64".into()));
+    }
+
+    #[test]
+    fn negation_of_other_expressions() {
+        // `-` only joins a lone integer; member access binds tighter
+        let source = "const X = -5.abs;";
+        match parse_Module(Lexer::new(source)).unwrap() {
+            Module::Root { ref items } => match items[0] {
+                Item::Const(_, _, Expression::Negate(ref inner), _) => {
+                    assert_eq!(format!("{:?}", inner), "MemberAccess(Literal(Integer(5)), \"abs\")")
+                }
+                ref item => panic!("{:?}", item),
+            },
+            ref module => panic!("{:?}", module),
+        }
+    }
+}
@@ -0,0 +1,369 @@
+//! Cycle collection for shared values.
+//!
+//! `Shared` is reference counted, so values that refer to each other, like
+//! an object stored in a table it holds, are never freed by counting alone.
+//! Every `Shared` is tracked here from when it's created, and `collect`
+//! finds the ones only reachable from each other and breaks their cycles.
+//!
+//! Collection is by trial deletion. The references each tracked value holds
+//! to other tracked values are subtracted from their strong counts, and
+//! whatever is left over comes from outside: a scope, the Rust stack, or a
+//! value the collector can't see into. Everything reachable from those is
+//! live. The rest is garbage, and clearing it drops the counts to zero.
+//! A value that's borrowed while collecting is assumed to be live. Weak
+//! references aren't edges, and once garbage is freed the live values get
+//! a chance to drop the weak references that died, with `Trace::sweep`.
+//!
+//! A collection runs by itself once `threshold` shared values have been
+//! created since the last one, and the embedder can run one at any time.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::{Rc, Weak};
+use vm::runtime as rt;
+use vm::value::{Object, Value, ValueKey};
+
+pub const DEFAULT_THRESHOLD: usize = 10000;
+
+/// Values that can hold shared values.
+pub trait Trace {
+    /// Reports each shared value held directly.
+    fn trace(&self, tracer: &mut Tracer);
+
+    /// Drops everything held, to break a cycle.
+    fn clear(&mut self);
+
+    /// Drops weak references whose values have been freed.
+    fn sweep(&mut self) {}
+}
+
+/// Collects the shared values a value holds, by address.
+pub struct Tracer {
+    edges: Vec<usize>,
+}
+
+impl Tracer {
+    pub fn value(&mut self, value: &Value) {
+        match *value {
+            Value::Object(ref obj) => self.edges.push(obj.addr()),
+            Value::Table(ref table) => self.edges.push(table.addr()),
+            Value::Array(ref array) => self.edges.push(array.addr()),
+            Value::ExternFunction(ref func) => self.edges.push(func.addr()),
+            _ => (),
+        }
+    }
+
+    pub fn key(&mut self, key: &ValueKey) {
+        if let ValueKey::Shared(ref shared) = *key {
+            self.edges.push(shared.addr());
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Stats {
+    /// Collections run so far
+    pub collections: usize,
+    /// Shared values tracked after the last collection
+    pub tracked: usize,
+    /// Values freed by the last collection
+    pub last_freed: usize,
+    /// Values freed by every collection
+    pub total_freed: usize,
+}
+
+/// A tracked value, with its type erased.
+trait Node {
+    /// `false` if the value is borrowed mutably and can't be traced.
+    fn trace(&self, tracer: &mut Tracer) -> bool;
+    fn clear(&self);
+    fn sweep(&self);
+}
+
+impl<T: Trace> Node for RefCell<T> {
+    fn trace(&self, tracer: &mut Tracer) -> bool {
+        match self.try_borrow() {
+            Ok(value) => {
+                value.trace(tracer);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut value) = self.try_borrow_mut() {
+            value.clear();
+        }
+    }
+
+    fn sweep(&self) {
+        if let Ok(mut value) = self.try_borrow_mut() {
+            value.sweep();
+        }
+    }
+}
+
+struct Heap {
+    nodes: Vec<(usize, Weak<Node>)>,
+    allocated: usize,
+    threshold: usize,
+    collecting: bool,
+    stats: Stats,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap {
+        nodes: vec![],
+        allocated: 0,
+        threshold: DEFAULT_THRESHOLD,
+        collecting: false,
+        stats: Stats::default(),
+    });
+}
+
+/// Starts tracking a new shared value, and collects if enough have been
+/// created since the last collection.
+pub fn track<T: Trace + 'static>(cell: &Rc<RefCell<T>>) {
+    let node: Rc<Node> = cell.clone();
+    let addr = &**cell as *const RefCell<T> as usize;
+
+    let due = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.nodes.push((addr, Rc::downgrade(&node)));
+        heap.allocated += 1;
+        heap.threshold != 0 && heap.allocated >= heap.threshold && !heap.collecting
+    });
+
+    if due {
+        collect();
+    }
+}
+
+/// How many shared values may be created between automatic collections.
+/// Zero turns them off.
+pub fn set_threshold(threshold: usize) {
+    HEAP.with(|heap| heap.borrow_mut().threshold = threshold);
+}
+
+pub fn stats() -> Stats {
+    HEAP.with(|heap| heap.borrow().stats)
+}
+
+/// Frees every unreachable cycle, returning how many values were freed.
+pub fn collect() -> usize {
+    // Nothing is borrowed from the heap while values are traced and dropped
+    let nodes = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.collecting = true;
+        heap.allocated = 0;
+        mem::replace(&mut heap.nodes, vec![])
+    });
+
+    let live: Vec<(usize, Rc<Node>)> = nodes.into_iter()
+        .filter_map(|(addr, node)| node.upgrade().map(|node| (addr, node)))
+        .collect();
+    let index: HashMap<usize, usize> =
+        live.iter().enumerate().map(|(i, &(addr, _))| (addr, i)).collect();
+
+    // Not counting the reference held by `live`
+    let mut outside: Vec<isize> =
+        live.iter().map(|&(_, ref node)| Rc::strong_count(node) as isize - 1).collect();
+    let mut reachable = vec![false; live.len()];
+    let mut edges = Vec::with_capacity(live.len());
+
+    for (i, &(_, ref node)) in live.iter().enumerate() {
+        let mut tracer = Tracer { edges: vec![] };
+        if !node.trace(&mut tracer) {
+            reachable[i] = true;
+        }
+
+        let targets: Vec<usize> =
+            tracer.edges.iter().filter_map(|addr| index.get(addr).cloned()).collect();
+        for &target in &targets {
+            outside[target] -= 1;
+        }
+        edges.push(targets);
+    }
+
+    let mut stack: Vec<usize> =
+        (0..live.len()).filter(|&i| reachable[i] || outside[i] > 0).collect();
+    for &i in &stack {
+        reachable[i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        for &target in &edges[i] {
+            if !reachable[target] {
+                reachable[target] = true;
+                stack.push(target);
+            }
+        }
+    }
+
+    let mut freed = 0;
+    for (i, &(_, ref node)) in live.iter().enumerate() {
+        if !reachable[i] {
+            node.clear();
+            freed += 1;
+        }
+    }
+
+    // Dropping `live` frees the garbage
+    let kept: Vec<(usize, Weak<Node>)> = live.into_iter()
+        .enumerate()
+        .filter(|&(i, _)| reachable[i])
+        .map(|(_, (addr, node))| (addr, Rc::downgrade(&node)))
+        .collect();
+
+    for &(_, ref node) in &kept {
+        if let Some(node) = node.upgrade() {
+            node.sweep();
+        }
+    }
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        let added = mem::replace(&mut heap.nodes, kept);
+        heap.nodes.extend(added);
+        heap.collecting = false;
+        heap.stats.collections += 1;
+        heap.stats.tracked = heap.nodes.len();
+        heap.stats.last_freed = freed;
+        heap.stats.total_freed += freed;
+    });
+
+    freed
+}
+
+impl Trace for Vec<Value> {
+    fn trace(&self, tracer: &mut Tracer) {
+        for value in self {
+            tracer.value(value);
+        }
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+}
+
+impl Trace for Object {
+    fn trace(&self, tracer: &mut Tracer) {
+        for value in self.fields.values() {
+            tracer.value(value);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+/// What a Rust closure captures can't be seen, so it's always treated as
+/// coming from outside.
+impl Trace for rt::Function {
+    fn trace(&self, _: &mut Tracer) {}
+
+    fn clear(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+    use vm::value::Symbol;
+    use vm::value::shared::Shared;
+    use vm::value::table::Table;
+
+    fn table_holding(value: Value) -> Shared<Table> {
+        let mut table = Table::new();
+        table.insert(ValueKey::Symbol(Symbol::intern("other")), value);
+        Shared::new(table)
+    }
+
+    // Each test runs on its own thread, and so has its own heap
+
+    #[test]
+    fn cycles_are_freed() {
+        let array = Shared::new(vec![]);
+        array.borrow_mut().push(Value::Array(array.clone()));
+        let first = table_holding(Value::Nil);
+        let second = table_holding(Value::Table(first.clone()));
+        first.borrow_mut().insert(ValueKey::Symbol(Symbol::intern("other")),
+                                  Value::Table(second.clone()));
+
+        let weak = (array.downgrade(), first.downgrade(), second.downgrade());
+        drop((array, first, second));
+        assert!(weak.0.upgrade().is_some() && weak.1.upgrade().is_some());
+
+        assert_eq!(collect(), 3);
+        assert!(weak.0.upgrade().is_none());
+        assert!(weak.1.upgrade().is_none());
+        assert!(weak.2.upgrade().is_none());
+    }
+
+    #[test]
+    fn reachable_values_survive() {
+        let array = Shared::new(vec![]);
+        array.borrow_mut().push(Value::Array(array.clone()));
+        // Only reachable through the array, which is held here
+        let table = table_holding(Value::Array(array.clone()));
+        array.borrow_mut().push(Value::Table(table.clone()));
+        let weak = table.downgrade();
+        drop(table);
+
+        assert_eq!(collect(), 0);
+        assert_eq!(array.borrow().len(), 2);
+        assert!(weak.upgrade().is_some());
+
+        drop(array);
+        assert_eq!(collect(), 2);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn borrowed_values_are_kept() {
+        let first = Shared::new(vec![]);
+        let second = Shared::new(vec![Value::Array(first.clone())]);
+        first.borrow_mut().push(Value::Array(second.clone()));
+        let weak = (first.downgrade(), second.downgrade());
+
+        // Borrowed for good, and held only by `first`, as if the borrow were
+        // reached through it. The collector can't see what it holds, so
+        // both stay.
+        mem::forget(second.borrow_mut());
+        drop((first, second));
+
+        assert_eq!(collect(), 0);
+        assert!(weak.0.upgrade().is_some());
+        assert!(weak.1.upgrade().is_some());
+    }
+
+    #[test]
+    fn collections_follow_the_threshold() {
+        set_threshold(0);
+        for _ in 0..3 {
+            let array = Shared::new(vec![]);
+            array.borrow_mut().push(Value::Array(array.clone()));
+        }
+        assert_eq!(stats().collections, 0);
+
+        set_threshold(4);
+        let kept = Shared::new(vec![]);
+        assert_eq!(stats().collections, 1);
+        let after = stats();
+        assert_eq!((after.tracked, after.last_freed, after.total_freed), (1, 3, 3));
+
+        for _ in 0..4 {
+            Shared::new(vec![Value::Array(kept.clone())]);
+        }
+        // The fourth array was being created, and still alive, when it ran
+        let after = stats();
+        assert_eq!(after.collections, 2);
+        assert_eq!((after.tracked, after.last_freed, after.total_freed), (2, 0, 3));
+
+        assert_eq!(collect(), 0);
+        assert_eq!(stats().collections, 3);
+    }
+}
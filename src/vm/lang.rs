@@ -0,0 +1,302 @@
+//! Language traits, which let objects overload operators.
+//!
+//! A trait marked `@lang("add")` (and so on) is the one the runtime consults
+//! when an operand is an object. Each has a single method with a fixed name:
+//!
+//! | lang          | method                         | used for               |
+//! |---------------|--------------------------------|------------------------|
+//! | `add`         | `add(self, rhs)`               | `a + b`                |
+//! | `sub`         | `sub(self, rhs)`               | `a - b`                |
+//! | `mul`         | `mul(self, rhs)`               | `a * b`                |
+//! | `eq`          | `eq(self, rhs)`                | `a == b`, `a != b`     |
+//! | `ord`         | `cmp(self, rhs)`               | `<`, `>`, `<=`, `>=`   |
+//! | `index`       | `index(self, key)`             | `a[key]`               |
+//! | `index_set`   | `index_set(self, key, value)`  | `a[key] = value`       |
+//! | `neg`         | `neg(self)`                    | `-a`                   |
+//! | `not`         | `not(self)`                    | `!a`                   |
+//! | `call`        | `call(self, args...)`          | `a(args...)`           |
+//! | `display`     | `to_string(self)`              | printing               |
+//!
+//! For binary operators the left operand is asked first and then the right.
+//! Either way the method gets the operands in source order, so like in Lua a
+//! method only found on the right operand's type is called with the left
+//! operand as `self`. `cmp` returns a negative, zero or positive integer,
+//! which is compared against zero, and `!=` is the negation of `eq`.
+//!
+//! When neither operand implements the trait the builtin meaning applies.
+//! Objects have no builtin arithmetic, ordering, indexing, negation or call,
+//! so those are an `OperatorError`. `==` and `!=` fall back to comparing
+//! identity, `!` to truthiness, and `display` to the formatting in
+//! `vm::value::display`.
+
+use ast::{self, BinOp};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use vm::value::Value;
+use vm::value::object::TraitDef;
+use vm::value::sym::Symbol;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LangTrait {
+    Add,
+    Sub,
+    Mul,
+    Eq,
+    Ord,
+    Index,
+    IndexSet,
+    Neg,
+    Not,
+    Call,
+    Display,
+}
+
+pub const ALL_LANG_TRAITS: &'static [LangTrait] = &[LangTrait::Add,
+                                                   LangTrait::Sub,
+                                                   LangTrait::Mul,
+                                                   LangTrait::Eq,
+                                                   LangTrait::Ord,
+                                                   LangTrait::Index,
+                                                   LangTrait::IndexSet,
+                                                   LangTrait::Neg,
+                                                   LangTrait::Not,
+                                                   LangTrait::Call,
+                                                   LangTrait::Display];
+
+impl LangTrait {
+    /// The name used in `@lang`.
+    pub fn name(self) -> &'static str {
+        match self {
+            LangTrait::Add => "add",
+            LangTrait::Sub => "sub",
+            LangTrait::Mul => "mul",
+            LangTrait::Eq => "eq",
+            LangTrait::Ord => "ord",
+            LangTrait::Index => "index",
+            LangTrait::IndexSet => "index_set",
+            LangTrait::Neg => "neg",
+            LangTrait::Not => "not",
+            LangTrait::Call => "call",
+            LangTrait::Display => "display",
+        }
+    }
+
+    /// The method the runtime calls.
+    pub fn method(self) -> &'static str {
+        match self {
+            LangTrait::Ord => "cmp",
+            LangTrait::Display => "to_string",
+            _ => self.name(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LangTrait> {
+        ALL_LANG_TRAITS.iter().cloned().find(|lang| lang.name() == name)
+    }
+
+    /// The trait that overloads a binary operator, if it can be.
+    pub fn for_binop(op: BinOp) -> Option<LangTrait> {
+        match op {
+            BinOp::Add => Some(LangTrait::Add),
+            BinOp::Sub => Some(LangTrait::Sub),
+            BinOp::Mul => Some(LangTrait::Mul),
+            BinOp::Equal | BinOp::NotEqual => Some(LangTrait::Eq),
+            BinOp::LessThan | BinOp::GreaterThan | BinOp::LessOrEqual |
+            BinOp::GreaterOrEqual => Some(LangTrait::Ord),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LangTrait {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.name())
+    }
+}
+
+#[derive(Debug)]
+pub enum LangError {
+    Unknown(String),
+    Duplicate(LangTrait),
+}
+
+impl fmt::Display for LangError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LangError::Unknown(ref name) => write!(fmt, "there is no lang trait `{}`", name),
+            LangError::Duplicate(lang) => {
+                write!(fmt, "lang trait `{}` is declared more than once", lang)
+            }
+        }
+    }
+}
+
+/// An operator that neither operand supports.
+#[derive(Debug)]
+pub struct OperatorError {
+    pub op: &'static str,
+    pub lhs: &'static str,
+    pub rhs: Option<&'static str>,
+}
+
+impl fmt::Display for OperatorError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.rhs {
+            Some(rhs) => write!(fmt, "can't apply `{}` to {} and {}", self.op, self.lhs, rhs),
+            None => write!(fmt, "can't apply `{}` to {}", self.op, self.lhs),
+        }
+    }
+}
+
+/// What to do with the value an overload returns.
+#[derive(Copy, Clone, Debug)]
+pub enum Adjust {
+    None,
+    /// Negate its truthiness, for `!=`
+    Not,
+    /// Compare it against zero with this operator, for `cmp`
+    Compare(BinOp),
+}
+
+/// A call the runtime should make in place of a builtin operation.
+#[derive(Debug)]
+pub struct Overload {
+    pub method: Rc<ast::Function>,
+    pub args: Vec<Value>,
+    pub adjust: Adjust,
+}
+
+/// The traits registered for each lang item, and their method symbols.
+#[derive(Debug, Default)]
+pub struct LangItems {
+    traits: HashMap<LangTrait, (Symbol, Symbol)>,
+}
+
+impl LangItems {
+    pub fn new() -> Self {
+        LangItems { traits: HashMap::new() }
+    }
+
+    /// Registers `tr` if it has a `@lang` attribute.
+    pub fn register(&mut self,
+                    attrs: &[ast::Attribute],
+                    tr: &ast::Trait)
+                    -> Result<(), LangError> {
+        for attr in attrs {
+            let name = match *attr {
+                ast::Attribute::Named(ref name, ref args) if name == "lang" => {
+                    match args.first() {
+                        Some(&ast::Attribute::String(ref s)) => s.trim_matches('"'),
+                        _ => continue,
+                    }
+                }
+                _ => continue,
+            };
+
+            let lang = LangTrait::from_name(name).ok_or_else(|| LangError::Unknown(name.into()))?;
+            if self.traits.contains_key(&lang) {
+                return Err(LangError::Duplicate(lang));
+            }
+            let entry = (Symbol::intern(&tr.name), Symbol::intern(lang.method()));
+            self.traits.insert(lang, entry);
+        }
+        Ok(())
+    }
+
+    pub fn trait_symbol(&self, lang: LangTrait) -> Option<Symbol> {
+        self.traits.get(&lang).map(|&(tr, _)| tr)
+    }
+
+    /// The method `value` implements `lang` with, if it's an object that
+    /// does.
+    pub fn method(&self,
+                  lang: LangTrait,
+                  value: &Value,
+                  traits: &HashMap<Symbol, TraitDef>)
+                  -> Option<Rc<ast::Function>> {
+        let (tr, method) = match self.traits.get(&lang) {
+            Some(&entry) => entry,
+            None => return None,
+        };
+        let obj = match *value {
+            Value::Object(ref obj) => obj.borrow(),
+            _ => return None,
+        };
+        let meta = match obj.metatype {
+            Some(ref meta) => meta,
+            None => return None,
+        };
+
+        if meta.implements(tr, traits) {
+            meta.find_method(method, true, traits)
+        } else {
+            None
+        }
+    }
+
+    /// The overload for `lhs op rhs`. `None` means the builtin operation
+    /// applies.
+    pub fn binary(&self,
+                  op: BinOp,
+                  lhs: &Value,
+                  rhs: &Value,
+                  traits: &HashMap<Symbol, TraitDef>)
+                  -> Option<Overload> {
+        let lang = match LangTrait::for_binop(op) {
+            Some(lang) => lang,
+            None => return None,
+        };
+        let method = match self.method(lang, lhs, traits) {
+            Some(method) => method,
+            None => {
+                match self.method(lang, rhs, traits) {
+                    Some(method) => method,
+                    None => return None,
+                }
+            }
+        };
+
+        let adjust = match op {
+            BinOp::NotEqual => Adjust::Not,
+            BinOp::LessThan | BinOp::GreaterThan | BinOp::LessOrEqual |
+            BinOp::GreaterOrEqual => Adjust::Compare(op),
+            _ => Adjust::None,
+        };
+        Some(Overload {
+            method: method,
+            args: vec![lhs.clone(), rhs.clone()],
+            adjust: adjust,
+        })
+    }
+
+    /// The overload for a unary operator, an index, a call or display,
+    /// with `value` as `self` followed by `args`.
+    pub fn unary(&self,
+                 lang: LangTrait,
+                 value: &Value,
+                 args: &[Value],
+                 traits: &HashMap<Symbol, TraitDef>)
+                 -> Option<Overload> {
+        self.method(lang, value, traits).map(|method| {
+            let mut all = Vec::with_capacity(args.len() + 1);
+            all.push(value.clone());
+            all.extend(args.iter().cloned());
+            Overload {
+                method: method,
+                args: all,
+                adjust: Adjust::None,
+            }
+        })
+    }
+}
+
+/// The error for an operator with no overload, where the builtin operation
+/// doesn't support objects.
+pub fn unsupported(op: &'static str, lhs: &Value, rhs: Option<&Value>) -> OperatorError {
+    OperatorError {
+        op: op,
+        lhs: lhs.type_name(),
+        rhs: rhs.map(Value::type_name),
+    }
+}
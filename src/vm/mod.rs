@@ -1,3 +1,6 @@
 pub mod expression;
 pub mod value;
 pub mod runtime;
+pub mod lang;
+pub mod ops;
+pub mod gc;
@@ -0,0 +1,956 @@
+//! What every binary operator means for every kind of value.
+//!
+//! The numeric kernels here are also what `ast::constant_folding` uses, so
+//! a folded expression always has the value it would have had at runtime.
+//!
+//! - Integers have arbitrary precision. Arithmetic that overflows an `i64`
+//!   gives a bigint instead, and a bigint result that fits is an `i64`
+//!   again, so the two are never told apart. Dividing by zero and shifting
+//!   by a negative amount or by 64 or more are errors. Shifts and bitwise
+//!   operators only take integers that fit in an `i64`.
+//! - Mixing an integer and a float converts the integer, and the result is
+//!   a float. Float arithmetic follows IEEE 754, so `1.0 / 0` is infinity.
+//! - `/` truncates integers. `%` is the remainder of truncating division and
+//!   takes the sign of the dividend, while `mod` takes the sign of the
+//!   divisor, for integers and floats alike: `-7 % 2 == -1` and
+//!   `-7 mod 2 == 1`.
+//! - `a /% b` is the array `[a / b, a % b]`.
+//! - `>>` is arithmetic and `>>>` logical, so `-8 >>> 1` fills with a zero.
+//! - `&`, `|` and `^` work on integers and on bools.
+//! - `+` joins two strings or two arrays, and `*` repeats a string or array
+//!   a non-negative integer number of times, as long as the result has no
+//!   more than `MAX_REPEAT_LEN` bytes or elements.
+//! - Comparisons order numbers and strings. `==` compares numbers by value,
+//!   strings and arrays by contents, SIMD vectors lane by lane, and
+//!   everything else by identity, including weak references, which are
+//!   equal when they refer to the same value. Values of different types are
+//!   never equal, except integers and floats.
+//! - SIMD vectors of the same type combine lane by lane. Integer lanes wrap
+//!   and float lanes are `f32`.
+//! - `||` and `&&` give whichever operand decides the result. Only `nil` and
+//!   `false` are falsy.
+//! - `-a` negates a number. `!a` flips the bits of an integer and is the
+//!   opposite of the truthiness of anything else.
+//!
+//! Objects get no builtin meaning other than identity. `binary` and `unary`
+//! look their overloads up through `vm::lang` first, and the builtins
+//! apply when there are none. `impls` and the ranges need the runtime's
+//! traits and iterators, so they aren't handled here.
+
+use ast::BinOp;
+use ast::printer::binop_str;
+use num::{BigInt, Integer, Signed, ToPrimitive, Zero};
+use simd;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use vm::lang::{self, LangItems, LangTrait, OperatorError, Overload};
+use vm::value::{Symbol, Value};
+use vm::value::object::TraitDef;
+use vm::value::shared::Shared;
+use vm::value::simd::SimdValue;
+
+/// The longest string or array `*` will make.
+pub const MAX_REPEAT_LEN: usize = 1 << 28;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ArithError {
+    DivideByZero,
+    ShiftOutOfRange(i64),
+    NegativeCount(i64),
+    /// Repeating that many times would be longer than `MAX_REPEAT_LEN`
+    RepeatTooLong(i64),
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArithError::DivideByZero => write!(fmt, "division by zero"),
+            ArithError::ShiftOutOfRange(n) => write!(fmt, "shift by {} is out of range 0..64", n),
+            ArithError::NegativeCount(n) => write!(fmt, "can't repeat {} times", n),
+            ArithError::RepeatTooLong(n) => write!(fmt, "repeating {} times is too long", n),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OpError {
+    Arith(ArithError),
+    Unsupported(OperatorError),
+}
+
+impl From<ArithError> for OpError {
+    fn from(err: ArithError) -> OpError {
+        OpError::Arith(err)
+    }
+}
+
+impl fmt::Display for OpError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpError::Arith(ref err) => err.fmt(fmt),
+            OpError::Unsupported(ref err) => err.fmt(fmt),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Number {
+    Int(i64),
+    /// Only ever holds integers that don't fit in an `i64`
+    Big(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    /// Demotes `big` to an `Int` if it fits.
+    pub fn from_big(big: BigInt) -> Number {
+        match big.to_i64() {
+            Some(i) => Number::Int(i),
+            None => Number::Big(big),
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match *self {
+            Number::Int(i) => i as f64,
+            Number::Big(ref b) => {
+                b.to_f64().unwrap_or(if b.is_negative() {
+                    ::std::f64::NEG_INFINITY
+                } else {
+                    ::std::f64::INFINITY
+                })
+            }
+            Number::Float(f) => f,
+        }
+    }
+
+    /// The integer as a bigint, or `None` for a float.
+    fn to_big(&self) -> Option<BigInt> {
+        match *self {
+            Number::Int(i) => Some(BigInt::from(i)),
+            Number::Big(ref b) => Some(b.clone()),
+            Number::Float(_) => None,
+        }
+    }
+
+    /// `+`, `-`, `*`, `/`, `%` and `mod`. `None` for any other operator.
+    pub fn arith(&self, op: BinOp, rhs: &Number) -> Result<Option<Number>, ArithError> {
+        match (self, rhs) {
+            (&Number::Int(l), &Number::Int(r)) => {
+                match int_arith(l, op, r)? {
+                    Some(Some(i)) => Ok(Some(Number::Int(i))),
+                    Some(None) => big_arith(&BigInt::from(l), op, &BigInt::from(r)),
+                    None => Ok(None),
+                }
+            }
+            (&Number::Float(_), _) |
+            (_, &Number::Float(_)) => {
+                Ok(float_arith(self.to_f64(), op, rhs.to_f64()).map(Number::Float))
+            }
+            (l, r) => big_arith(&l.to_big().unwrap(), op, &r.to_big().unwrap()),
+        }
+    }
+
+    /// `/%`
+    pub fn divrem(&self, rhs: &Number) -> Result<(Number, Number), ArithError> {
+        match (self.arith(BinOp::Div, rhs)?, self.arith(BinOp::Rem, rhs)?) {
+            (Some(div), Some(rem)) => Ok((div, rem)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// `-n`
+    pub fn negate(&self) -> Number {
+        match *self {
+            Number::Int(i) => {
+                match i.checked_neg() {
+                    Some(i) => Number::Int(i),
+                    None => Number::Big(-BigInt::from(i)),
+                }
+            }
+            Number::Big(ref b) => Number::from_big(-b),
+            Number::Float(f) => Number::Float(-f),
+        }
+    }
+
+    /// `==`, `!=` and the orderings. `None` for any other operator.
+    pub fn compare(&self, op: BinOp, rhs: &Number) -> Option<bool> {
+        let ord = match (self, rhs) {
+            (&Number::Int(l), &Number::Int(r)) => Some(l.cmp(&r)),
+            (&Number::Float(_), _) |
+            (_, &Number::Float(_)) => self.to_f64().partial_cmp(&rhs.to_f64()),
+            (l, r) => Some(l.to_big().cmp(&r.to_big())),
+        };
+        compare_result(ord, op)
+    }
+}
+
+/// Applies a comparison operator to an ordering. Unordered values, like
+/// `NaN`, are only unequal.
+fn compare_result(ord: Option<Ordering>, op: BinOp) -> Option<bool> {
+    Some(match op {
+        BinOp::Equal => ord == Some(Ordering::Equal),
+        BinOp::NotEqual => ord != Some(Ordering::Equal),
+        BinOp::LessThan => ord == Some(Ordering::Less),
+        BinOp::GreaterThan => ord == Some(Ordering::Greater),
+        BinOp::LessOrEqual => ord == Some(Ordering::Less) || ord == Some(Ordering::Equal),
+        BinOp::GreaterOrEqual => ord == Some(Ordering::Greater) || ord == Some(Ordering::Equal),
+        _ => return None,
+    })
+}
+
+/// Integer arithmetic within an `i64`. The inner `None` means the result
+/// overflowed, and the outer one that `op` isn't arithmetic.
+pub fn int_arith(l: i64, op: BinOp, r: i64) -> Result<Option<Option<i64>>, ArithError> {
+    use ast::BinOp::*;
+
+    match op {
+        Div | Rem | Mod if r == 0 => return Err(ArithError::DivideByZero),
+        // `i64::MIN % -1` traps on most hardware, but the answer is just 0
+        Rem | Mod if r == -1 => return Ok(Some(Some(0))),
+        _ => (),
+    }
+
+    let result = match op {
+        Add => l.checked_add(r),
+        Sub => l.checked_sub(r),
+        Mul => l.checked_mul(r),
+        Div => l.checked_div(r),
+        Rem => l.checked_rem(r),
+        Mod => Some(l.mod_floor(&r)),
+
+        _ => return Ok(None),
+    };
+
+    Ok(Some(result))
+}
+
+/// Integer arithmetic on bigints, with the result demoted if it fits.
+pub fn big_arith(l: &BigInt, op: BinOp, r: &BigInt) -> Result<Option<Number>, ArithError> {
+    use ast::BinOp::*;
+
+    match op {
+        Div | Rem | Mod if r.is_zero() => return Err(ArithError::DivideByZero),
+        _ => (),
+    }
+
+    let result = match op {
+        Add => l + r,
+        Sub => l - r,
+        Mul => l * r,
+        Div => l / r,
+        Rem => l % r,
+        Mod => l.mod_floor(r),
+
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Number::from_big(result)))
+}
+
+pub fn float_arith(l: f64, op: BinOp, r: f64) -> Option<f64> {
+    use ast::BinOp::*;
+
+    Some(match op {
+        Add => l + r,
+        Sub => l - r,
+        Mul => l * r,
+        Div => l / r,
+        Rem => l % r,
+        Mod => {
+            let rem = l % r;
+            if rem != 0.0 && (rem < 0.0) != (r < 0.0) { rem + r } else { rem }
+        }
+
+        _ => return None,
+    })
+}
+
+/// `<<`, `>>` and `>>>`. `None` for any other operator.
+pub fn shift(l: i64, op: BinOp, r: i64) -> Result<Option<i64>, ArithError> {
+    if r < 0 || r >= 64 {
+        return Err(ArithError::ShiftOutOfRange(r));
+    }
+
+    Ok(Some(match op {
+        BinOp::LShiftLeft => ((l as u64) << r) as i64,
+        BinOp::AShiftRight => l >> r,
+        BinOp::LShiftRight => ((l as u64) >> r) as i64,
+        _ => return Ok(None),
+    }))
+}
+
+pub fn int_bitwise(l: i64, op: BinOp, r: i64) -> Option<i64> {
+    match op {
+        BinOp::BitOr => Some(l | r),
+        BinOp::BitAnd => Some(l & r),
+        BinOp::BitXor => Some(l ^ r),
+        _ => None,
+    }
+}
+
+pub fn bool_bitwise(l: bool, op: BinOp, r: bool) -> Option<bool> {
+    match op {
+        BinOp::BitOr => Some(l | r),
+        BinOp::BitAnd => Some(l & r),
+        BinOp::BitXor => Some(l ^ r),
+        _ => None,
+    }
+}
+
+pub fn lane_f32(l: f32, op: BinOp, r: f32) -> Option<f32> {
+    match op {
+        BinOp::Add => Some(l + r),
+        BinOp::Sub => Some(l - r),
+        BinOp::Mul => Some(l * r),
+        BinOp::Div => Some(l / r),
+        _ => None,
+    }
+}
+
+pub fn lane_i32(l: i32, op: BinOp, r: i32) -> Option<i32> {
+    match op {
+        BinOp::Add => Some(l.wrapping_add(r)),
+        BinOp::Sub => Some(l.wrapping_sub(r)),
+        BinOp::Mul => Some(l.wrapping_mul(r)),
+        BinOp::BitAnd => Some(l & r),
+        BinOp::BitOr => Some(l | r),
+        BinOp::BitXor => Some(l ^ r),
+        _ => None,
+    }
+}
+
+pub fn lane_u32(l: u32, op: BinOp, r: u32) -> Option<u32> {
+    match op {
+        BinOp::Add => Some(l.wrapping_add(r)),
+        BinOp::Sub => Some(l.wrapping_sub(r)),
+        BinOp::Mul => Some(l.wrapping_mul(r)),
+        BinOp::BitAnd => Some(l & r),
+        BinOp::BitOr => Some(l | r),
+        BinOp::BitXor => Some(l ^ r),
+        _ => None,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn lang(self) -> LangTrait {
+        match self {
+            UnaryOp::Negate => LangTrait::Neg,
+            UnaryOp::Not => LangTrait::Not,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::Not => "!",
+        }
+    }
+}
+
+/// What an operator evaluates to.
+#[derive(Debug)]
+pub enum Outcome {
+    Value(Value),
+    /// The operand overloads the operator, so the runtime has to call this
+    Call(Overload),
+}
+
+/// `lhs op rhs`, calling the overload if either operand is an object that
+/// has one.
+pub fn binary(lhs: &Value,
+              op: BinOp,
+              rhs: &Value,
+              lang: &LangItems,
+              traits: &HashMap<Symbol, TraitDef>)
+              -> Result<Outcome, OpError> {
+    if is_object(lhs) || is_object(rhs) {
+        if let Some(overload) = lang.binary(op, lhs, rhs, traits) {
+            return Ok(Outcome::Call(overload));
+        }
+    }
+    builtin_binary(lhs, op, rhs).map(Outcome::Value)
+}
+
+/// `-value` or `!value`, calling the overload if `value` is an object that
+/// has one.
+pub fn unary(op: UnaryOp,
+             value: &Value,
+             lang: &LangItems,
+             traits: &HashMap<Symbol, TraitDef>)
+             -> Result<Outcome, OpError> {
+    if is_object(value) {
+        if let Some(overload) = lang.unary(op.lang(), value, &[], traits) {
+            return Ok(Outcome::Call(overload));
+        }
+    }
+    builtin_unary(op, value).map(Outcome::Value)
+}
+
+fn is_object(value: &Value) -> bool {
+    match *value {
+        Value::Object(_) => true,
+        _ => false,
+    }
+}
+
+/// `op value` for values that don't overload `op`.
+pub fn builtin_unary(op: UnaryOp, value: &Value) -> Result<Value, OpError> {
+    match (op, value) {
+        (UnaryOp::Not, &Value::Integer(i)) => Ok(Value::Integer(!i)),
+        (UnaryOp::Not, _) => Ok(Value::Bool(!value.is_truthy())),
+        (UnaryOp::Negate, _) => {
+            match number(value) {
+                Some(num) => Ok(from_number(num.negate())),
+                None => Err(OpError::Unsupported(lang::unsupported(op.as_str(), value, None))),
+            }
+        }
+    }
+}
+
+/// `lhs op rhs` for values that don't overload `op`.
+pub fn builtin_binary(lhs: &Value, op: BinOp, rhs: &Value) -> Result<Value, OpError> {
+    use ast::BinOp::*;
+
+    let result = match op {
+        LogicalOr => Some(if lhs.is_truthy() { lhs.clone() } else { rhs.clone() }),
+        LogicalAnd => Some(if lhs.is_truthy() { rhs.clone() } else { lhs.clone() }),
+        Equal => Some(Value::Bool(equal(lhs, rhs))),
+        NotEqual => Some(Value::Bool(!equal(lhs, rhs))),
+        LessThan | GreaterThan | LessOrEqual | GreaterOrEqual => compare(lhs, op, rhs),
+        BitOr | BitXor | BitAnd => bitwise(lhs, op, rhs),
+        LShiftLeft | LShiftRight | AShiftRight => {
+            match (lhs, rhs) {
+                (&Value::Integer(l), &Value::Integer(r)) => shift(l, op, r)?.map(Value::Integer),
+                _ => None,
+            }
+        }
+        Add | Sub | Mul | Div | Rem | Mod => arithmetic(lhs, op, rhs)?,
+        DivRem => {
+            match (number(lhs), number(rhs)) {
+                (Some(l), Some(r)) => {
+                    let (div, rem) = l.divrem(&r)?;
+                    let pair = vec![from_number(div), from_number(rem)];
+                    Some(Value::Array(Shared::new(pair)))
+                }
+                _ => None,
+            }
+        }
+        Implements | RangeExclusive | RangeInclusive => None,
+    };
+
+    result.ok_or_else(|| OpError::Unsupported(lang::unsupported(binop_str(op), lhs, Some(rhs))))
+}
+
+fn number(value: &Value) -> Option<Number> {
+    match *value {
+        Value::Integer(i) => Some(Number::Int(i)),
+        Value::BigInt(ref b) => Some(Number::Big((**b).clone())),
+        Value::Float(f) => Some(Number::Float(f)),
+        _ => None,
+    }
+}
+
+fn from_number(num: Number) -> Value {
+    match num {
+        Number::Int(i) => Value::Integer(i),
+        Number::Big(b) => Value::BigInt(Rc::new(b)),
+        Number::Float(f) => Value::Float(f),
+    }
+}
+
+/// `lhs == rhs`, when neither overloads `==`.
+pub fn equal(lhs: &Value, rhs: &Value) -> bool {
+    equal_with(lhs, rhs, &mut vec![])
+}
+
+/// `seen` has the addresses of the pairs of arrays already being compared
+/// further out. Comparing such a pair again means both arrays contain
+/// themselves the same way, so the pair is taken to be equal there, and the
+/// rest of the comparison decides. An array that's being modified can only
+/// be compared by identity.
+fn equal_with(lhs: &Value, rhs: &Value, seen: &mut Vec<(usize, usize)>) -> bool {
+    match (lhs, rhs) {
+        (&Value::Nil, &Value::Nil) => true,
+        (&Value::Bool(l), &Value::Bool(r)) => l == r,
+        (&Value::String(ref l), &Value::String(ref r)) => l == r,
+        (&Value::Symbol(l), &Value::Symbol(r)) => l == r,
+        (&Value::Array(ref l), &Value::Array(ref r)) => {
+            if l.ptr_eq(r) {
+                return true;
+            }
+            let pair = (l.addr(), r.addr());
+            if seen.contains(&pair) {
+                return true;
+            }
+            let (l, r) = match (l.try_borrow(), r.try_borrow()) {
+                (Some(l), Some(r)) => (l, r),
+                _ => return false,
+            };
+            seen.push(pair);
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| equal_with(l, r, seen))
+        }
+        (&Value::Object(ref l), &Value::Object(ref r)) => l.ptr_eq(r),
+        (&Value::Table(ref l), &Value::Table(ref r)) => l.ptr_eq(r),
+        (&Value::Weak(ref l), &Value::Weak(ref r)) => l.addr() == r.addr(),
+        (&Value::Simd(ref l), &Value::Simd(ref r)) => simd_equal(l, r),
+        (&Value::PlainFunction(ref l), &Value::PlainFunction(ref r)) => {
+            &**l as *const _ == &**r as *const _
+        }
+        (&Value::ExternFunction(ref l), &Value::ExternFunction(ref r)) => l.ptr_eq(r),
+        _ => {
+            match (number(lhs), number(rhs)) {
+                (Some(l), Some(r)) => l.compare(BinOp::Equal, &r).unwrap(),
+                _ => false,
+            }
+        }
+    }
+}
+
+fn compare(lhs: &Value, op: BinOp, rhs: &Value) -> Option<Value> {
+    let result = match (lhs, rhs) {
+        (&Value::String(ref l), &Value::String(ref r)) => compare_result(l.partial_cmp(r), op),
+        _ => {
+            match (number(lhs), number(rhs)) {
+                (Some(l), Some(r)) => l.compare(op, &r),
+                _ => None,
+            }
+        }
+    };
+    result.map(Value::Bool)
+}
+
+fn bitwise(lhs: &Value, op: BinOp, rhs: &Value) -> Option<Value> {
+    match (lhs, rhs) {
+        (&Value::Integer(l), &Value::Integer(r)) => int_bitwise(l, op, r).map(Value::Integer),
+        (&Value::Bool(l), &Value::Bool(r)) => bool_bitwise(l, op, r).map(Value::Bool),
+        (&Value::Simd(ref l), &Value::Simd(ref r)) => {
+            simd_binary(l, op, r).map(|v| Value::Simd(Box::new(v)))
+        }
+        _ => None,
+    }
+}
+
+fn arithmetic(lhs: &Value, op: BinOp, rhs: &Value) -> Result<Option<Value>, ArithError> {
+    match (lhs, op, rhs) {
+        (&Value::String(ref l), BinOp::Add, &Value::String(ref r)) => {
+            Ok(Some(Value::String(Rc::new(format!("{}{}", l, r)))))
+        }
+        (&Value::Array(ref l), BinOp::Add, &Value::Array(ref r)) => {
+            let mut joined = l.borrow().clone();
+            joined.extend(r.borrow().iter().cloned());
+            Ok(Some(Value::Array(Shared::new(joined))))
+        }
+        (&Value::String(ref s), BinOp::Mul, &Value::Integer(n)) |
+        (&Value::Integer(n), BinOp::Mul, &Value::String(ref s)) => {
+            repeated_len(s.len(), n)?;
+            let count = if s.is_empty() { 0 } else { n as usize };
+            Ok(Some(Value::String(Rc::new(s.repeat(count)))))
+        }
+        (&Value::Array(ref a), BinOp::Mul, &Value::Integer(n)) |
+        (&Value::Integer(n), BinOp::Mul, &Value::Array(ref a)) => {
+            let a = a.borrow();
+            let len = repeated_len(a.len(), n)?;
+            let repeated: Vec<Value> = a.iter().cycle().take(len).cloned().collect();
+            Ok(Some(Value::Array(Shared::new(repeated))))
+        }
+        (&Value::Simd(ref l), _, &Value::Simd(ref r)) => {
+            Ok(simd_binary(l, op, r).map(|v| Value::Simd(Box::new(v))))
+        }
+        _ => {
+            match (number(lhs), number(rhs)) {
+                (Some(l), Some(r)) => Ok(l.arith(op, &r)?.map(from_number)),
+                _ => Ok(None),
+            }
+        }
+    }
+}
+
+/// The length of something `len` long repeated `n` times.
+fn repeated_len(len: usize, n: i64) -> Result<usize, ArithError> {
+    if n < 0 {
+        return Err(ArithError::NegativeCount(n));
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    match (len as u64).checked_mul(n as u64) {
+        Some(total) if total <= MAX_REPEAT_LEN as u64 => Ok(total as usize),
+        _ => Err(ArithError::RepeatTooLong(n)),
+    }
+}
+
+/// Builds a vector from each lane of `f`, or `None` if any lane is.
+fn map_lanes<T, F>(f: F) -> Option<(T, T, T, T)>
+    where F: Fn(u32) -> Option<T>
+{
+    match (f(0), f(1), f(2), f(3)) {
+        (Some(a), Some(b), Some(c), Some(d)) => Some((a, b, c, d)),
+        _ => None,
+    }
+}
+
+fn simd_binary(lhs: &SimdValue, op: BinOp, rhs: &SimdValue) -> Option<SimdValue> {
+    match (*lhs, *rhs) {
+        (SimdValue::F32x4(l), SimdValue::F32x4(r)) => {
+            map_lanes(|i| lane_f32(l.extract(i), op, r.extract(i)))
+                .map(|(a, b, c, d)| SimdValue::F32x4(simd::f32x4::new(a, b, c, d)))
+        }
+        (SimdValue::I32x4(l), SimdValue::I32x4(r)) => {
+            map_lanes(|i| lane_i32(l.extract(i), op, r.extract(i)))
+                .map(|(a, b, c, d)| SimdValue::I32x4(simd::i32x4::new(a, b, c, d)))
+        }
+        (SimdValue::U32x4(l), SimdValue::U32x4(r)) => {
+            map_lanes(|i| lane_u32(l.extract(i), op, r.extract(i)))
+                .map(|(a, b, c, d)| SimdValue::U32x4(simd::u32x4::new(a, b, c, d)))
+        }
+        (SimdValue::Bool32fx4(l), SimdValue::Bool32fx4(r)) => {
+            map_lanes(|i| bool_bitwise(l.extract(i), op, r.extract(i)))
+                .map(|(a, b, c, d)| SimdValue::Bool32fx4(simd::bool32fx4::new(a, b, c, d)))
+        }
+        (SimdValue::Bool32ix4(l), SimdValue::Bool32ix4(r)) => {
+            map_lanes(|i| bool_bitwise(l.extract(i), op, r.extract(i)))
+                .map(|(a, b, c, d)| SimdValue::Bool32ix4(simd::bool32ix4::new(a, b, c, d)))
+        }
+        _ => None,
+    }
+}
+
+fn simd_equal(lhs: &SimdValue, rhs: &SimdValue) -> bool {
+    (0..4).all(|i| {
+        match (*lhs, *rhs) {
+            (SimdValue::F32x4(l), SimdValue::F32x4(r)) => l.extract(i) == r.extract(i),
+            (SimdValue::I32x4(l), SimdValue::I32x4(r)) => l.extract(i) == r.extract(i),
+            (SimdValue::U32x4(l), SimdValue::U32x4(r)) => l.extract(i) == r.extract(i),
+            (SimdValue::Bool32fx4(l), SimdValue::Bool32fx4(r)) => l.extract(i) == r.extract(i),
+            (SimdValue::Bool32ix4(l), SimdValue::Bool32ix4(r)) => l.extract(i) == r.extract(i),
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::{self, ArrayLiteral, Expression, Literal};
+    use ast::BinOp::*;
+    use ast::constant_folding;
+    use lexer::Lexer;
+    use parser::parse_Module;
+    use std::{i32, i64};
+    use vm::value::WeakValue;
+    use vm::value::object::{Object, ObjectFields};
+    use vm::value::table::Table;
+    use vm::runtime::Runtime;
+    use vm::runtime::load::const_value;
+
+    #[derive(Debug)]
+    enum Expected {
+        /// The result, as `{:?}` prints it
+        Prints(&'static str),
+        Arith(ArithError),
+        Unsupported,
+    }
+
+    fn int(i: i64) -> Value {
+        Value::Integer(i)
+    }
+
+    fn float(f: f64) -> Value {
+        Value::Float(f)
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(Rc::new(s.to_string()))
+    }
+
+    fn array(items: Vec<Value>) -> Value {
+        Value::Array(Shared::new(items))
+    }
+
+    fn big(i: i64) -> Value {
+        Value::BigInt(Rc::new(BigInt::from(i) * BigInt::from(i)))
+    }
+
+    fn simd(value: SimdValue) -> Value {
+        Value::Simd(Box::new(value))
+    }
+
+    fn object() -> Value {
+        Value::Object(Shared::new(Object {
+            metatype: None,
+            fields: ObjectFields::new(),
+        }))
+    }
+
+    fn weak(value: &Value) -> Value {
+        Value::Weak(WeakValue::new(value).unwrap())
+    }
+
+    fn rows() -> Vec<(Value, BinOp, Value, Expected)> {
+        use self::Expected::*;
+        let table = Value::Table(Shared::new(Table::new()));
+        let obj = object();
+        let target = array(vec![int(1)]);
+        // Kept alive by the rows, so the weak references don't go nil
+        let other_target = array(vec![int(1)]);
+        let f32s = |a, b, c, d| simd(SimdValue::F32x4(simd::f32x4::new(a, b, c, d)));
+        let i32s = |a, b, c, d| simd(SimdValue::I32x4(simd::i32x4::new(a, b, c, d)));
+        vec![
+            (int(-7), Rem, int(2), Prints("-1")),
+            (int(-7), Mod, int(2), Prints("1")),
+            (int(7), Mod, int(-2), Prints("-1")),
+            (float(-7.5), Rem, float(2.0), Prints("-1.5")),
+            (float(-7.5), Mod, float(2.0), Prints("0.5")),
+            (int(7), Div, int(-2), Prints("-3")),
+            (int(1), Div, int(0), Arith(ArithError::DivideByZero)),
+            (int(1), Mod, int(0), Arith(ArithError::DivideByZero)),
+            (int(i64::MAX), Add, int(1), Prints("9223372036854775808")),
+            (int(i64::MIN), Sub, int(1), Prints("-9223372036854775809")),
+            (int(1), Add, float(0.5), Prints("1.5")),
+            (float(0.5), Mul, int(4), Prints("2.0")),
+            (int(3), Div, float(2.0), Prints("1.5")),
+            (int(-8), AShiftRight, int(1), Prints("-4")),
+            (int(-8), LShiftRight, int(1), Prints("9223372036854775804")),
+            (int(1), LShiftLeft, int(63), Prints("-9223372036854775808")),
+            (int(1), LShiftLeft, int(64), Arith(ArithError::ShiftOutOfRange(64))),
+            (int(1), AShiftRight, int(-1), Arith(ArithError::ShiftOutOfRange(-1))),
+            (int(6), BitAnd, int(3), Prints("2")),
+            (int(6), BitXor, int(3), Prints("5")),
+            (Value::Bool(true), BitOr, Value::Bool(false), Prints("true")),
+            (float(1.0), BitAnd, int(1), Unsupported),
+            (int(7), DivRem, int(-2), Prints("[-3, 1]")),
+            (int(-7), DivRem, int(2), Prints("[-3, -1]")),
+            (float(7.0), DivRem, int(2), Prints("[3.5, 1.0]")),
+            (float(-7.5), DivRem, float(2.0), Prints("[-3.75, -1.5]")),
+            (int(7), DivRem, int(0), Arith(ArithError::DivideByZero)),
+            (string("ab"), Add, string("cd"), Prints("\"abcd\"")),
+            (string("ab"), Mul, int(3), Prints("\"ababab\"")),
+            (int(2), Mul, string("ab"), Prints("\"abab\"")),
+            (string("ab"), Mul, int(0), Prints("\"\"")),
+            (string("ab"), Mul, int(-1), Arith(ArithError::NegativeCount(-1))),
+            (string("ab"), Mul, int(i64::MAX), Arith(ArithError::RepeatTooLong(i64::MAX))),
+            (string(""), Mul, int(i64::MAX), Prints("\"\"")),
+            (string("a"), Add, int(1), Unsupported),
+            (string("a"), Sub, string("a"), Unsupported),
+            (array(vec![int(1), int(2)]), Add, array(vec![int(3)]), Prints("[1, 2, 3]")),
+            (array(vec![int(1), int(2)]), Mul, int(2), Prints("[1, 2, 1, 2]")),
+            (int(2), Mul, array(vec![int(1)]), Prints("[1, 1]")),
+            (array(vec![int(1), int(2)]),
+             Mul,
+             int(i64::MAX),
+             Arith(ArithError::RepeatTooLong(i64::MAX))),
+            (array(vec![]), Mul, int(i64::MAX), Prints("[]")),
+            (array(vec![int(1)]), Mul, int(-2), Arith(ArithError::NegativeCount(-2))),
+            (array(vec![int(1), int(2)]), Equal, array(vec![int(1), int(2)]), Prints("true")),
+            (array(vec![int(1)]), Equal, array(vec![float(1.0)]), Prints("true")),
+            (array(vec![array(vec![int(2)])]),
+             Equal,
+             array(vec![array(vec![int(3)])]),
+             Prints("false")),
+            (array(vec![int(1)]), NotEqual, array(vec![int(1), int(1)]), Prints("true")),
+            (int(1), Equal, float(1.0), Prints("true")),
+            (int(1), Equal, string("1"), Prints("false")),
+            (Value::Nil, Equal, Value::Bool(false), Prints("false")),
+            (string("a"), LessThan, string("b"), Prints("true")),
+            (int(2), GreaterOrEqual, float(2.5), Prints("false")),
+            (string("a"), LessThan, int(1), Unsupported),
+            (Value::Nil, LogicalOr, int(3), Prints("3")),
+            (int(0), LogicalAnd, int(3), Prints("3")),
+            (Value::Bool(false), LogicalAnd, int(3), Prints("false")),
+            (big(1 << 32), Sub, big(1 << 32), Prints("0")),
+            (big(1 << 32), Add, int(-1), Prints("18446744073709551615")),
+            (big(1 << 32), DivRem, int(1 << 32), Prints("[4294967296, 0]")),
+            (big(1 << 32), Div, int(0), Arith(ArithError::DivideByZero)),
+            (big(1 << 32), GreaterThan, int(i64::MAX), Prints("true")),
+            (big(1 << 32), Equal, float(18446744073709551616.0), Prints("true")),
+            (big(1 << 32), BitAnd, int(1), Unsupported),
+            (big(1 << 32), LShiftLeft, int(1), Unsupported),
+            (Value::Symbol(Symbol::intern("a")), Equal, Value::Symbol(Symbol::intern("a")),
+             Prints("true")),
+            (Value::Symbol(Symbol::intern("a")), Equal, Value::Symbol(Symbol::intern("b")),
+             Prints("false")),
+            (Value::Symbol(Symbol::intern("a")), Equal, string("a"), Prints("false")),
+            (Value::Symbol(Symbol::intern("a")), LessThan, Value::Symbol(Symbol::intern("b")),
+             Unsupported),
+            (Value::Symbol(Symbol::intern("a")), Add, Value::Symbol(Symbol::intern("b")),
+             Unsupported),
+            (table.clone(), Equal, table.clone(), Prints("true")),
+            (table.clone(), Equal, Value::Table(Shared::new(Table::new())), Prints("false")),
+            (table.clone(), Add, table.clone(), Unsupported),
+            (table.clone(), LogicalAnd, int(1), Prints("1")),
+            (obj.clone(), Equal, obj.clone(), Prints("true")),
+            (obj.clone(), NotEqual, object(), Prints("true")),
+            (obj.clone(), Add, int(1), Unsupported),
+            (obj.clone(), LessThan, obj.clone(), Unsupported),
+            (f32s(1.0, 2.0, 3.0, 4.0), Add, f32s(0.5, 0.5, 0.5, 0.5),
+             Prints("<1.5, 2.5, 3.5, 4.5:f32>")),
+            (i32s(i32::MAX, 1, 2, 3), Add, i32s(1, 1, 1, 1),
+             Prints("<-2147483648, 2, 3, 4:i32>")),
+            (f32s(1.0, 2.0, 3.0, 4.0), Equal, f32s(1.0, 2.0, 3.0, 4.0), Prints("true")),
+            (f32s(1.0, 2.0, 3.0, 4.0), Equal, i32s(1, 2, 3, 4), Prints("false")),
+            (f32s(1.0, 2.0, 3.0, 4.0), Add, i32s(1, 2, 3, 4), Unsupported),
+            (i32s(1, 2, 3, 4), Mul, int(2), Unsupported),
+            (weak(&target), Equal, weak(&target), Prints("true")),
+            (weak(&target), Equal, weak(&other_target), Prints("false")),
+            (weak(&target), Equal, target.clone(), Prints("false")),
+            (weak(&target), Add, int(1), Unsupported),
+            (target.clone(), Equal, other_target.clone(), Prints("true")),
+            (obj.clone(), Implements, obj.clone(), Unsupported),
+            (int(1), RangeExclusive, int(3), Unsupported),
+            (int(1), RangeInclusive, int(3), Unsupported),
+        ]
+    }
+
+    fn check(result: &Result<Value, OpError>, expected: &Expected) -> bool {
+        match (result, expected) {
+            (&Ok(ref value), &Expected::Prints(text)) => format!("{:?}", value) == text,
+            (&Err(OpError::Arith(err)), &Expected::Arith(expected)) => err == expected,
+            (&Err(OpError::Unsupported(_)), &Expected::Unsupported) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn binary_operators() {
+        for (lhs, op, rhs, expected) in rows() {
+            let result = builtin_binary(&lhs, op, &rhs);
+            assert!(check(&result, &expected),
+                    "{:?} {} {:?} gave {:?}, expected {:?}",
+                    lhs,
+                    binop_str(op),
+                    rhs,
+                    result,
+                    expected);
+        }
+    }
+
+    fn literal(value: &Value) -> Option<Expression> {
+        Some(match *value {
+            Value::Nil => Expression::Nil,
+            Value::Bool(b) => Expression::Literal(Literal::Bool(b)),
+            Value::Integer(i) => Expression::Literal(Literal::Integer(i)),
+            Value::Float(f) => Expression::Literal(Literal::Float(f)),
+            Value::String(ref s) => Expression::Literal(Literal::String(format!("\"{}\"", s))),
+            Value::Array(ref a) => {
+                let items = a.borrow().iter().map(literal).collect::<Option<_>>()?;
+                Expression::Literal(Literal::Array(ArrayLiteral::List(items)))
+            }
+            _ => return None,
+        })
+    }
+
+    /// Whatever folding makes of a row has to be what the runtime does.
+    #[test]
+    fn folding_agrees() {
+        let (mut literals, mut folded) = (0, 0);
+        for (lhs, op, rhs, expected) in rows() {
+            let (l, r) = match (literal(&lhs), literal(&rhs)) {
+                (Some(l), Some(r)) => (l, r),
+                _ => continue,
+            };
+            literals += 1;
+            let location = ast::Location::default();
+            let mut expr = Expression::BinaryOp(Box::new(l), op, Box::new(r), location);
+            let agrees = match (constant_folding::simplify(&mut expr), &expected) {
+                (Some(diag), &Expected::Arith(err)) => diag.error == err,
+                (Some(_), _) => false,
+                // Not folded, so left for the runtime
+                (None, _) if const_value(&expr).is_none() => continue,
+                (None, _) => check(&Ok(const_value(&expr).unwrap()), &expected),
+            };
+            folded += 1;
+            assert!(agrees, "folding {:?} {} {:?} disagrees", lhs, binop_str(op), rhs);
+        }
+        assert!(folded > literals / 2, "only {} of {} rows folded", folded, literals);
+    }
+
+    #[test]
+    fn arrays_containing_themselves() {
+        let a = Shared::new(vec![]);
+        a.borrow_mut().push(Value::Array(a.clone()));
+        let b = Shared::new(vec![]);
+        b.borrow_mut().push(Value::Array(b.clone()));
+        let c = Shared::new(vec![int(1)]);
+        c.borrow_mut().push(Value::Array(c.clone()));
+
+        assert!(equal(&Value::Array(a.clone()), &Value::Array(b.clone())));
+        assert!(!equal(&Value::Array(a.clone()), &Value::Array(c.clone())));
+
+        // Emptied so the cycles don't leak
+        for cycle in &[a, b, c] {
+            cycle.borrow_mut().clear();
+        }
+    }
+
+    #[test]
+    fn arrays_being_modified_compare_by_identity() {
+        let a = Shared::new(vec![int(1)]);
+        let b = Shared::new(vec![int(1)]);
+        let _borrowed = a.borrow_mut();
+        assert!(!equal(&Value::Array(a.clone()), &Value::Array(b.clone())));
+        assert!(equal(&Value::Array(a.clone()), &Value::Array(a.clone())));
+    }
+
+    const OVERLOADS: &'static str = "
+        @lang(\"add\") trait Add { fn add(self, rhs); }
+        @lang(\"eq\") trait Eq { fn eq(self, rhs); }
+        @lang(\"ord\") trait Ord: Eq { fn cmp(self, rhs); }
+        @lang(\"neg\") trait Neg { fn neg(self); }
+        type Vec2 { x, y }
+        type Plain;
+        impl Add for Vec2 { fn add(self, rhs) { return rhs; } }
+        impl Eq for Vec2 { fn eq(self, rhs) { return true; } }
+        impl Ord for Vec2 { fn cmp(self, rhs) { return 0; } }
+        impl Neg for Vec2 { fn neg(self) { return self; } }
+    ";
+
+    /// The method called and what it's called with, or the value.
+    fn outcome(result: Result<Outcome, OpError>) -> String {
+        match result {
+            Ok(Outcome::Call(call)) => {
+                format!("{}{:?} {:?}", call.method.name, call.args, call.adjust)
+            }
+            Ok(Outcome::Value(value)) => format!("{:?}", value),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn objects_overload_operators() {
+        let runtime = Runtime::new(parse_Module(Lexer::new(OVERLOADS)).unwrap()).unwrap();
+        let sym = Symbol::intern;
+        let fields = vec![(sym("x"), int(1)), (sym("y"), int(2))];
+        let v = runtime.new_object(sym("Vec2"), fields).unwrap();
+        let p = runtime.new_object(sym("Plain"), vec![]).unwrap();
+        let bin = |lhs: &Value, op, rhs: &Value| {
+            outcome(binary(lhs, op, rhs, &runtime.lang, &runtime.traits))
+        };
+        let un = |op, value: &Value| outcome(unary(op, value, &runtime.lang, &runtime.traits));
+
+        assert_eq!(bin(&v, Add, &int(3)), "add[Vec2 { x: 1, y: 2 }, 3] None");
+        assert_eq!(bin(&int(3), Add, &v), "add[3, Vec2 { x: 1, y: 2 }] None");
+        assert_eq!(bin(&v, NotEqual, &p), "eq[Vec2 { x: 1, y: 2 }, Plain {}] Not");
+        assert_eq!(bin(&v, LessThan, &int(3)),
+                   "cmp[Vec2 { x: 1, y: 2 }, 3] Compare(LessThan)");
+        assert_eq!(un(UnaryOp::Negate, &v), "neg[Vec2 { x: 1, y: 2 }] None");
+
+        // No overload, so the builtin applies
+        assert_eq!(bin(&v, Mul, &int(3)), "can't apply `*` to object and int");
+        assert_eq!(bin(&p, Add, &int(3)), "can't apply `+` to object and int");
+        assert_eq!(bin(&p, Equal, &p), "true");
+        assert_eq!(un(UnaryOp::Not, &v), "false");
+        assert_eq!(un(UnaryOp::Negate, &p), "can't apply `-` to object");
+        assert_eq!(bin(&int(1), Add, &int(2)), "3");
+        assert_eq!(un(UnaryOp::Negate, &int(i64::MIN)), "9223372036854775808");
+        assert_eq!(un(UnaryOp::Not, &int(0)), "-1");
+    }
+}
@@ -0,0 +1,494 @@
+//! Building a module's types and traits.
+//!
+//! The module is type checked first, and the mismatches `ast::typeck`
+//! finds are load errors like any other.
+//!
+//! Traits marked `@lang` are registered as the overloads of their
+//! operators, see `vm::lang`.
+//!
+//! Each `impl` of a trait is completed from the trait's defaults, and the
+//! `impl`s of a type without a trait are merged into one inherent impl. A
+//! type has to implement the supertraits of every trait it implements.
+//! Types and traits are found by name alone, so inline child modules share
+//! one namespace with the root module. The runtime can't evaluate
+//! expressions yet, so a const in a trait or `impl`, and the default of a
+//! field, has to fold to a literal, or an array of them.
+
+use ast::{self, Location};
+use ast::{constant_folding, resolve, typeck};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use vm::lang::{LangError, LangItems};
+use vm::value::{Symbol, Value};
+use vm::value::object::{ImplError, Layout, MetaType, TraitDef, TypeImpl};
+use vm::runtime::PRELUDE;
+use vm::value::shared::Shared;
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// A type or trait declared again; the location is the first one
+    Duplicate(Symbol, Location),
+    /// An `impl` for a type that isn't declared
+    UnknownType(Symbol),
+    /// An `impl` of a trait that isn't declared
+    UnknownTrait(Symbol),
+    /// A second `impl` of the trait for the type
+    DuplicateImpl(Symbol, Symbol),
+    /// An `impl` of the trait for the type leaves out something without a
+    /// default
+    Incomplete(Symbol, Symbol, ImplError),
+    /// A const that doesn't fold to a literal
+    NotConstant(Symbol),
+    /// The default of the type's field doesn't fold to a literal
+    DefaultNotConstant(Symbol, Symbol),
+    /// The type implements the first trait but not the second, which is one
+    /// of its supertraits
+    MissingSupertrait(Symbol, Symbol, Symbol),
+    Type(typeck::TypeError),
+    /// A bad `@lang` attribute on a trait
+    Lang(LangError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Duplicate(name, loc) => {
+                write!(fmt,
+                       "`{}` is already declared at {}:{}",
+                       name,
+                       loc.line,
+                       loc.column)
+            }
+            LoadError::UnknownType(name) => write!(fmt, "there is no type `{}`", name),
+            LoadError::UnknownTrait(name) => write!(fmt, "there is no trait `{}`", name),
+            LoadError::DuplicateImpl(ty, tr) => {
+                write!(fmt, "`{}` is implemented for `{}` more than once", tr, ty)
+            }
+            LoadError::Incomplete(ty, tr, ImplError::MissingConst(name)) => {
+                write!(fmt,
+                       "`impl {} for {}` is missing const `{}`, which has no default",
+                       tr,
+                       ty,
+                       name)
+            }
+            LoadError::Incomplete(ty, tr, ImplError::MissingMethod(name)) => {
+                write!(fmt,
+                       "`impl {} for {}` is missing `{}`, which has no default",
+                       tr,
+                       ty,
+                       name)
+            }
+            LoadError::NotConstant(name) => {
+                write!(fmt, "const `{}` isn't a constant value", name)
+            }
+            LoadError::DefaultNotConstant(ty, field) => {
+                write!(fmt, "the default of `{}.{}` isn't a constant value", ty, field)
+            }
+            LoadError::MissingSupertrait(ty, tr, sup) => {
+                write!(fmt,
+                       "`{}` implements `{}` but not its supertrait `{}`",
+                       ty,
+                       tr,
+                       sup)
+            }
+            LoadError::Type(ref error) => error.fmt(fmt),
+            LoadError::Lang(ref error) => error.fmt(fmt),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub location: Location,
+    pub error: LoadError,
+}
+
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub types: HashMap<Symbol, Rc<MetaType>>,
+    pub traits: HashMap<Symbol, TraitDef>,
+    pub lang: LangItems,
+}
+
+/// Builds every type and trait declared in `root` and its inline modules.
+pub fn load_module(root: &ast::Module) -> Result<Loaded, Vec<Diagnostic>> {
+    let mut items = vec![];
+    collect_items(root, &mut items);
+
+    let mut loader = Loader {
+        traits: HashMap::new(),
+        types: HashMap::new(),
+        lang: LangItems::new(),
+        declared: HashMap::new(),
+        impls: HashMap::new(),
+        diagnostics: vec![],
+    };
+
+    loader.check_types(root);
+    for &item in &items {
+        match *item {
+            ast::Item::Trait(_, ref tr) => loader.check_consts(&tr.constants),
+            ast::Item::TypeImpl(_, ref imp) => loader.check_consts(&imp.constants),
+            _ => (),
+        }
+    }
+    for &item in &items {
+        match *item {
+            ast::Item::Trait(ref attrs, ref tr) => loader.add_trait(attrs, tr),
+            ast::Item::TypeDecl(_, ref decl) => loader.add_type(decl),
+            _ => (),
+        }
+    }
+    for &item in &items {
+        if let ast::Item::TypeImpl(_, ref imp) = *item {
+            loader.add_impl(imp);
+        }
+    }
+    loader.check_supertraits();
+
+    if !loader.diagnostics.is_empty() {
+        loader.diagnostics.sort_by_key(|diag| diag.location.index);
+        return Err(loader.diagnostics);
+    }
+
+    Ok(Loaded {
+        types: loader.types.into_iter().map(|(name, meta)| (name, Rc::new(meta))).collect(),
+        traits: loader.traits,
+        lang: loader.lang,
+    })
+}
+
+fn collect_items<'a>(module: &'a ast::Module, items: &mut Vec<&'a ast::Item>) {
+    let module_items = match *module {
+        ast::Module::Root { ref items } |
+        ast::Module::Inline { ref items, .. } => items,
+        ast::Module::Extern { .. } => return,
+    };
+
+    for item in module_items {
+        match *item {
+            ast::Item::Module(ref child) => collect_items(child, items),
+            _ => items.push(item),
+        }
+    }
+}
+
+/// The value of a const's initializer, if it folds to a literal.
+pub fn const_value(expr: &ast::Expression) -> Option<Value> {
+    let mut expr = expr.clone();
+    if !constant_folding::fold(&mut expr).is_empty() {
+        return None;
+    }
+
+    match expr {
+        ast::Expression::Nil => Some(Value::Nil),
+        ast::Expression::Literal(ref lit) => literal_value(lit),
+        _ => None,
+    }
+}
+
+fn literal_value(lit: &ast::Literal) -> Option<Value> {
+    match *lit {
+        ast::Literal::Integer(i) => Some(Value::Integer(i)),
+        ast::Literal::BigInt(ref s) => s.parse().ok().map(|big| Value::BigInt(Rc::new(big))),
+        ast::Literal::Suffixed(ref lit, _) => literal_value(lit),
+        ast::Literal::Float(f) => Some(Value::Float(f)),
+        ast::Literal::Bool(b) => Some(Value::Bool(b)),
+        // Literals are kept as written, and escapes aren't decoded yet
+        ast::Literal::String(ref s) if !s.contains('\\') => {
+            Some(Value::String(Rc::new(s[1..s.len() - 1].to_string())))
+        }
+        ast::Literal::Symbol(ref name) => Some(Value::Symbol(Symbol::intern(name))),
+        ast::Literal::Array(ast::ArrayLiteral::List(ref items)) => {
+            let items: Option<Vec<Value>> = items.iter().map(const_value).collect();
+            items.map(|items| Value::Array(Shared::new(items)))
+        }
+        _ => None,
+    }
+}
+
+fn eval(expr: &ast::Expression) -> Value {
+    // Only reached once `check_consts` has passed
+    const_value(expr).unwrap_or(Value::Nil)
+}
+
+struct Loader {
+    traits: HashMap<Symbol, TraitDef>,
+    types: HashMap<Symbol, MetaType>,
+    lang: LangItems,
+    /// Where each type and trait was declared
+    declared: HashMap<Symbol, Location>,
+    /// Where each trait was implemented for each type
+    impls: HashMap<(Symbol, Symbol), Location>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Loader {
+    fn report(&mut self, location: Location, error: LoadError) {
+        self.diagnostics.push(Diagnostic {
+            location: location,
+            error: error,
+        });
+    }
+
+    /// Reports what the type checker finds, except for missing supertraits,
+    /// which `check_supertraits` reports from the loaded traits.
+    fn check_types(&mut self, root: &ast::Module) {
+        let resolution = resolve::resolve_module(root, PRELUDE);
+        for diag in typeck::check_module(root, &resolution) {
+            match diag.error {
+                typeck::TypeError::MissingSupertrait(..) => (),
+                error => self.report(diag.location, LoadError::Type(error)),
+            }
+        }
+    }
+
+    fn check_consts(&mut self, consts: &[ast::AssocConst]) {
+        for c in consts {
+            if let Some(ref value) = c.value {
+                if const_value(value).is_none() {
+                    self.report(c.location, LoadError::NotConstant(Symbol::intern(&c.name)));
+                }
+            }
+        }
+    }
+
+    /// Whether `name` is new, reporting it if it isn't.
+    fn declare(&mut self, name: Symbol, location: Location) -> bool {
+        let previous = self.declared.get(&name).cloned();
+        if let Some(previous) = previous {
+            self.report(location, LoadError::Duplicate(name, previous));
+            return false;
+        }
+        self.declared.insert(name, location);
+        true
+    }
+
+    fn add_trait(&mut self, attrs: &[ast::Attribute], tr: &ast::Trait) {
+        let name = Symbol::intern(&tr.name);
+        if !self.declare(name, tr.location) {
+            return;
+        }
+        self.traits.insert(name, TraitDef::from_ast(tr, eval));
+        if let Err(error) = self.lang.register(attrs, tr) {
+            self.report(tr.location, LoadError::Lang(error));
+        }
+    }
+
+    fn add_type(&mut self, decl: &ast::TypeDecl) {
+        let name = Symbol::intern(&decl.name);
+        if !self.declare(name, decl.location) {
+            return;
+        }
+        for field in decl.fields.iter().flat_map(|fields| fields) {
+            if let Some(ref default) = field.default {
+                if const_value(default).is_none() {
+                    let error = LoadError::DefaultNotConstant(name, Symbol::intern(&field.name));
+                    self.report(field.location, error);
+                }
+            }
+        }
+
+        self.types.insert(name,
+                          MetaType {
+                              name: name,
+                              layout: Layout::from_decl(decl),
+                              inherent_type: TypeImpl {
+                                  name: name,
+                                  interface: None,
+                                  constants: HashMap::new(),
+                                  static_methods: HashMap::new(),
+                                  member_methods: HashMap::new(),
+                              },
+                              trait_impls: vec![],
+                          });
+    }
+
+    fn add_impl(&mut self, imp: &ast::TypeImpl) {
+        let ty = Symbol::intern(&imp.name);
+        let mut built = TypeImpl::from_ast(imp, eval);
+
+        if !self.types.contains_key(&ty) {
+            return self.report(imp.location, LoadError::UnknownType(ty));
+        }
+
+        let tr = match built.interface {
+            Some(tr) => tr,
+            None => {
+                let inherent = &mut self.types.get_mut(&ty).unwrap().inherent_type;
+                inherent.constants.extend(built.constants);
+                inherent.static_methods.extend(built.static_methods);
+                inherent.member_methods.extend(built.member_methods);
+                return;
+            }
+        };
+
+        match self.traits.get(&tr).map(|def| def.complete(&mut built)) {
+            Some(Ok(())) => (),
+            Some(Err(error)) => {
+                return self.report(imp.location, LoadError::Incomplete(ty, tr, error))
+            }
+            None => return self.report(imp.location, LoadError::UnknownTrait(tr)),
+        }
+
+        let meta = self.types.get_mut(&ty).unwrap();
+        if meta.trait_impl(tr).is_some() {
+            return self.report(imp.location, LoadError::DuplicateImpl(ty, tr));
+        }
+        meta.trait_impls.push(built);
+        self.impls.insert((ty, tr), imp.location);
+    }
+
+    /// Reports each type that implements a trait without its supertraits,
+    /// at the `impl` of the trait.
+    fn check_supertraits(&mut self) {
+        let mut missing = vec![];
+        for meta in self.types.values() {
+            if let Err((tr, sup)) = meta.check_supertraits(&self.traits) {
+                missing.push((self.impls[&(meta.name, tr)],
+                              LoadError::MissingSupertrait(meta.name, tr, sup)));
+            }
+        }
+        for (location, error) in missing {
+            self.report(location, error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use vm::lang::LangTrait;
+    use parser::parse_Module;
+
+    fn load(source: &str) -> Result<Loaded, Vec<Diagnostic>> {
+        load_module(&parse_Module(Lexer::new(source)).unwrap())
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        match load(source) {
+            Ok(_) => vec![],
+            Err(diags) => diags.iter().map(|diag| diag.error.to_string()).collect(),
+        }
+    }
+
+    const SHAPE: &'static str = "
+        trait Shape {
+            const SIDES;
+            const NAME = \"shape\";
+            fn area(self);
+            fn describe(self) { return self.NAME; }
+        }
+        type Square { side }
+    ";
+
+    #[test]
+    fn impls_are_completed_from_defaults() {
+        let source = format!("{}
+            impl Shape for Square {{
+                const SIDES = 2 * 2;
+                fn area(self) {{ return self.side * self.side; }}
+            }}
+            impl Square {{
+                fn new(side) {{ return new_object Square {{ side }}; }}
+            }}", SHAPE);
+        let loaded = load(&source).unwrap();
+
+        let square = &loaded.types[&Symbol::intern("Square")];
+        let imp = square.trait_impl(Symbol::intern("Shape")).unwrap();
+        assert_eq!(format!("{:?}", imp.constants[&Symbol::intern("SIDES")]), "4");
+        assert_eq!(format!("{:?}", imp.constants[&Symbol::intern("NAME")]), "\"shape\"");
+        assert!(imp.member_methods.contains_key(&Symbol::intern("describe")));
+        assert!(square.inherent_type.static_methods.contains_key(&Symbol::intern("new")));
+    }
+
+    #[test]
+    fn methods_are_found_in_impl_order() {
+        let traits = "
+            trait Named { fn name(self); fn greet(self) { return 1; } }
+            trait Titled { fn name(self); fn greet(self) { return 2; } }
+            type Knight;
+        ";
+        let named = "impl Named for Knight { fn name(self) { return 1; } }";
+        let titled = "impl Titled for Knight { fn name(self) { return 2; } }";
+        let found = |source: String, name: &str| {
+            let loaded = load(&source).unwrap();
+            let knight = &loaded.types[&Symbol::intern("Knight")];
+            let func = knight.find_method(Symbol::intern(name), true, &loaded.traits).unwrap();
+            format!("{:?}", func.body.statements[0])
+        };
+
+        let named_first = format!("{}{}\n{}", traits, named, titled);
+        let titled_first = format!("{}{}\n{}", traits, titled, named);
+        for _ in 0..16 {
+            assert!(found(named_first.clone(), "name").contains("Integer(1)"));
+            assert!(found(named_first.clone(), "greet").contains("Integer(1)"));
+            assert!(found(titled_first.clone(), "name").contains("Integer(2)"));
+            assert!(found(titled_first.clone(), "greet").contains("Integer(2)"));
+        }
+    }
+
+    #[test]
+    fn incomplete_impls_are_reported() {
+        let source = format!("{}
+            impl Shape for Square {{
+                const SIDES = 4;
+            }}", SHAPE);
+        assert_eq!(errors(&source),
+                   vec!["`impl Shape for Square` is missing `area`, which has no default"]);
+
+        let source = format!("{}
+            impl Shape for Square {{
+                fn area(self) {{ return 0; }}
+            }}", SHAPE);
+        assert_eq!(errors(&source),
+                   vec!["`impl Shape for Square` is missing const `SIDES`, which has no default"]);
+    }
+
+    #[test]
+    fn bad_impls_are_reported() {
+        assert_eq!(errors("impl Nothing { }"), vec!["there is no type `Nothing`"]);
+        assert_eq!(errors("type T; impl Nothing for T { }"),
+                   vec!["there is no trait `Nothing`"]);
+        assert_eq!(errors("trait A { } type T; impl A for T { } impl A for T { }"),
+                   vec!["`A` is implemented for `T` more than once"]);
+        assert_eq!(errors("type T; impl T { const X = f(); }"),
+                   vec!["const `X` isn't a constant value"]);
+        assert_eq!(errors("type T; mod inner { type T; }").len(), 1);
+        assert_eq!(errors("type T { a = 1 + 2, b = f() }"),
+                   vec!["the default of `T.b` isn't a constant value"]);
+    }
+
+    #[test]
+    fn supertraits_must_be_implemented() {
+        let traits = "trait Eq { fn eq(self, rhs); } trait Ord: Eq { fn cmp(self, rhs); } type T;";
+        let ord = "impl Ord for T { fn cmp(self, rhs) { return 0; } }";
+        let eq = "impl Eq for T { fn eq(self, rhs) { return true; } }";
+
+        assert_eq!(errors(&format!("{} {}", traits, ord)),
+                   vec!["`T` implements `Ord` but not its supertrait `Eq`"]);
+        assert_eq!(errors(&format!("{} {} {}", traits, ord, eq)), Vec::<String>::new());
+    }
+
+    #[test]
+    fn type_errors_fail_the_load() {
+        assert_eq!(errors("fn f(x: int) -> string { return x; }"),
+                   vec!["expected `string`, found `int`"]);
+        assert_eq!(errors("fn f(x) -> string { print(x); return x; }"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn lang_traits_are_registered() {
+        let loaded = load("@lang(\"add\") trait Plus { fn add(self, rhs); }").unwrap();
+        assert_eq!(loaded.lang.trait_symbol(LangTrait::Add), Some(Symbol::intern("Plus")));
+        assert_eq!(loaded.lang.trait_symbol(LangTrait::Sub), None);
+
+        assert_eq!(errors("@lang(\"iterator\") trait Iterator { fn next(self); }"),
+                   vec!["there is no lang trait `iterator`"]);
+        assert_eq!(errors("@lang(\"neg\") trait A { fn neg(self); }
+                           @lang(\"neg\") trait B { fn neg(self); }"),
+                   vec!["lang trait `neg` is declared more than once"]);
+    }
+}
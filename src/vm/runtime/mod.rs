@@ -1,12 +1,277 @@
+use ast;
+use ast::visit::{self, Visitor};
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use vm::lang::LangItems;
 use vm::value::{Symbol, Value};
+use vm::value::object::{FieldError, MetaType, Object, TraitDef};
+use vm::value::shared::Shared;
+
+pub mod load;
+
+/// Functions the runtime provides to every script
+pub const PRELUDE: &'static [&'static str] = &["print", "panic"];
 
 pub struct Runtime {
     pub root_module: ast::Module,
+    pub types: HashMap<Symbol, Rc<MetaType>>,
+    pub traits: HashMap<Symbol, TraitDef>,
+    /// The traits that overload operators
+    pub lang: LangItems,
+    /// The symbol of every name the module declares or refers to
+    pub symbols: HashMap<ast::Identifier, Symbol>,
+}
+
+impl Runtime {
+    /// Loads a module, interning every name it declares or refers to, type
+    /// checking it and building its types and traits.
+    pub fn new(root_module: ast::Module) -> Result<Runtime, Vec<load::Diagnostic>> {
+        let mut names = Names { symbols: HashMap::new() };
+        names.visit_module(&root_module);
+        let loaded = load::load_module(&root_module)?;
+        Ok(Runtime {
+            root_module: root_module,
+            types: loaded.types,
+            traits: loaded.traits,
+            lang: loaded.lang,
+            symbols: names.symbols,
+        })
+    }
+
+    /// `new_object name { given }`. The fields are checked against the
+    /// type's layout, and the missing ones set to their defaults.
+    pub fn new_object(&self,
+                      name: Symbol,
+                      given: Vec<(Symbol, Value)>)
+                      -> Result<Value, ConstructError> {
+        let meta = match self.types.get(&name) {
+            Some(meta) => meta.clone(),
+            None => return Err(ConstructError::UnknownType(name)),
+        };
+        // The loader has checked that every default is constant
+        let eval = |expr: &ast::Expression| load::const_value(expr).unwrap_or(Value::Nil);
+        Object::construct(meta, given, eval)
+            .map(|obj| Value::Object(Shared::new(obj)))
+            .map_err(|err| ConstructError::Field(name, err))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConstructError {
+    UnknownType(Symbol),
+    Field(Symbol, FieldError),
+}
+
+impl fmt::Display for ConstructError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConstructError::UnknownType(name) => write!(fmt, "there is no type `{}`", name),
+            ConstructError::Field(ty, FieldError::Unknown(field)) => {
+                write!(fmt, "`{}` has no field `{}`", ty, field)
+            }
+            ConstructError::Field(ty, FieldError::Missing(field)) => {
+                write!(fmt, "missing field `{}`, which `{}` has no default for", field, ty)
+            }
+        }
+    }
 }
 
 pub struct Scope {
     pub using: HashMap<Symbol, Value>,
     pub vars: HashMap<Symbol, Value>,
     pub consts: HashMap<Symbol, Value>,
-}
\ No newline at end of file
+}
+
+/// A function implemented in Rust and exposed to scripts. It returns the
+/// value thrown on error.
+#[derive(Clone)]
+pub struct Function {
+    pub name: String,
+    /// `None` if it takes any number of arguments
+    pub arity: Option<usize>,
+    pub call: Rc<Fn(&[Value]) -> Result<Value, Value>>,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Function({})", self.name)
+    }
+}
+
+/// Interns the names of items, fields, methods, variables and symbol
+/// literals.
+struct Names {
+    symbols: HashMap<ast::Identifier, Symbol>,
+}
+
+impl Names {
+    fn intern(&mut self, name: &ast::Identifier) {
+        if !self.symbols.contains_key(name) {
+            self.symbols.insert(name.clone(), Symbol::intern(name));
+        }
+    }
+
+    fn intern_all<'a, I>(&mut self, names: I)
+        where I: IntoIterator<Item = &'a ast::Identifier>
+    {
+        for name in names {
+            self.intern(name);
+        }
+    }
+}
+
+impl Visitor for Names {
+    fn visit_module(&mut self, module: &ast::Module) {
+        match *module {
+            ast::Module::Inline { ref name, .. } |
+            ast::Module::Extern { ref name, .. } => {
+                self.intern(name);
+            }
+            ast::Module::Root { .. } => (),
+        }
+        visit::walk_module(self, module);
+    }
+
+    fn visit_item(&mut self, item: &ast::Item) {
+        match *item {
+            ast::Item::Extern(ref name, _) |
+            ast::Item::Const(_, ref name, _, _) => {
+                self.intern(name);
+            }
+            _ => (),
+        }
+        visit::walk_item(self, item);
+    }
+
+    fn visit_type_decl(&mut self, decl: &ast::TypeDecl) {
+        self.intern(&decl.name);
+        self.intern_all(decl.fields.iter().flat_map(|fields| fields).map(|field| &field.name));
+        visit::walk_type_decl(self, decl);
+    }
+
+    fn visit_type_impl(&mut self, imp: &ast::TypeImpl) {
+        self.intern(&imp.name);
+        self.intern_all(&imp.interface);
+        self.intern_all(imp.constants.iter().map(|c| &c.name));
+        visit::walk_type_impl(self, imp);
+    }
+
+    fn visit_trait(&mut self, tr: &ast::Trait) {
+        self.intern(&tr.name);
+        self.intern_all(&tr.supertraits);
+        self.intern_all(tr.constants.iter().map(|c| &c.name));
+        visit::walk_trait(self, tr);
+    }
+
+    fn visit_trait_function(&mut self, func: &ast::TraitFunction) {
+        self.intern(&func.name);
+        self.intern_all(&func.parameters);
+        visit::walk_trait_function(self, func);
+    }
+
+    fn visit_function(&mut self, func: &ast::Function) {
+        self.intern(&func.name);
+        self.intern_all(&func.parameters);
+        visit::walk_function(self, func);
+    }
+
+    fn visit_lambda(&mut self, lambda: &ast::Lambda) {
+        self.intern_all(&lambda.parameters);
+        visit::walk_lambda(self, lambda);
+    }
+
+    fn visit_statement(&mut self, stmnt: &ast::Statement) {
+        if let ast::Statement::Declaration(ref name, ref extra, _, _, _) = *stmnt {
+            self.intern(name);
+            self.intern_all(extra.iter().map(|&(ref name, _)| name));
+        }
+        visit::walk_statement(self, stmnt);
+    }
+
+    fn visit_for_loop(&mut self, l: &ast::ForLoop) {
+        self.intern_all(&l.bindings);
+        visit::walk_for_loop(self, l);
+    }
+
+    fn visit_expression(&mut self, expr: &ast::Expression) {
+        match *expr {
+            ast::Expression::Identifier(ref name, _) |
+            ast::Expression::MemberAccess(_, ref name) |
+            ast::Expression::ObjectConstructor(ref name, _, _) => {
+                self.intern(name);
+            }
+            _ => (),
+        }
+        visit::walk_expression(self, expr);
+    }
+
+    fn visit_literal(&mut self, lit: &ast::Literal) {
+        if let ast::Literal::Symbol(ref name) = *lit {
+            self.intern(name);
+        }
+        visit::walk_literal(self, lit);
+    }
+
+    fn visit_object_literal(&mut self, obj: &ast::ObjectLiteral) {
+        self.intern_all(obj.iter().map(|field| &field.key));
+        visit::walk_object_literal(self, obj);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::parse_Module;
+
+    #[test]
+    fn names_are_interned() {
+        let source = "
+            type Point { x, y }
+            fn norm(p) {
+                let len = p.x * p.x;
+                return [len, :done, { z: 1 }];
+            }
+        ";
+        let runtime = Runtime::new(parse_Module(Lexer::new(source)).unwrap()).unwrap();
+        for name in &["Point", "x", "y", "norm", "p", "len", "done", "z"] {
+            assert_eq!(runtime.symbols.get(*name), Some(&Symbol::intern(name)), "{}", name);
+        }
+        assert_eq!(runtime.symbols.len(), 8);
+    }
+
+    #[test]
+    fn objects_are_checked_against_their_layout() {
+        let source = "type Person { name, can_fly = false, legs = 1 + 1 }\ntype Loose;";
+        let runtime = Runtime::new(parse_Module(Lexer::new(source)).unwrap()).unwrap();
+        let sym = Symbol::intern;
+
+        let given = vec![(sym("name"), Value::Integer(7)), (sym("legs"), Value::Integer(3))];
+        let person = match runtime.new_object(sym("Person"), given) {
+            Ok(Value::Object(obj)) => obj,
+            other => panic!("{:?}", other),
+        };
+        let fields: Vec<String> = person.borrow()
+            .fields
+            .iter()
+            .map(|(name, value)| format!("{}: {:?}", name, value))
+            .collect();
+        assert_eq!(fields, vec!["name: 7", "can_fly: false", "legs: 3"]);
+
+        let defaults = match runtime.new_object(sym("Person"), vec![(sym("name"), Value::Nil)]) {
+            Ok(Value::Object(obj)) => obj,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(format!("{:?}", defaults.borrow().fields.get(sym("legs"))), "2");
+
+        assert_eq!(runtime.new_object(sym("Person"), vec![]).unwrap_err(),
+                   ConstructError::Field(sym("Person"), FieldError::Missing(sym("name"))));
+        let typo = vec![(sym("name"), Value::Nil), (sym("cna_fly"), Value::Bool(true))];
+        assert_eq!(runtime.new_object(sym("Person"), typo).unwrap_err(),
+                   ConstructError::Field(sym("Person"), FieldError::Unknown(sym("cna_fly"))));
+        assert_eq!(runtime.new_object(sym("Nobody"), vec![]).unwrap_err(),
+                   ConstructError::UnknownType(sym("Nobody")));
+        assert!(runtime.new_object(sym("Loose"), vec![(sym("anything"), Value::Nil)]).is_ok());
+    }
+}
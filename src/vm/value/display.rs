@@ -0,0 +1,463 @@
+//! Formatting values for printing.
+//!
+//! Values are written the way a script would write them: `[1, 2]`,
+//! `<1, 2, 3, 4:i32>`, `:name`. Objects are an object literal after their
+//! type's name, like `Point { x: 1, y: 2 }`. A table is one too, where
+//! symbol keys are written as names and any other key in brackets, like
+//! `{ name: "x", [1]: true }`. Functions print as `<fn name/arity>`.
+//!
+//! Arrays, tables and objects can hold themselves, so one that's already
+//! being printed further out prints as `<cycle>`. Debug formatting also
+//! stops at a depth limit, quotes strings even on their own, and ignores
+//! `to_string` overrides, so that what's printed is the value itself.
+
+use ast::printer::{float_str, with_point};
+use std::fmt;
+use vm::runtime as rt;
+use vm::value::{Object, Table, Value, ValueKey};
+use vm::value::shared::Shared;
+use vm::value::simd::SimdValue;
+
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+
+pub struct Formatter<'a> {
+    debug: bool,
+    max_depth: Option<usize>,
+    to_string: Option<&'a mut FnMut(&Value) -> Option<String>>,
+    /// The addresses of the arrays, tables and objects being printed
+    open: Vec<usize>,
+    out: String,
+}
+
+impl<'a> Formatter<'a> {
+    /// Formatting for showing a value to the user. A string on its own is
+    /// printed as it is.
+    pub fn display() -> Formatter<'a> {
+        Formatter {
+            debug: false,
+            max_depth: None,
+            to_string: None,
+            open: vec![],
+            out: String::new(),
+        }
+    }
+
+    /// Formatting for debugging, which stops `DEFAULT_MAX_DEPTH` levels in.
+    pub fn debug() -> Formatter<'a> {
+        Formatter {
+            debug: true,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            ..Formatter::display()
+        }
+    }
+
+    /// How many levels of arrays, tables and objects to print before
+    /// writing `...` for their contents. `None` means no limit.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Lets objects choose how they're displayed. `to_string` is asked
+    /// about each object first, and returns what its `display` lang trait
+    /// method gave, or `None` to format it as usual. `LangItems::unary` finds
+    /// that method, but nothing can call it yet, so `Display for Value`
+    /// doesn't pass one.
+    pub fn to_string(mut self, to_string: &'a mut FnMut(&Value) -> Option<String>) -> Self {
+        self.to_string = Some(to_string);
+        self
+    }
+
+    pub fn format(mut self, value: &Value) -> String {
+        match *value {
+            Value::String(ref s) if !self.debug => return (**s).clone(),
+            _ => self.value(value, 0),
+        }
+        self.out
+    }
+
+    fn push(&mut self, s: &str) {
+        self.out.push_str(s);
+    }
+
+    fn value(&mut self, value: &Value, depth: usize) {
+        match *value {
+            Value::Nil => self.push("nil"),
+            Value::Bool(b) => self.push(if b { "true" } else { "false" }),
+            Value::Integer(i) => self.push(&i.to_string()),
+            Value::BigInt(ref b) => self.push(&b.to_string()),
+            Value::Float(f) => self.push(&float_str(f)),
+            Value::String(ref s) => self.string(s),
+            Value::Symbol(sym) => {
+                self.push(":");
+                self.push(sym.as_str());
+            }
+            Value::Object(ref obj) => {
+                if !self.debug {
+                    let shown = self.to_string.as_mut().and_then(|to_string| (*to_string)(value));
+                    if let Some(shown) = shown {
+                        return self.push(&shown);
+                    }
+                }
+                self.object(obj, depth);
+            }
+            Value::Table(ref table) => self.table(table, depth),
+            Value::Array(ref array) => self.array(array, depth),
+            Value::Weak(ref weak) => {
+                self.push("<weak ");
+                self.push(weak.upgrade().type_name());
+                self.push(">");
+            }
+            Value::Simd(ref simd) => self.simd(simd),
+            Value::PlainFunction(ref func) => {
+                self.function(&func.name, Some(func.parameters.len()))
+            }
+            Value::ExternFunction(ref func) => {
+                match func.try_borrow() {
+                    Some(func) => {
+                        let rt::Function { ref name, arity, .. } = *func;
+                        self.function(name, arity)
+                    }
+                    None => self.push("<fn>"),
+                }
+            }
+        }
+    }
+
+    /// Quoted and escaped, as in a string literal.
+    fn string(&mut self, s: &str) {
+        self.out.push('"');
+        for c in s.chars() {
+            match c {
+                '"' => self.push("\\\""),
+                '\\' => self.push("\\\\"),
+                '\n' => self.push("\\n"),
+                '\r' => self.push("\\r"),
+                '\t' => self.push("\\t"),
+                '\0' => self.push("\\0"),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    fn function(&mut self, name: &str, arity: Option<usize>) {
+        self.push("<fn ");
+        self.push(if name.is_empty() { "anonymous" } else { name });
+        if let Some(arity) = arity {
+            self.push(&format!("/{}", arity));
+        }
+        self.push(">");
+    }
+
+    /// Writes the contents of an array, table or object with `body`, or
+    /// `<cycle>` if it's already being printed, or `elided` if it's too
+    /// deep.
+    fn nested<F>(&mut self, addr: usize, depth: usize, elided: &str, body: F)
+        where F: FnOnce(&mut Self)
+    {
+        if self.open.contains(&addr) {
+            return self.push("<cycle>");
+        }
+        if self.max_depth.map_or(false, |max| depth >= max) {
+            return self.push(elided);
+        }
+
+        self.open.push(addr);
+        body(self);
+        self.open.pop();
+    }
+
+    fn array(&mut self, array: &Shared<Vec<Value>>, depth: usize) {
+        // Copied out so that nothing is borrowed while `to_string` runs
+        let items = match array.try_borrow() {
+            Some(items) => items.clone(),
+            None => return self.push("<borrowed>"),
+        };
+
+        self.nested(array.addr(), depth, "[...]", |f| {
+            f.push("[");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    f.push(", ");
+                }
+                f.value(item, depth + 1);
+            }
+            f.push("]");
+        });
+    }
+
+    fn table(&mut self, table: &Shared<Table>, depth: usize) {
+        let mut entries = match table.try_borrow() {
+            Some(table) => table.entries(),
+            None => return self.push("<borrowed>"),
+        };
+        entries.sort_by(|l, r| l.0.cmp(&r.0));
+
+        self.nested(table.addr(), depth, "{ ... }", |f| {
+            f.fields(entries.iter().map(|&(ref key, ref value)| (key, value)), depth)
+        });
+    }
+
+    fn object(&mut self, obj: &Shared<Object>, depth: usize) {
+        let (name, fields) = match obj.try_borrow() {
+            Some(obj) => {
+                let name = obj.metatype.as_ref().map(|meta| meta.name.as_str());
+                let fields: Vec<(ValueKey, Value)> = obj.fields
+                    .iter()
+                    .map(|(name, value)| (ValueKey::Symbol(name), value.clone()))
+                    .collect();
+                (name, fields)
+            }
+            None => return self.push("<borrowed>"),
+        };
+
+        if let Some(name) = name {
+            self.push(name);
+            self.push(" ");
+        }
+        self.nested(obj.addr(), depth, "{ ... }", |f| {
+            f.fields(fields.iter().map(|&(ref key, ref value)| (key, value)), depth)
+        });
+    }
+
+    fn fields<'v, I>(&mut self, fields: I, depth: usize)
+        where I: Iterator<Item = (&'v ValueKey, &'v Value)>
+    {
+        let mut empty = true;
+        for (i, (key, value)) in fields.enumerate() {
+            self.push(if i == 0 { "{ " } else { ", " });
+            empty = false;
+
+            match *key {
+                ValueKey::Symbol(name) => self.push(name.as_str()),
+                ref key => {
+                    self.push("[");
+                    self.value(&key.value(), depth + 1);
+                    self.push("]");
+                }
+            }
+            self.push(": ");
+            self.value(value, depth + 1);
+        }
+        self.push(if empty { "{}" } else { " }" });
+    }
+
+    fn simd(&mut self, simd: &SimdValue) {
+        let (lanes, ty): (Vec<String>, Option<&str>) = match *simd {
+            SimdValue::F32x4(v) => (lanes(|i| f32_str(v.extract(i))), Some("f32")),
+            SimdValue::I32x4(v) => (lanes(|i| v.extract(i).to_string()), Some("i32")),
+            SimdValue::U32x4(v) => (lanes(|i| v.extract(i).to_string()), Some("u32")),
+            SimdValue::Bool32fx4(v) => (lanes(|i| v.extract(i).to_string()), None),
+            SimdValue::Bool32ix4(v) => (lanes(|i| v.extract(i).to_string()), None),
+        };
+
+        self.push("<");
+        self.push(&lanes.join(", "));
+        if let Some(ty) = ty {
+            self.push(":");
+            self.push(ty);
+        }
+        self.push(">");
+    }
+}
+
+fn lanes<F>(lane: F) -> Vec<String>
+    where F: Fn(u32) -> String
+{
+    (0..4).map(lane).collect()
+}
+
+fn f32_str(f: f32) -> String {
+    if f.is_finite() {
+        with_point(f.to_string())
+    } else {
+        float_str(f as f64)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&Formatter::display().format(self))
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&Formatter::debug().format(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast;
+    use lexer::Lexer;
+    use parser::parse_Module;
+    use simd;
+    use std::rc::Rc;
+    use vm::runtime::Runtime;
+    use vm::value::Symbol;
+    use vm::value::object::ObjectFields;
+
+    fn runtime() -> Runtime {
+        let source = "type Point { x, y }\nfn add(a, b) { return a + b; }\nfn log(msg) {}";
+        Runtime::new(parse_Module(Lexer::new(source)).unwrap()).unwrap()
+    }
+
+    fn function(runtime: &Runtime, name: &str) -> Value {
+        let items = match runtime.root_module {
+            ast::Module::Root { ref items } => items,
+            _ => unreachable!(),
+        };
+        items.iter()
+            .filter_map(|item| match *item {
+                ast::Item::Function(_, ref func) if func.name == name => {
+                    Some(Value::PlainFunction(Rc::new(func.clone())))
+                }
+                _ => None,
+            })
+            .next()
+            .unwrap()
+    }
+
+    fn point(runtime: &Runtime, x: i64, y: i64) -> Value {
+        let given = vec![(Symbol::intern("x"), Value::Integer(x)),
+                         (Symbol::intern("y"), Value::Integer(y))];
+        runtime.new_object(Symbol::intern("Point"), given).unwrap()
+    }
+
+    fn array(items: Vec<Value>) -> Value {
+        Value::Array(Shared::new(items))
+    }
+
+    #[test]
+    fn scalars() {
+        let s = Value::String(Rc::new("say \"hi\"\n".into()));
+        assert_eq!(s.to_string(), "say \"hi\"\n");
+        assert_eq!(format!("{:?}", s), r#""say \"hi\"\n""#);
+        let list = array(vec![Value::Nil,
+                              Value::Bool(true),
+                              Value::Integer(-7),
+                              Value::Float(2.0),
+                              Value::Float(0.5),
+                              Value::Symbol(Symbol::intern("name")),
+                              s]);
+        assert_eq!(list.to_string(), r#"[nil, true, -7, 2.0, 0.5, :name, "say \"hi\"\n"]"#);
+    }
+
+    #[test]
+    fn simd_lanes_have_their_type() {
+        let simd = |v| Value::Simd(Box::new(v));
+        let f = simd(SimdValue::F32x4(simd::f32x4::new(1.5, 2.0, -3.0, 4.0)));
+        let i = simd(SimdValue::I32x4(simd::i32x4::new(1, -2, 3, 4)));
+        let u = simd(SimdValue::U32x4(simd::u32x4::new(1, 2, 3, 4)));
+        let b = simd(SimdValue::Bool32fx4(simd::bool32fx4::new(true, false, true, true)));
+        assert_eq!(f.to_string(), "<1.5, 2.0, -3.0, 4.0:f32>");
+        assert_eq!(i.to_string(), "<1, -2, 3, 4:i32>");
+        assert_eq!(u.to_string(), "<1, 2, 3, 4:u32>");
+        assert_eq!(b.to_string(), "<true, false, true, true>");
+    }
+
+    #[test]
+    fn functions_have_their_arity() {
+        let runtime = runtime();
+        assert_eq!(function(&runtime, "add").to_string(), "<fn add/2>");
+        assert_eq!(function(&runtime, "log").to_string(), "<fn log/1>");
+
+        let print = |name: &str, arity| {
+            Value::ExternFunction(Shared::new(rt::Function {
+                name: name.into(),
+                arity: arity,
+                call: Rc::new(|_| Ok(Value::Nil)),
+            }))
+        };
+        assert_eq!(print("print", None).to_string(), "<fn print>");
+        assert_eq!(print("", Some(0)).to_string(), "<fn anonymous/0>");
+    }
+
+    #[test]
+    fn objects_and_tables_are_literals() {
+        let runtime = runtime();
+        assert_eq!(point(&runtime, 1, 2).to_string(), "Point { x: 1, y: 2 }");
+
+        let mut fields = ObjectFields::new();
+        fields.set(Symbol::intern("a"), Value::Integer(1));
+        let untyped = Value::Object(Shared::new(Object {
+            metatype: None,
+            fields: fields,
+        }));
+        assert_eq!(untyped.to_string(), "{ a: 1 }");
+
+        let table = Shared::new(Table::new());
+        assert_eq!(Value::Table(table.clone()).to_string(), "{}");
+        table.borrow_mut().insert(ValueKey::Symbol(Symbol::intern("name")), Value::Integer(3));
+        table.borrow_mut().insert(ValueKey::Integer(1), Value::Bool(true));
+        table.borrow_mut().insert(ValueKey::String(Rc::new("k".into())), point(&runtime, 0, 0));
+        assert_eq!(Value::Table(table).to_string(),
+                   r#"{ [1]: true, ["k"]: Point { x: 0, y: 0 }, name: 3 }"#);
+
+        let target = array(vec![]);
+        let weak = target.downgrade().unwrap();
+        assert_eq!(weak.to_string(), "<weak array>");
+    }
+
+    #[test]
+    fn values_holding_themselves_are_cycles() {
+        let list = Shared::new(vec![]);
+        list.borrow_mut().push(Value::Array(list.clone()));
+        assert_eq!(format!("{:?}", Value::Array(list.clone())), "[<cycle>]");
+
+        let table = Shared::new(Table::new());
+        let key = ValueKey::Symbol(Symbol::intern("me"));
+        table.borrow_mut().insert(key, Value::Table(table.clone()));
+        assert_eq!(Value::Table(table.clone()).to_string(), "{ me: <cycle> }");
+
+        // The same value twice, but not inside itself, isn't a cycle
+        let inner = array(vec![]);
+        assert_eq!(array(vec![inner.clone(), inner]).to_string(), "[[], []]");
+
+        list.borrow_mut().clear();
+        table.borrow_mut().clear();
+    }
+
+    #[test]
+    fn debug_stops_at_the_depth_limit() {
+        let nested = (0..10).fold(Value::Integer(1), |inner, _| array(vec![inner]));
+        let all = "[[[[[[[[[[1]]]]]]]]]]";
+        let limited = "[[[[[[[[[...]]]]]]]]]";
+        assert_eq!(format!("{:?}", nested), limited);
+        assert_eq!(Formatter::debug().max_depth(None).format(&nested), all);
+        assert_eq!(Formatter::debug().max_depth(Some(1)).format(&nested), "[[...]]");
+        assert_eq!(nested.to_string(), all);
+
+        let runtime = runtime();
+        let objects = array(vec![point(&runtime, 1, 2)]);
+        assert_eq!(Formatter::debug().max_depth(Some(1)).format(&objects), "[Point { ... }]");
+    }
+
+    #[test]
+    fn to_string_overrides_objects_when_displayed() {
+        let runtime = runtime();
+        let value = array(vec![point(&runtime, 1, 2), Value::Integer(3)]);
+        let mut asked = 0;
+        {
+            let mut to_string = |value: &Value| {
+                asked += 1;
+                match *value {
+                    Value::Object(_) => Some("(1, 2)".into()),
+                    _ => None,
+                }
+            };
+            assert_eq!(Formatter::display().to_string(&mut to_string).format(&value),
+                       "[(1, 2), 3]");
+            assert_eq!(Formatter::debug().to_string(&mut to_string).format(&value),
+                       "[Point { x: 1, y: 2 }, 3]");
+        }
+        assert_eq!(asked, 1);
+
+        let mut unchanged = |_: &Value| None;
+        assert_eq!(Formatter::display().to_string(&mut unchanged).format(&value),
+                   "[Point { x: 1, y: 2 }, 3]");
+    }
+}
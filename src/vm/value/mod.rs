@@ -1,35 +1,48 @@
 use ast;
-use std::collections::HashMap;
+use num::BigInt;
 use std::rc::Rc;
 use vm::runtime as rt;
 pub use vm::value::object::Object;
-use vm::value::shared::{Shared, SharedRef};
+use vm::value::shared::{Shared, SharedRef, WeakRef, WeakShared};
 use vm::value::simd::SimdValue;
 pub use vm::value::sym::Symbol;
+pub use vm::value::table::{Table, TableMode};
 
 pub mod sym;
+pub mod display;
 pub mod object;
 pub mod simd;
 pub mod shared;
+pub mod shape;
+pub mod table;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ValueKey {
+    Bool(bool),
     Integer(i64),
+    BigInt(Rc<BigInt>),
     String(Rc<String>),
     Symbol(Symbol),
     Shared(SharedRef),
+    /// A key in a table with weak keys
+    Weak(WeakRef),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub enum Value {
     Nil,
+    Bool(bool),
     Integer(i64),
+    /// An integer that doesn't fit in an `i64`. Arithmetic demotes results
+    /// that do, so an integer has only one representation.
+    BigInt(Rc<BigInt>),
     Float(f64),
     String(Rc<String>),
     Symbol(Symbol),
     Object(Shared<Object>),
-    Table(Shared<HashMap<ValueKey, Value>>),
+    Table(Shared<Table>),
     Array(Shared<Vec<Value>>),
+    Weak(WeakValue),
     Simd(Box<SimdValue>),
     PlainFunction(Rc<ast::Function>),
     ExternFunction(Shared<rt::Function>), 
@@ -37,11 +50,49 @@ pub enum Value {
     // TODO: Closures
 }
 
+impl Value {
+    /// The name of the value's type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "bool",
+            Value::Integer(_) |
+            Value::BigInt(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Symbol(_) => "symbol",
+            Value::Object(_) => "object",
+            Value::Table(_) => "table",
+            Value::Array(_) => "array",
+            Value::Weak(_) => "weak",
+            Value::Simd(_) => "simd",
+            Value::PlainFunction(_) |
+            Value::ExternFunction(_) => "function",
+        }
+    }
+
+    /// A weak reference to the value, or `None` if it isn't an object, table
+    /// or array.
+    pub fn downgrade(&self) -> Option<Value> {
+        WeakValue::new(self).map(Value::Weak)
+    }
+
+    /// Only `nil` and `false` are falsy.
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Value::Nil | Value::Bool(false) => false,
+            _ => true,
+        }
+    }
+}
+
 impl ValueKey {
     pub fn create(value: &Value) -> Result<ValueKey, &'static str> {
         use self::Value::*;
         match *value {
+            Bool(b) => Ok(ValueKey::Bool(b)),
             Integer(i) => Ok(ValueKey::Integer(i)),
+            BigInt(ref b) => Ok(ValueKey::BigInt(b.clone())),
             String(ref s) => Ok(ValueKey::String(s.clone())),
             Symbol(s) => Ok(ValueKey::Symbol(s)),
 
@@ -51,8 +102,101 @@ impl ValueKey {
 
             Nil => Err("nil cannot be used as a table key"),
             Float(_) => Err("floats cannot be used as a table key"),
+            Weak(_) => Err("weak references cannot be used as a table key"),
             Simd(_) => Err("SIMD values cannot be used as a table key"),
-            PlainFunction(_) => Err("Functions may not be used as table keys"),
+            PlainFunction(_) | ExternFunction(_) => Err("Functions may not be used as table keys"),
         }
     }
+
+    /// The value the key was made from, or nil if it was weak and its
+    /// value has been freed.
+    pub fn value(&self) -> Value {
+        match *self {
+            ValueKey::Bool(b) => Value::Bool(b),
+            ValueKey::Integer(i) => Value::Integer(i),
+            ValueKey::BigInt(ref b) => Value::BigInt(b.clone()),
+            ValueKey::String(ref s) => Value::String(s.clone()),
+            ValueKey::Symbol(s) => Value::Symbol(s),
+            ValueKey::Shared(ref shared) => shared_value(shared),
+            ValueKey::Weak(ref weak) => {
+                weak.upgrade().map(|shared| shared_value(&shared)).unwrap_or(Value::Nil)
+            }
+        }
+    }
+}
+
+fn shared_value(shared: &SharedRef) -> Value {
+    if let Some(obj) = shared.downcast() {
+        Value::Object(obj)
+    } else if let Some(table) = shared.downcast() {
+        Value::Table(table)
+    } else if let Some(array) = shared.downcast() {
+        Value::Array(array)
+    } else {
+        Value::Nil
+    }
+}
+
+/// A reference that doesn't keep its object, table or array alive.
+#[derive(Clone, Debug)]
+pub enum WeakValue {
+    Object(WeakShared<Object>),
+    Table(WeakShared<Table>),
+    Array(WeakShared<Vec<Value>>),
+}
+
+impl WeakValue {
+    pub fn new(value: &Value) -> Option<WeakValue> {
+        match *value {
+            Value::Object(ref obj) => Some(WeakValue::Object(obj.downgrade())),
+            Value::Table(ref table) => Some(WeakValue::Table(table.downgrade())),
+            Value::Array(ref array) => Some(WeakValue::Array(array.downgrade())),
+            _ => None,
+        }
+    }
+
+    /// The target, or nil once it's been freed.
+    pub fn upgrade(&self) -> Value {
+        let value = match *self {
+            WeakValue::Object(ref obj) => obj.upgrade().map(Value::Object),
+            WeakValue::Table(ref table) => table.upgrade().map(Value::Table),
+            WeakValue::Array(ref array) => array.upgrade().map(Value::Array),
+        };
+        value.unwrap_or(Value::Nil)
+    }
+
+    /// The target's address, as in `Shared::addr`.
+    pub fn addr(&self) -> usize {
+        match *self {
+            WeakValue::Object(ref obj) => obj.addr(),
+            WeakValue::Table(ref table) => table.addr(),
+            WeakValue::Array(ref array) => array.addr(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vm::gc;
+
+    #[test]
+    fn weak_values_upgrade_to_nil_once_collected() {
+        let cycle = Shared::new(vec![]);
+        cycle.borrow_mut().push(Value::Array(cycle.clone()));
+        let weak = Value::Array(cycle.clone()).downgrade().unwrap();
+        assert_eq!(Value::Integer(1).downgrade().map(|_| ()), None);
+
+        let target = match weak {
+            Value::Weak(ref weak) => weak,
+            _ => unreachable!(),
+        };
+        assert_eq!(target.addr(), cycle.addr());
+        assert_eq!(format!("{:?}", target.upgrade()), "[<cycle>]");
+
+        drop(cycle);
+        assert_eq!(format!("{:?}", target.upgrade()), "[<cycle>]");
+        gc::collect();
+        assert_eq!(format!("{:?}", target.upgrade()), "nil");
+    }
 }
@@ -1,7 +1,11 @@
 use ast;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{HashMap, HashSet};
+use std::iter::Zip;
 use std::rc::Rc;
+use std::slice;
+use std::vec;
 use vm::value::Value;
+use vm::value::shape::{InlineCache, Shape};
 use vm::value::sym::Symbol;
 
 #[derive(Clone, Debug)]
@@ -10,16 +14,208 @@ pub struct Object {
     pub fields: ObjectFields,
 }
 
+impl Object {
+    /// Makes an object for `new_object`, validating the given fields and
+    /// filling in missing ones from their defaults with `eval`.
+    pub fn construct<F>(metatype: Rc<MetaType>,
+                        given: Vec<(Symbol, Value)>,
+                        mut eval: F)
+                        -> Result<Object, FieldError>
+        where F: FnMut(&ast::Expression) -> Value
+    {
+        let names: Vec<Symbol> = given.iter().map(|&(name, _)| name).collect();
+        metatype.validate_fields(&names)?;
+
+        let mut fields = match metatype.layout {
+            Some(ref layout) => ObjectFields::with_shape(layout.shape.clone()),
+            None => ObjectFields::new(),
+        };
+        if let Some(ref layout) = metatype.layout {
+            for field in &layout.fields {
+                if let Some(ref default) = field.default {
+                    if !names.contains(&field.name) {
+                        fields.set(field.name, eval(default));
+                    }
+                }
+            }
+        }
+        for (name, value) in given {
+            fields.set(name, value);
+        }
+
+        Ok(Object {
+            metatype: Some(metatype),
+            fields: fields,
+        })
+    }
+
+    /// `self.name`, at the member access site `cache` belongs to. A field
+    /// the object doesn't have is nil.
+    pub fn get_field(&self, name: Symbol, cache: &mut InlineCache) -> Value {
+        cache.get(&self.fields, name)
+    }
+
+    /// `self.name = value`, at the member access site `cache` belongs to. An
+    /// object whose type declares its fields can't be given any others.
+    pub fn set_field(&mut self,
+                     name: Symbol,
+                     value: Value,
+                     cache: &mut InlineCache)
+                     -> Result<(), FieldError> {
+        if let Some(slot) = cache.slot(self.fields.shape(), name) {
+            *self.fields.slot_mut(slot) = value;
+            return Ok(());
+        }
+        // Such objects start out with every declared field
+        if self.metatype.as_ref().map_or(false, |meta| meta.layout.is_some()) {
+            return Err(FieldError::Unknown(name));
+        }
+        cache.set(&mut self.fields, name, value);
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct MetaType {
     pub name: Symbol,
+    /// `None` if the `type` was declared without a field list
+    pub layout: Option<Layout>,
     pub inherent_type: TypeImpl,
-    pub trait_impls: HashMap<Symbol, TypeImpl>,
+    /// In the order the `impl`s were declared, which is the order methods
+    /// are looked up in
+    pub trait_impls: Vec<TypeImpl>,
+}
+
+impl MetaType {
+    /// Whether objects of this type implement `tr`, directly or through a
+    /// subtrait.
+    pub fn implements(&self, tr: Symbol, traits: &HashMap<Symbol, TraitDef>) -> bool {
+        self.implemented().any(|imp| {
+            imp == tr || traits.get(&imp).map(|def| def.extends(tr, traits)).unwrap_or(false)
+        })
+    }
+
+    /// The traits this type has an `impl` of, in declaration order.
+    pub fn implemented<'a>(&'a self) -> Box<Iterator<Item = Symbol> + 'a> {
+        Box::new(self.trait_impls.iter().filter_map(|imp| imp.interface))
+    }
+
+    pub fn trait_impl(&self, tr: Symbol) -> Option<&TypeImpl> {
+        self.trait_impls.iter().find(|imp| imp.interface == Some(tr))
+    }
+
+    /// Finds a method in the inherent impl, then the trait impls, then the
+    /// default bodies of the implemented traits and their supertraits. When
+    /// several traits have the method, the first one implemented wins.
+    pub fn find_method(&self,
+                       name: Symbol,
+                       is_member: bool,
+                       traits: &HashMap<Symbol, TraitDef>)
+                       -> Option<Rc<ast::Function>> {
+        if let Some(func) = self.inherent_type.methods(is_member).get(&name) {
+            return Some(func.clone());
+        }
+        for imp in &self.trait_impls {
+            if let Some(func) = imp.methods(is_member).get(&name) {
+                return Some(func.clone());
+            }
+        }
+        self.implemented()
+            .filter_map(|tr| traits.get(&tr))
+            .filter_map(|def| def.find_default(name, is_member, traits))
+            .next()
+    }
+
+    /// Checks that every supertrait of the traits this type implements is
+    /// implemented too, returning the first trait and supertrait that
+    /// aren't.
+    pub fn check_supertraits(&self,
+                             traits: &HashMap<Symbol, TraitDef>)
+                             -> Result<(), (Symbol, Symbol)> {
+        for tr in self.implemented() {
+            if let Some(def) = traits.get(&tr) {
+                for &sup in &def.supertraits {
+                    if self.trait_impl(sup).is_none() {
+                        return Err((tr, sup));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the fields given to `new_object` against the declared layout.
+    /// Every field is allowed on a type without one.
+    pub fn validate_fields(&self, given: &[Symbol]) -> Result<(), FieldError> {
+        match self.layout {
+            Some(ref layout) => layout.validate(given),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The fields of a `type`, in declaration order.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    pub fields: Vec<FieldLayout>,
+    /// The shape objects of the type start with
+    pub shape: Rc<Shape>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldLayout {
+    pub name: Symbol,
+    /// Evaluated each time an object is made without this field
+    pub default: Option<Rc<ast::Expression>>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FieldError {
+    Unknown(Symbol),
+    Missing(Symbol),
+}
+
+impl Layout {
+    pub fn from_decl(decl: &ast::TypeDecl) -> Option<Layout> {
+        decl.fields.as_ref().map(|fields| {
+            let fields: Vec<FieldLayout> = fields.iter()
+                .map(|field| {
+                    FieldLayout {
+                        name: Symbol::intern(&field.name),
+                        default: field.default.clone().map(Rc::new),
+                    }
+                })
+                .collect();
+            Layout {
+                shape: Shape::from_fields(fields.iter().map(|field| field.name)),
+                fields: fields,
+            }
+        })
+    }
+
+    pub fn field(&self, name: Symbol) -> Option<&FieldLayout> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    pub fn validate(&self, given: &[Symbol]) -> Result<(), FieldError> {
+        for &name in given {
+            if self.field(name).is_none() {
+                return Err(FieldError::Unknown(name));
+            }
+        }
+        for field in &self.fields {
+            if field.default.is_none() && !given.contains(&field.name) {
+                return Err(FieldError::Missing(field.name));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct TraitDef {
     pub name: Symbol,
+    pub supertraits: Vec<Symbol>,
     pub constants: HashSet<Symbol>,
     pub static_methods: HashMap<Symbol, Rc<ast::TraitFunction>>,
     pub member_methods: HashMap<Symbol, Rc<ast::TraitFunction>>,
@@ -35,36 +231,301 @@ pub struct TypeImpl {
     pub member_methods: HashMap<Symbol, Rc<ast::Function>>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImplError {
+    MissingConst(Symbol),
+    MissingMethod(Symbol),
+}
+
+impl TraitDef {
+    /// Builds the definition of a trait, evaluating the default values of
+    /// its consts with `eval`.
+    pub fn from_ast<F>(tr: &ast::Trait, mut eval: F) -> TraitDef
+        where F: FnMut(&ast::Expression) -> Value
+    {
+        let name = Symbol::intern(&tr.name);
+        let mut def = TraitDef {
+            name: name,
+            supertraits: tr.supertraits.iter().map(|sup| Symbol::intern(sup)).collect(),
+            constants: HashSet::new(),
+            static_methods: HashMap::new(),
+            member_methods: HashMap::new(),
+            default_impl: None,
+        };
+        let mut defaults = TypeImpl {
+            name: name,
+            interface: Some(name),
+            constants: HashMap::new(),
+            static_methods: HashMap::new(),
+            member_methods: HashMap::new(),
+        };
+
+        for c in &tr.constants {
+            let sym = Symbol::intern(&c.name);
+            def.constants.insert(sym);
+            if let Some(ref value) = c.value {
+                defaults.constants.insert(sym, eval(value));
+            }
+        }
+
+        for func in &tr.methods {
+            let sym = Symbol::intern(&func.name);
+            if let Some(default) = default_method(func) {
+                defaults.methods_mut(func.is_member).insert(sym, Rc::new(default));
+            }
+
+            let methods = if func.is_member {
+                &mut def.member_methods
+            } else {
+                &mut def.static_methods
+            };
+            methods.insert(sym, Rc::new(func.clone()));
+        }
+
+        let has_defaults = !defaults.constants.is_empty() || !defaults.static_methods.is_empty() ||
+                           !defaults.member_methods.is_empty();
+        if has_defaults {
+            def.default_impl = Some(defaults);
+        }
+        def
+    }
+
+    /// This trait and everything it inherits from, nearest first. Traits
+    /// missing from `traits` are skipped.
+    pub fn ancestors<'a>(&'a self, traits: &'a HashMap<Symbol, TraitDef>) -> Vec<&'a TraitDef> {
+        let mut found = vec![self];
+        let mut i = 0;
+        while i < found.len() {
+            for sup in &found[i].supertraits {
+                if let Some(def) = traits.get(sup) {
+                    if !found.iter().any(|known| known.name == def.name) {
+                        found.push(def);
+                    }
+                }
+            }
+            i += 1;
+        }
+        found
+    }
+
+    /// Whether this trait is `other` or inherits from it.
+    pub fn extends(&self, other: Symbol, traits: &HashMap<Symbol, TraitDef>) -> bool {
+        self.ancestors(traits).iter().any(|def| def.name == other)
+    }
+
+    /// The default body for a method, from this trait or the nearest
+    /// supertrait that has one.
+    pub fn find_default(&self,
+                        name: Symbol,
+                        is_member: bool,
+                        traits: &HashMap<Symbol, TraitDef>)
+                        -> Option<Rc<ast::Function>> {
+        self.ancestors(traits)
+            .iter()
+            .filter_map(|def| def.default_impl.as_ref())
+            .filter_map(|imp| imp.methods(is_member).get(&name))
+            .next()
+            .cloned()
+    }
+
+    /// Fills in whatever `imp` leaves out with the trait's defaults. Fails
+    /// if something without a default is missing.
+    pub fn complete(&self, imp: &mut TypeImpl) -> Result<(), ImplError> {
+        let defaults = self.default_impl.as_ref();
+
+        for &name in &self.constants {
+            if !imp.constants.contains_key(&name) {
+                let value = defaults.and_then(|d| d.constants.get(&name))
+                    .ok_or(ImplError::MissingConst(name))?;
+                imp.constants.insert(name, value.clone());
+            }
+        }
+
+        for (&name, func) in self.static_methods.iter().chain(&self.member_methods) {
+            if !imp.methods_mut(func.is_member).contains_key(&name) {
+                let default = defaults.and_then(|d| d.methods(func.is_member).get(&name))
+                    .ok_or(ImplError::MissingMethod(name))?;
+                imp.methods_mut(func.is_member).insert(name, default.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl TypeImpl {
+    /// Builds an `impl` block, evaluating its consts with `eval`.
+    pub fn from_ast<F>(imp: &ast::TypeImpl, mut eval: F) -> TypeImpl
+        where F: FnMut(&ast::Expression) -> Value
+    {
+        let mut result = TypeImpl {
+            name: Symbol::intern(&imp.name),
+            interface: imp.interface.as_ref().map(|name| Symbol::intern(name)),
+            constants: HashMap::new(),
+            static_methods: HashMap::new(),
+            member_methods: HashMap::new(),
+        };
+
+        for c in &imp.constants {
+            if let Some(ref value) = c.value {
+                result.constants.insert(Symbol::intern(&c.name), eval(value));
+            }
+        }
+        for func in &imp.methods {
+            let sym = Symbol::intern(&func.name);
+            result.methods_mut(func.is_member).insert(sym, Rc::new(func.clone()));
+        }
+        result
+    }
+
+    fn methods(&self, is_member: bool) -> &HashMap<Symbol, Rc<ast::Function>> {
+        if is_member {
+            &self.member_methods
+        } else {
+            &self.static_methods
+        }
+    }
+
+    fn methods_mut(&mut self, is_member: bool) -> &mut HashMap<Symbol, Rc<ast::Function>> {
+        if is_member {
+            &mut self.member_methods
+        } else {
+            &mut self.static_methods
+        }
+    }
+}
+
+/// The default body of a trait method as a function of its own.
+fn default_method(func: &ast::TraitFunction) -> Option<ast::Function> {
+    func.body.as_ref().map(|body| {
+        ast::Function {
+            name: func.name.clone(),
+            location: func.location,
+            parameters: func.parameters.clone(),
+            parameter_locations: func.parameter_locations.clone(),
+            parameter_types: func.parameter_types.clone(),
+            return_type: func.return_type.clone(),
+            can_error: func.can_error,
+            is_member: func.is_member,
+            body: body.clone(),
+        }
+    })
+}
+
+/// An object's fields, stored in the slots its shape gives them.
 #[derive(Clone, Debug)]
-pub enum ObjectFields {
-    Tree(BTreeMap<Symbol, Value>),
-    Hash(HashMap<Symbol, Value>),
+pub struct ObjectFields {
+    shape: Rc<Shape>,
+    slots: Vec<Value>,
 }
 
 impl ObjectFields {
-    pub fn new(hash: bool) -> Self {
-        if hash {
-            ObjectFields::Hash(HashMap::new())
-        } else {
-            ObjectFields::Tree(BTreeMap::new())
+    /// No fields, on the root shape.
+    pub fn new() -> Self {
+        ObjectFields::with_shape(Shape::root())
+    }
+
+    /// Every field of `shape`, set to nil.
+    pub fn with_shape(shape: Rc<Shape>) -> Self {
+        ObjectFields {
+            slots: vec![Value::Nil; shape.len()],
+            shape: shape,
         }
     }
 
+    pub fn shape(&self) -> &Rc<Shape> {
+        &self.shape
+    }
+
     pub fn get(&self, key: Symbol) -> Value {
-        match *self {
-            ObjectFields::Tree(ref tree) => tree.get(&key).cloned().unwrap_or(Value::Nil),
-            ObjectFields::Hash(ref hash) => hash.get(&key).cloned().unwrap_or(Value::Nil),
+        match self.shape.slot(key) {
+            Some(slot) => self.slots[slot].clone(),
+            None => Value::Nil,
         }
     }
 
+    /// Sets a field, moving to a new shape if the object didn't have it.
     pub fn set(&mut self, key: Symbol, value: Value) {
-        match *self {
-            ObjectFields::Tree(ref mut tree) => {
-                tree.insert(key, value);
-            }
-            ObjectFields::Hash(ref mut hash) => {
-                hash.insert(key, value);
+        match self.shape.slot(key) {
+            Some(slot) => self.slots[slot] = value,
+            None => {
+                self.shape = Shape::with_field(&self.shape, key);
+                self.slots.push(value);
             }
         }
     }
-}
\ No newline at end of file
+
+    /// The value in a slot of the shape.
+    pub fn slot(&self, slot: usize) -> &Value {
+        &self.slots[slot]
+    }
+
+    pub fn slot_mut(&mut self, slot: usize) -> &mut Value {
+        &mut self.slots[slot]
+    }
+
+    /// The fields and their values, in slot order.
+    pub fn iter(&self) -> Zip<vec::IntoIter<Symbol>, slice::Iter<Value>> {
+        self.shape.fields().to_vec().into_iter().zip(self.slots.iter())
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.slots
+    }
+
+    /// Removes every field, going back to the root shape.
+    pub fn clear(&mut self) {
+        self.shape = Shape::root();
+        self.slots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::parse_Module;
+
+    #[test]
+    fn declared_fields_are_the_only_ones_set() {
+        let sym = Symbol::intern;
+        let ast = parse_Module(Lexer::new("type Point { x, y = 0 }")).unwrap();
+        let decl = match ast {
+            ast::Module::Root { ref items } => {
+                match items[0] {
+                    ast::Item::TypeDecl(_, ref decl) => decl.clone(),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
+        };
+        let inherent = TypeImpl {
+            name: sym("Point"),
+            interface: None,
+            constants: HashMap::new(),
+            static_methods: HashMap::new(),
+            member_methods: HashMap::new(),
+        };
+        let meta = Rc::new(MetaType {
+            name: sym("Point"),
+            layout: Layout::from_decl(&decl),
+            inherent_type: inherent,
+            trait_impls: vec![],
+        });
+        let given = vec![(sym("x"), Value::Integer(1))];
+        let mut point = Object::construct(meta, given, |_| Value::Integer(0)).unwrap();
+
+        let mut cache = InlineCache::new();
+        assert!(point.set_field(sym("y"), Value::Integer(2), &mut cache).is_ok());
+        assert_eq!(format!("{:?}", point.get_field(sym("y"), &mut cache)), "2");
+        assert_eq!(point.set_field(sym("z"), Value::Nil, &mut InlineCache::new()),
+                   Err(FieldError::Unknown(sym("z"))));
+
+        let mut loose = Object {
+            metatype: None,
+            fields: ObjectFields::new(),
+        };
+        assert!(loose.set_field(sym("z"), Value::Integer(3), &mut cache).is_ok());
+        assert_eq!(format!("{:?}", loose.get_field(sym("z"), &mut cache)), "3");
+    }
+}
@@ -0,0 +1,283 @@
+//! Shapes, which say where an object keeps each of its fields.
+//!
+//! An object's fields are stored in a `Vec<Value>`, and its shape maps each
+//! field name to an index in it. Objects that were given the same fields in
+//! the same order share a shape. They all start from the empty root, or from
+//! their type's layout, and adding a field to a shape always leads to the
+//! same child. Objects of one type therefore usually share one shape, so a
+//! member access site can remember where it found a field with an
+//! `InlineCache`, and skip the lookup while it keeps seeing that shape.
+//!
+//! A shape and the child it was last extended with share one table of field
+//! names, so adding a field doesn't copy the ones before it. Only a second
+//! child of the same shape starts a new table.
+
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::{Rc, Weak};
+use vm::value::object::ObjectFields;
+use vm::value::{Symbol, Value};
+
+/// Field names in slot order. Every shape sharing it sees a prefix.
+struct FieldTable {
+    fields: Vec<Symbol>,
+    slots: HashMap<Symbol, usize>,
+}
+
+pub struct Shape {
+    /// Kept alive so the transition to this shape stays in use
+    parent: Option<Rc<Shape>>,
+    table: Rc<RefCell<FieldTable>>,
+    /// How many of the table's fields this shape has
+    len: usize,
+    /// The shapes made by adding one more field, while they're in use
+    transitions: RefCell<HashMap<Symbol, Weak<Shape>>>,
+}
+
+thread_local! {
+    static ROOT: Rc<Shape> = Rc::new(Shape {
+        parent: None,
+        table: Rc::new(RefCell::new(FieldTable {
+            fields: vec![],
+            slots: HashMap::new(),
+        })),
+        len: 0,
+        transitions: RefCell::new(HashMap::new()),
+    });
+}
+
+impl Shape {
+    /// The shape with no fields.
+    pub fn root() -> Rc<Shape> {
+        ROOT.with(|root| root.clone())
+    }
+
+    /// The shape reached from the root by adding `fields` in order.
+    pub fn from_fields<I>(fields: I) -> Rc<Shape>
+        where I: IntoIterator<Item = Symbol>
+    {
+        fields.into_iter().fold(Shape::root(), |shape, name| Shape::with_field(&shape, name))
+    }
+
+    /// `shape` with `name` added after its other fields. It's `shape` itself
+    /// if it already has the field.
+    pub fn with_field(shape: &Rc<Shape>, name: Symbol) -> Rc<Shape> {
+        if shape.slot(name).is_some() {
+            return shape.clone();
+        }
+        if let Some(child) = shape.transitions.borrow().get(&name).and_then(Weak::upgrade) {
+            return child;
+        }
+
+        let table = shape.extended_table(name);
+        let child = Rc::new(Shape {
+            parent: Some(shape.clone()),
+            table: table,
+            len: shape.len + 1,
+            transitions: RefCell::new(HashMap::new()),
+        });
+        let mut transitions = shape.transitions.borrow_mut();
+        transitions.retain(|_, child| child.upgrade().is_some());
+        transitions.insert(name, Rc::downgrade(&child));
+        child
+    }
+
+    /// A table with this shape's fields followed by `name`. It's this
+    /// shape's own table if nothing else was added after them yet, or
+    /// `name` was.
+    fn extended_table(&self, name: Symbol) -> Rc<RefCell<FieldTable>> {
+        let mut table = self.table.borrow_mut();
+        if table.fields.len() == self.len {
+            table.slots.insert(name, self.len);
+            table.fields.push(name);
+        } else if table.fields[self.len] != name {
+            let fields: Vec<Symbol> =
+                table.fields[..self.len].iter().cloned().chain(Some(name)).collect();
+            let slots = fields.iter().enumerate().map(|(slot, &name)| (name, slot)).collect();
+            return Rc::new(RefCell::new(FieldTable {
+                fields: fields,
+                slots: slots,
+            }));
+        }
+        self.table.clone()
+    }
+
+    /// The shape this one was made from, by adding its last field.
+    pub fn parent(&self) -> Option<&Rc<Shape>> {
+        self.parent.as_ref()
+    }
+
+    /// Where `name` is stored, if objects of this shape have it.
+    pub fn slot(&self, name: Symbol) -> Option<usize> {
+        match self.table.borrow().slots.get(&name) {
+            Some(&slot) if slot < self.len => Some(slot),
+            _ => None,
+        }
+    }
+
+    /// The field names, in slot order.
+    pub fn fields(&self) -> Ref<[Symbol]> {
+        let len = self.len;
+        Ref::map(self.table.borrow(), |table| &table.fields[..len])
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl fmt::Debug for Shape {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Shape({:?})", &*self.fields())
+    }
+}
+
+/// The slot a member access site last found its field in, and the shape it
+/// was found on. Each site accesses one field name, so the name isn't part
+/// of what's cached. `Object::get_field` and `Object::set_field` take the
+/// cache of the site they're called from.
+#[derive(Clone, Debug, Default)]
+pub struct InlineCache {
+    entry: Option<(Rc<Shape>, usize)>,
+}
+
+impl InlineCache {
+    pub fn new() -> Self {
+        InlineCache { entry: None }
+    }
+
+    /// The slot of `name` in `shape`, looking it up only if `shape` isn't
+    /// the one cached.
+    pub fn slot(&mut self, shape: &Rc<Shape>, name: Symbol) -> Option<usize> {
+        if let Some((ref cached, slot)) = self.entry {
+            if Rc::ptr_eq(cached, shape) {
+                return Some(slot);
+            }
+        }
+
+        let slot = shape.slot(name);
+        if let Some(slot) = slot {
+            self.entry = Some((shape.clone(), slot));
+        }
+        slot
+    }
+
+    /// `fields.get(name)`.
+    pub fn get(&mut self, fields: &ObjectFields, name: Symbol) -> Value {
+        match self.slot(fields.shape(), name) {
+            Some(slot) => fields.slot(slot).clone(),
+            None => Value::Nil,
+        }
+    }
+
+    /// `fields.set(name, value)`. Adding a field changes the object's shape,
+    /// and the cache follows it.
+    pub fn set(&mut self, fields: &mut ObjectFields, name: Symbol, value: Value) {
+        match self.slot(fields.shape(), name) {
+            Some(slot) => *fields.slot_mut(slot) = value,
+            None => {
+                fields.set(name, value);
+                self.slot(fields.shape(), name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sym(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+
+    fn fields(shape: &Shape) -> Vec<String> {
+        shape.fields().iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn transitions_are_shared() {
+        let xy = Shape::from_fields(vec![sym("x"), sym("y")]);
+        let x = Shape::with_field(&Shape::root(), sym("x"));
+        assert!(Rc::ptr_eq(&Shape::with_field(&x, sym("y")), &xy));
+        assert!(Rc::ptr_eq(xy.parent().unwrap(), &x));
+        assert!(Shape::root().parent().is_none());
+        assert!(Rc::ptr_eq(&Shape::with_field(&xy, sym("x")), &xy));
+        assert!(!Rc::ptr_eq(&Shape::from_fields(vec![sym("y"), sym("x")]), &xy));
+        assert_eq!((xy.slot(sym("x")), xy.slot(sym("y"))), (Some(0), Some(1)));
+        assert_eq!(x.slot(sym("y")), None);
+    }
+
+    #[test]
+    fn tables_are_shared_until_a_shape_branches() {
+        let xy = Shape::from_fields(vec![sym("x"), sym("y")]);
+        let xyz = Shape::with_field(&xy, sym("z"));
+        let xyw = Shape::with_field(&xy, sym("w"));
+        assert!(Rc::ptr_eq(&xy.table, &xyz.table));
+        assert!(!Rc::ptr_eq(&xy.table, &xyw.table));
+
+        assert_eq!(fields(&xy), vec!["x", "y"]);
+        assert_eq!(fields(&xyz), vec!["x", "y", "z"]);
+        assert_eq!(fields(&xyw), vec!["x", "y", "w"]);
+        assert_eq!((xyw.slot(sym("w")), xyw.slot(sym("z"))), (Some(2), None));
+        assert_eq!((xyz.slot(sym("z")), xyz.slot(sym("w"))), (Some(2), None));
+        assert_eq!(xy.slot(sym("z")), None);
+
+        // Remade after it was freed, `xyz` finds its field still in the table
+        drop(xyz);
+        let xyz = Shape::with_field(&xy, sym("z"));
+        assert!(Rc::ptr_eq(&xy.table, &xyz.table));
+    }
+
+    #[test]
+    fn dead_transitions_are_pruned() {
+        let a = Shape::from_fields(vec![sym("pruned_a")]);
+        for i in 0..10 {
+            Shape::with_field(&a, sym(&format!("pruned_{}", i)));
+        }
+        let kept = Shape::with_field(&a, sym("pruned_kept"));
+        assert_eq!(a.transitions.borrow().len(), 1);
+        assert!(Rc::ptr_eq(&Shape::with_field(&a, sym("pruned_kept")), &kept));
+    }
+
+    #[test]
+    fn caches_hit_while_the_shape_stays() {
+        let mut first = ObjectFields::new();
+        first.set(sym("x"), Value::Integer(1));
+        first.set(sym("y"), Value::Integer(2));
+        let mut second = first.clone();
+        *second.slot_mut(1) = Value::Integer(3);
+        let mut other = ObjectFields::new();
+        other.set(sym("y"), Value::Integer(4));
+
+        let mut cache = InlineCache::new();
+        let cached = |cache: &InlineCache| cache.entry.as_ref().map(|&(ref shape, slot)| {
+            (fields(shape), slot)
+        });
+        assert_eq!(format!("{:?}", cache.get(&first, sym("y"))), "2");
+        assert_eq!(cached(&cache), Some((vec!["x".into(), "y".into()], 1)));
+
+        // A hit doesn't look the name up, so it would answer for any name
+        assert_eq!(cache.slot(second.shape(), sym("anything")), Some(1));
+        assert_eq!(format!("{:?}", cache.get(&second, sym("y"))), "3");
+
+        // A miss looks it up in the new shape
+        assert_eq!(format!("{:?}", cache.get(&other, sym("y"))), "4");
+        assert_eq!(cached(&cache), Some((vec!["y".into()], 0)));
+        // Nothing is cached for a field the shape doesn't have
+        let mut missing = InlineCache::new();
+        assert_eq!(format!("{:?}", missing.get(&other, sym("x"))), "nil");
+        assert_eq!(cached(&missing), None);
+
+        // Setting a new field moves the object, and the cache, to the child
+        let mut setter = InlineCache::new();
+        setter.set(&mut other, sym("x"), Value::Integer(5));
+        assert_eq!(cached(&setter), Some((vec!["y".into(), "x".into()], 1)));
+        assert_eq!(format!("{:?}", other.get(sym("x"))), "5");
+    }
+}
@@ -1,15 +1,90 @@
 use std::any::Any;
-use std::cell::RefCell;
+use std::cell::{Ref, RefCell, RefMut};
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 use std::fmt::{self, Debug};
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
+use vm::gc::{self, Trace};
 
 #[derive(Clone, Debug)]
 pub struct Shared<T> {
     inner: Rc<RefCell<T>>,
 }
 
+impl<T: Trace + 'static> Shared<T> {
+    /// A new shared value, tracked by the cycle collector.
+    pub fn new(value: T) -> Self {
+        let inner = Rc::new(RefCell::new(value));
+        gc::track(&inner);
+        Shared { inner: inner }
+    }
+}
+
+impl<T> Shared<T> {
+    pub fn borrow(&self) -> Ref<T> {
+        self.inner.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<T> {
+        self.inner.borrow_mut()
+    }
+
+    /// The value, unless it's borrowed mutably.
+    pub fn try_borrow(&self) -> Option<Ref<T>> {
+        self.inner.try_borrow().ok()
+    }
+
+    /// Whether both refer to the same value.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.addr() == other.addr()
+    }
+
+    /// The address of the value, which identifies it.
+    pub fn addr(&self) -> usize {
+        &*self.inner as *const RefCell<T> as usize
+    }
+
+    pub fn downgrade(&self) -> WeakShared<T> {
+        WeakShared {
+            inner: Rc::downgrade(&self.inner),
+            addr: self.addr(),
+        }
+    }
+}
+
+/// A `Shared` that doesn't keep its value alive.
+pub struct WeakShared<T> {
+    inner: Weak<RefCell<T>>,
+    addr: usize,
+}
+
+impl<T> WeakShared<T> {
+    /// The value, unless it's been freed.
+    pub fn upgrade(&self) -> Option<Shared<T>> {
+        self.inner.upgrade().map(|inner| Shared { inner: inner })
+    }
+
+    /// The value's address, which stays reserved while this exists.
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+impl<T> Clone for WeakShared<T> {
+    fn clone(&self) -> Self {
+        WeakShared {
+            inner: self.inner.clone(),
+            addr: self.addr,
+        }
+    }
+}
+
+impl<T> Debug for WeakShared<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "WeakShared({:#x})", self.addr)
+    }
+}
+
 #[derive(Clone)]
 pub struct SharedRef {
     inner: Rc<Any>,
@@ -21,6 +96,76 @@ impl SharedRef {
         let pair: (*const u8, *const u8) = unsafe { ::std::mem::transmute(ptr) };
         pair.0
     }
+
+    /// The same address as `Shared::addr` for the value.
+    pub fn addr(&self) -> usize {
+        self.ptr() as usize
+    }
+
+    pub fn downgrade(&self) -> WeakRef {
+        WeakRef {
+            inner: Rc::downgrade(&self.inner),
+            addr: self.addr(),
+        }
+    }
+
+    /// The value as a `Shared<T>`, if that's what it is.
+    pub fn downcast<T: Any>(&self) -> Option<Shared<T>> {
+        self.inner.clone().downcast::<RefCell<T>>().ok().map(|inner| Shared { inner: inner })
+    }
+}
+
+/// A `SharedRef` that doesn't keep its value alive. It compares by the same
+/// address, which isn't reused while this exists, so it keeps its place in a
+/// table after the value is freed.
+#[derive(Clone)]
+pub struct WeakRef {
+    inner: Weak<Any>,
+    addr: usize,
+}
+
+impl WeakRef {
+    pub fn upgrade(&self) -> Option<SharedRef> {
+        self.inner.upgrade().map(|inner| SharedRef { inner: inner })
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+}
+
+impl PartialEq for WeakRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr
+    }
+}
+
+impl Eq for WeakRef {}
+
+impl PartialOrd for WeakRef {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for WeakRef {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.addr.cmp(&other.addr)
+    }
+}
+
+impl Hash for WeakRef {
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        self.addr.hash(state)
+    }
+}
+
+impl Debug for WeakRef {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "WeakRef({:#x})", self.addr)
+    }
 }
 
 impl<T> From<Shared<T>> for SharedRef
@@ -1,33 +1,62 @@
+//! Symbols, which are interned names.
+//!
+//! There's one symbol table for the whole process, so a symbol stands for
+//! the same name wherever it was made and can always be printed as it.
+//! Interned names are never freed.
+
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+#[derive(Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Symbol(usize);
 
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Symbol(pub usize);
+impl Symbol {
+    /// The symbol for `name`, interning it if it's new.
+    pub fn intern(name: &str) -> Symbol {
+        SYMBOLS.lock().unwrap().intern(name)
+    }
 
-pub struct SymbolTable {
-    strings: Vec<String>,
-    lookup: HashMap<String, Symbol>,
+    /// The name the symbol was interned from.
+    pub fn as_str(self) -> &'static str {
+        SYMBOLS.lock().unwrap().names[self.0]
+    }
 }
 
-impl SymbolTable {
-    pub fn new() -> Self {
-        SymbolTable {
-            strings: vec![],
-            lookup: HashMap::new(),
-        }
+impl fmt::Debug for Symbol {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, ":{}", self.as_str())
     }
+}
 
-    pub fn intern(&mut self, key: &str) -> Symbol {
-        if let Some(&sym) = self.lookup.get(key) {
+impl fmt::Display for Symbol {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+struct SymbolTable {
+    names: Vec<&'static str>,
+    lookup: HashMap<&'static str, Symbol>,
+}
+
+lazy_static! {
+    static ref SYMBOLS: Mutex<SymbolTable> = Mutex::new(SymbolTable {
+        names: vec![],
+        lookup: HashMap::new(),
+    });
+}
+
+impl SymbolTable {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&sym) = self.lookup.get(name) {
             return sym;
         }
 
-        let sym = Symbol(self.strings.len());
-        self.strings.push(key.to_string());
-        self.lookup.insert(key.to_string(), sym);
+        let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+        let sym = Symbol(self.names.len());
+        self.names.push(name);
+        self.lookup.insert(name, sym);
         sym
     }
-
-    pub fn get(&self, sym: Symbol) -> &str {
-        &self.strings[sym.0]
-    }
 }
@@ -0,0 +1,337 @@
+//! Tables, which map keys to values and can hold either weakly.
+//!
+//! Like Lua's `__mode`, a table with weak keys doesn't keep the objects,
+//! tables and arrays used as its keys alive, and one with weak values doesn't
+//! keep its objects, tables and arrays alive. Other keys and values are held
+//! as usual. An entry whose key or value has been freed reads as missing,
+//! and is removed when the cycle collector next runs.
+//!
+//! The values of a table with weak keys are strong, so a value that refers
+//! back to its own key keeps both alive.
+
+use std::collections::HashMap;
+use vm::gc::{Trace, Tracer};
+use vm::value::{Value, ValueKey, WeakValue};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TableMode {
+    Strong,
+    WeakKeys,
+    WeakValues,
+    Weak,
+}
+
+impl TableMode {
+    /// Lua's `__mode` strings: `""`, `"k"`, `"v"` and `"kv"`.
+    pub fn from_str(mode: &str) -> Option<TableMode> {
+        Some(match mode {
+            "" => TableMode::Strong,
+            "k" => TableMode::WeakKeys,
+            "v" => TableMode::WeakValues,
+            "kv" | "vk" => TableMode::Weak,
+            _ => return None,
+        })
+    }
+
+    pub fn weak_keys(self) -> bool {
+        self == TableMode::WeakKeys || self == TableMode::Weak
+    }
+
+    pub fn weak_values(self) -> bool {
+        self == TableMode::WeakValues || self == TableMode::Weak
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Slot {
+    Strong(Value),
+    Weak(WeakValue),
+}
+
+impl Slot {
+    /// The value, or nil if it's been freed.
+    fn get(&self) -> Value {
+        match *self {
+            Slot::Strong(ref value) => value.clone(),
+            Slot::Weak(ref weak) => weak.upgrade(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Table {
+    mode: TableMode,
+    entries: HashMap<ValueKey, Slot>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Table::with_mode(TableMode::Strong)
+    }
+
+    pub fn with_mode(mode: TableMode) -> Self {
+        Table {
+            mode: mode,
+            entries: HashMap::new(),
+        }
+    }
+
+    pub fn mode(&self) -> TableMode {
+        self.mode
+    }
+
+    /// Changes the mode, re-storing the entries that are still alive.
+    pub fn set_mode(&mut self, mode: TableMode) {
+        let entries = self.entries();
+        self.mode = mode;
+        self.entries.clear();
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+
+    /// The key as it's stored, which is weak if keys are.
+    fn stored_key(&self, key: ValueKey) -> ValueKey {
+        match key {
+            ValueKey::Shared(ref shared) if self.mode.weak_keys() => {
+                ValueKey::Weak(shared.downgrade())
+            }
+            key => key,
+        }
+    }
+
+    /// The key as it was given, or `None` if it's been freed.
+    fn live_key(key: &ValueKey) -> Option<ValueKey> {
+        match *key {
+            ValueKey::Weak(ref weak) => weak.upgrade().map(ValueKey::Shared),
+            ref key => Some(key.clone()),
+        }
+    }
+
+    /// The value for `key`, or nil if there isn't one.
+    pub fn get(&self, key: &ValueKey) -> Value {
+        let key = self.stored_key(key.clone());
+        self.entries.get(&key).map(Slot::get).unwrap_or(Value::Nil)
+    }
+
+    /// Sets the value for `key`. Setting it to nil removes it.
+    pub fn insert(&mut self, key: ValueKey, value: Value) {
+        let key = self.stored_key(key);
+        if let Value::Nil = value {
+            self.entries.remove(&key);
+            return;
+        }
+
+        let slot = match WeakValue::new(&value) {
+            Some(weak) if self.mode.weak_values() => Slot::Weak(weak),
+            _ => Slot::Strong(value),
+        };
+        self.entries.insert(key, slot);
+    }
+
+    pub fn remove(&mut self, key: &ValueKey) -> Value {
+        let key = self.stored_key(key.clone());
+        self.entries.remove(&key).map(|slot| slot.get()).unwrap_or(Value::Nil)
+    }
+
+    /// The entries whose key and value are both alive.
+    pub fn entries(&self) -> Vec<(ValueKey, Value)> {
+        self.entries
+            .iter()
+            .filter_map(|(key, slot)| match (Table::live_key(key), slot.get()) {
+                (Some(_), Value::Nil) | (None, _) => None,
+                (Some(key), value) => Some((key, value)),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Removes the entries whose key or value has been freed, returning how
+    /// many there were.
+    pub fn sweep(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|key, slot| match (Table::live_key(key), slot.get()) {
+            (Some(_), Value::Nil) | (None, _) => false,
+            _ => true,
+        });
+        before - self.entries.len()
+    }
+}
+
+/// Only what's held strongly is traced. Weak keys and values are never
+/// edges, which is what lets the collector free them.
+impl Trace for Table {
+    fn trace(&self, tracer: &mut Tracer) {
+        for (key, slot) in &self.entries {
+            tracer.key(key);
+            if let Slot::Strong(ref value) = *slot {
+                tracer.value(value);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn sweep(&mut self) {
+        Table::sweep(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use vm::gc;
+    use vm::value::shared::{Shared, SharedRef};
+
+    fn array() -> Value {
+        Value::Array(Shared::new(vec![]))
+    }
+
+    fn key(value: &Value) -> ValueKey {
+        ValueKey::create(value).unwrap()
+    }
+
+    fn hash(key: &ValueKey) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn modes_are_parsed_like_lua() {
+        assert_eq!(TableMode::from_str(""), Some(TableMode::Strong));
+        assert_eq!(TableMode::from_str("k"), Some(TableMode::WeakKeys));
+        assert_eq!(TableMode::from_str("v"), Some(TableMode::WeakValues));
+        assert_eq!(TableMode::from_str("kv"), Some(TableMode::Weak));
+        assert_eq!(TableMode::from_str("vk"), Some(TableMode::Weak));
+        assert_eq!(TableMode::from_str("kk"), None);
+        assert_eq!(TableMode::from_str("K"), None);
+
+        let modes = [TableMode::Strong,
+                     TableMode::WeakKeys,
+                     TableMode::WeakValues,
+                     TableMode::Weak];
+        let weak: Vec<_> = modes.iter()
+            .map(|mode| (mode.weak_keys(), mode.weak_values()))
+            .collect();
+        assert_eq!(weak, vec![(false, false), (true, false), (false, true), (true, true)]);
+    }
+
+    #[test]
+    fn weak_keys_disappear() {
+        let mut table = Table::with_mode(TableMode::WeakKeys);
+        let target = array();
+        table.insert(key(&target), Value::Integer(1));
+        table.insert(ValueKey::Integer(2), array());
+        assert_eq!(format!("{:?}", table.get(&key(&target))), "1");
+        assert_eq!(table.len(), 2);
+
+        drop(target);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.sweep(), 1);
+        assert_eq!(table.sweep(), 0);
+        // The value of a non-shared key is held strongly
+        assert_eq!(format!("{:?}", table.get(&ValueKey::Integer(2))), "[]");
+    }
+
+    #[test]
+    fn weak_values_disappear() {
+        let mut table = Table::with_mode(TableMode::WeakValues);
+        let target = array();
+        let held = array();
+        table.insert(ValueKey::Integer(1), target.clone());
+        table.insert(key(&held), Value::Integer(2));
+        table.insert(ValueKey::Integer(3), Value::Integer(3));
+
+        drop(target);
+        assert_eq!(format!("{:?}", table.get(&ValueKey::Integer(1))), "nil");
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.sweep(), 1);
+        assert_eq!(format!("{:?}", table.get(&key(&held))), "2");
+    }
+
+    #[test]
+    fn weak_tables_lose_either() {
+        let mut table = Table::with_mode(TableMode::Weak);
+        let (first, second, third) = (array(), array(), array());
+        table.insert(key(&first), second.clone());
+        table.insert(key(&third), Value::Bool(true));
+
+        drop(second);
+        drop(third);
+        assert_eq!(table.len(), 0);
+        assert_eq!(table.sweep(), 2);
+        drop(first);
+    }
+
+    #[test]
+    fn strong_tables_keep_them() {
+        let mut table = Table::new();
+        let target = array();
+        let weak = match target {
+            Value::Array(ref array) => array.downgrade(),
+            _ => unreachable!(),
+        };
+        table.insert(key(&target), target.clone());
+        drop(target);
+
+        assert_eq!(table.sweep(), 0);
+        assert!(weak.upgrade().is_some());
+        table.set_mode(TableMode::Weak);
+        assert!(weak.upgrade().is_none());
+        assert!(table.is_empty());
+    }
+
+    /// Entries are swept when the collector frees their targets.
+    #[test]
+    fn collecting_sweeps_tables() {
+        let table = Shared::new(Table::with_mode(TableMode::WeakValues));
+        let cycle = Shared::new(vec![]);
+        cycle.borrow_mut().push(Value::Array(cycle.clone()));
+        table.borrow_mut().insert(ValueKey::Integer(1), Value::Array(cycle.clone()));
+        drop(cycle);
+
+        assert_eq!(table.borrow().len(), 1);
+        assert_eq!(gc::collect(), 1);
+        assert_eq!(table.borrow().entries.len(), 0);
+    }
+
+    #[test]
+    fn weak_keys_compare_and_hash_by_address() {
+        let (first, second) = (array(), array());
+        let weak = |value: &Value| match key(value) {
+            ValueKey::Shared(shared) => ValueKey::Weak(shared.downgrade()),
+            _ => unreachable!(),
+        };
+        let strong: SharedRef = match first {
+            Value::Array(ref array) => array.clone().into(),
+            _ => unreachable!(),
+        };
+
+        let (a, b, other) = (weak(&first), weak(&first), weak(&second));
+        assert!(a == b && hash(&a) == hash(&b));
+        assert!(a != other);
+        assert!(a != ValueKey::Shared(strong.clone()));
+        // Equal arrays are still different keys
+        assert!(key(&first) != key(&second));
+
+        drop((first, strong));
+        assert_eq!(format!("{:?}", a.value()), "nil");
+        assert!(a == b && hash(&a) == hash(&b));
+    }
+}